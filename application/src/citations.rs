@@ -0,0 +1,198 @@
+use domain::models::SearchHit;
+
+/// Appended to every RAG prompt so answers point back at their sources.
+pub const CITATION_INSTRUCTION: &str = "Cite the code you rely on right after each claim as [path:line] or [path:start-end], copying paths and lines from the SOURCE labels in the context. Do not cite anything that is not labelled there.";
+
+/// A RAG answer whose citations have been checked against the retrieved chunks.
+#[derive(Debug, Clone, Default)]
+pub struct RagAnswer {
+    /// The answer with citations to unretrieved code removed.
+    pub text: String,
+    /// Retrieved chunks the answer cites, in order of first citation.
+    pub sources: Vec<SearchHit>,
+    /// Citations that matched no retrieved chunk.
+    pub dropped: Vec<String>,
+}
+
+impl RagAnswer {
+    /// An answer that was produced without retrieval, so it cites nothing.
+    pub fn uncited(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Whether `path` looks like a relative file path: path characters only,
+/// at least one letter, and a directory or an extension.
+fn is_path_like(path: &str) -> bool {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    let has_extension = file
+        .rsplit_once('.')
+        .is_some_and(|(_, ext)| ext.starts_with(|c: char| c.is_ascii_alphabetic()));
+    path.chars()
+        .all(|c| c.is_alphanumeric() || "._-/~@+".contains(c))
+        && path.chars().any(char::is_alphabetic)
+        && !path.contains("//")
+        && (path.contains('/') || has_extension)
+}
+
+/// A line number: digits only, starting at 1.
+fn parse_line(text: &str) -> Option<usize> {
+    let text = text.trim();
+    if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok().filter(|line| *line > 0)
+}
+
+/// `path:line` or `path:start-end`, as written inside brackets.
+fn parse_reference(item: &str) -> Option<(&str, usize, usize)> {
+    let (path, lines) = item.trim().rsplit_once(':')?;
+    let path = path.trim().trim_start_matches("./");
+    // Keeps `[note: 3]`, slices like `[1.5:3]` and URLs like
+    // `[http://host.x:8080]` from passing as citations.
+    if !is_path_like(path) {
+        return None;
+    }
+    let (start, end) = match lines.split_once('-') {
+        Some((start, end)) => (parse_line(start)?, parse_line(end)?),
+        None => {
+            let line = parse_line(lines)?;
+            (line, line)
+        }
+    };
+    (start <= end).then_some((path, start, end))
+}
+
+/// The retrieved chunk a reference points into, if any.
+fn resolve(hits: &[SearchHit], path: &str, start: usize, end: usize) -> Option<usize> {
+    hits.iter().position(|hit| {
+        hit.is_citable()
+            && hit.path.trim_start_matches("./") == path
+            && start <= hit.end_line
+            && end >= hit.start_line
+    })
+}
+
+/// Check every bracketed citation in `answer` against `hits`. References to
+/// chunks that were not retrieved are removed from the text and reported.
+/// Bracketed text that is not a citation is left alone.
+pub fn resolve_citations(answer: &str, hits: &[SearchHit]) -> RagAnswer {
+    let mut text = String::with_capacity(answer.len());
+    let mut cited: Vec<usize> = Vec::new();
+    let mut dropped = Vec::new();
+    let mut rest = answer;
+
+    while let Some(open) = rest.find('[') {
+        let Some(close) = rest[open..].find(']').map(|i| open + i) else {
+            break;
+        };
+        let inner = &rest[open + 1..close];
+        let items: Vec<&str> = inner.split([',', ';']).collect();
+        let references: Vec<_> = items.iter().map(|item| parse_reference(item)).collect();
+        if references.iter().any(Option::is_none) {
+            // Not (entirely) a citation: keep it as written.
+            text.push_str(&rest[..=close]);
+            rest = &rest[close + 1..];
+            continue;
+        }
+
+        let mut kept = Vec::new();
+        for (item, (path, start, end)) in items.iter().zip(references.into_iter().flatten()) {
+            match resolve(hits, path, start, end) {
+                Some(index) => {
+                    if !cited.contains(&index) {
+                        cited.push(index);
+                    }
+                    kept.push(item.trim());
+                }
+                None => dropped.push(item.trim().to_string()),
+            }
+        }
+        if kept.is_empty() {
+            // Drop the whole bracket along with the space before it.
+            text.push_str(rest[..open].trim_end_matches(' '));
+        } else {
+            text.push_str(&rest[..open]);
+            text.push('[');
+            text.push_str(&kept.join(", "));
+            text.push(']');
+        }
+        rest = &rest[close + 1..];
+    }
+    text.push_str(rest);
+
+    RagAnswer {
+        text,
+        sources: cited.into_iter().map(|i| hits[i].clone()).collect(),
+        dropped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(path: &str, start_line: usize, end_line: usize) -> SearchHit {
+        SearchHit {
+            path: path.to_string(),
+            start_line,
+            end_line,
+            symbol: None,
+            text: String::new(),
+            score: 0.5,
+        }
+    }
+
+    #[test]
+    fn test_valid_citations_are_kept() {
+        let hits = [hit("src/config.rs", 10, 40), hit("./README.md", 1, 20)];
+        let answer = resolve_citations(
+            "Loaded in one place [src/config.rs:12-15]. See the docs [README.md:3, src/config.rs:40].",
+            &hits,
+        );
+        assert_eq!(
+            answer.text,
+            "Loaded in one place [src/config.rs:12-15]. See the docs [README.md:3, src/config.rs:40]."
+        );
+        assert_eq!(answer.sources.len(), 2);
+        assert_eq!(answer.sources[0].path, "src/config.rs");
+        assert!(answer.dropped.is_empty());
+    }
+
+    #[test]
+    fn test_out_of_range_citations_are_dropped() {
+        let hits = [hit("src/config.rs", 10, 40)];
+        let answer = resolve_citations(
+            "Loaded here [src/config.rs:90]. Also [src/config.rs:41-50, src/config.rs:20] and [src/other.rs:1].",
+            &hits,
+        );
+        assert_eq!(answer.text, "Loaded here. Also [src/config.rs:20] and.");
+        assert_eq!(
+            answer.dropped,
+            vec!["src/config.rs:90", "src/config.rs:41-50", "src/other.rs:1"]
+        );
+        assert_eq!(answer.sources.len(), 1);
+    }
+
+    #[test]
+    fn test_non_citation_brackets_are_left_alone() {
+        let hits = [hit("src/config.rs", 10, 40)];
+        for text in [
+            "Start it at [http://host.x:8080] first.",
+            "Take the slice [1.5:3] of it.",
+            "A [note: 3] here.",
+            "Index with [1:3].",
+            "Versions [v1.2:beta] differ.",
+            "Zero [src/config.rs:0] is no line.",
+            "Backwards [src/config.rs:20-10] too.",
+            "Options [a, b] stay.",
+        ] {
+            let answer = resolve_citations(text, &hits);
+            assert_eq!(answer.text, text);
+            assert!(answer.dropped.is_empty(), "{}", text);
+        }
+    }
+}
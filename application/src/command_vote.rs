@@ -0,0 +1,97 @@
+use shared::shell::normalize;
+use std::collections::HashMap;
+
+/// Outcome of sampling the same command request several times.
+pub struct VoteResult {
+    /// The winning command, as first written by the model.
+    pub command: String,
+    /// Number of samples that normalized to the winning command.
+    pub votes: usize,
+    /// Total number of samples requested.
+    pub samples: usize,
+    /// Other distinct candidates with their vote counts, most popular first.
+    pub alternatives: Vec<(String, usize)>,
+}
+
+impl VoteResult {
+    /// Share of samples agreeing with the winner (0.0 to 1.0).
+    pub fn confidence(&self) -> f64 {
+        if self.samples == 0 {
+            return 0.0;
+        }
+        self.votes as f64 / self.samples as f64
+    }
+}
+
+/// Pick the majority command. Candidates are compared by their normalized
+/// shell form; ties go to the candidate seen first. Candidates that do not
+/// parse as shell still count towards `samples` but can't win.
+pub fn tally_votes(candidates: &[String]) -> Option<VoteResult> {
+    let mut order: Vec<String> = Vec::new();
+    let mut groups: HashMap<String, (String, usize)> = HashMap::new();
+    for candidate in candidates {
+        let Some(key) = normalize(candidate) else {
+            continue;
+        };
+        let entry = groups.entry(key.clone()).or_insert_with(|| {
+            order.push(key);
+            (candidate.clone(), 0)
+        });
+        entry.1 += 1;
+    }
+
+    let mut ranked: Vec<(String, usize)> = order
+        .iter()
+        .filter_map(|key| groups.remove(key))
+        .collect();
+    // Stable sort keeps first-seen order among equal counts.
+    ranked.sort_by_key(|(_, votes)| std::cmp::Reverse(*votes));
+    let mut ranked = ranked.into_iter();
+    let (command, votes) = ranked.next()?;
+    Some(VoteResult {
+        command,
+        votes,
+        samples: candidates.len(),
+        alternatives: ranked.collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tally_votes;
+
+    fn samples(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_equivalent_spellings_share_a_vote() {
+        let vote = tally_votes(&samples(&["ls  -la", "ls -la", "ls -l"])).unwrap();
+        assert_eq!(vote.command, "ls  -la");
+        assert_eq!(vote.votes, 2);
+        assert_eq!(vote.alternatives, vec![("ls -l".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_different_expansions_do_not_share_a_vote() {
+        let vote = tally_votes(&samples(&["echo '$HOME'", "echo $HOME", "echo $HOME"])).unwrap();
+        assert_eq!(vote.command, "echo $HOME");
+        assert_eq!(vote.votes, 2);
+    }
+
+    #[test]
+    fn test_ties_go_to_the_first_seen() {
+        let vote = tally_votes(&samples(&["pwd", "ls"])).unwrap();
+        assert_eq!(vote.command, "pwd");
+    }
+
+    #[test]
+    fn test_unparseable_samples_count_but_cannot_win() {
+        let vote = tally_votes(&samples(&["echo 'a", "echo 'a", "ls"])).unwrap();
+        assert_eq!(vote.command, "ls");
+        assert_eq!(vote.samples, 3);
+        assert!((vote.confidence() - 1.0 / 3.0).abs() < 1e-9);
+        assert!(tally_votes(&samples(&["echo 'a"])).is_none());
+        assert!(tally_votes(&[]).is_none());
+    }
+}
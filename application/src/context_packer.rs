@@ -0,0 +1,135 @@
+/// Rough token count of `text`. Code and English average close to four
+/// characters per token with common tokenizers; counting 3.5 errs on the
+/// high side so a packed prompt still fits.
+pub fn estimate_tokens(text: &str) -> usize {
+    (text.chars().count() * 2).div_ceil(7)
+}
+
+/// `text` cut after the last whole line that fits in `max_tokens`, and the
+/// number of lines kept. A cut text ends with a note saying so, even when
+/// the budget is too small for the note itself.
+pub fn truncate_to_tokens(text: &str, max_tokens: usize) -> (String, usize) {
+    if estimate_tokens(text) <= max_tokens {
+        return (text.to_string(), text.lines().count());
+    }
+    const NOTE: &str = "... (truncated)";
+    let mut budget = max_tokens.saturating_sub(estimate_tokens(NOTE) + 1);
+    let mut kept = String::new();
+    let mut lines = 0;
+    for line in text.lines() {
+        let cost = estimate_tokens(line) + 1;
+        if cost > budget {
+            break;
+        }
+        budget -= cost;
+        kept.push_str(line);
+        kept.push('\n');
+        lines += 1;
+    }
+    kept.push_str(NOTE);
+    (kept, lines)
+}
+
+/// The items that fit a token budget and what was left out.
+#[derive(Debug)]
+pub struct Packed<T> {
+    /// Items that fit, in their original order.
+    pub kept: Vec<T>,
+    pub used_tokens: usize,
+    pub dropped: usize,
+    pub dropped_tokens: usize,
+}
+
+/// Keep items, best first, while they fit in `budget` tokens. An item too
+/// large for the room left is skipped, so smaller ones after it can still fit.
+pub fn pack<T>(items: Vec<T>, budget: usize, tokens: impl Fn(&T) -> usize) -> Packed<T> {
+    let mut packed = Packed {
+        kept: Vec::new(),
+        used_tokens: 0,
+        dropped: 0,
+        dropped_tokens: 0,
+    };
+    for item in items {
+        let cost = tokens(&item);
+        if packed.used_tokens + cost <= budget {
+            packed.used_tokens += cost;
+            packed.kept.push(item);
+        } else {
+            packed.dropped += 1;
+            packed.dropped_tokens += cost;
+        }
+    }
+    packed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_rounds_up() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("a"), 1);
+        assert_eq!(estimate_tokens("abcdefg"), 2);
+        // Characters, not bytes, are counted.
+        assert_eq!(estimate_tokens("ééééééé"), 2);
+    }
+
+    #[test]
+    fn test_text_that_fits_is_kept_whole() {
+        let text = "fn main() {\n    run();\n}";
+        assert_eq!(truncate_to_tokens(text, 100), (text.to_string(), 3));
+        assert_eq!(truncate_to_tokens("", 0), (String::new(), 0));
+    }
+
+    #[test]
+    fn test_truncation_keeps_whole_lines() {
+        let text = "line one\nline two\nline three\nline four\n";
+        let (cut, lines) = truncate_to_tokens(text, 10);
+        assert!(estimate_tokens(text) > 10);
+        assert!(estimate_tokens(&cut) <= 10);
+        assert_eq!(lines, 1);
+        assert_eq!(cut, "line one\n... (truncated)");
+    }
+
+    #[test]
+    fn test_zero_budget_keeps_only_the_note() {
+        assert_eq!(truncate_to_tokens("some text", 0), ("... (truncated)".to_string(), 0));
+        let packed = pack(vec!["a", "b"], 0, |_| 1);
+        assert!(packed.kept.is_empty());
+        assert_eq!((packed.dropped, packed.dropped_tokens), (2, 2));
+        // Empty items still fit.
+        assert_eq!(pack(vec![""], 0, |_| 0).kept, vec![""]);
+    }
+
+    #[test]
+    fn test_a_first_line_larger_than_the_budget() {
+        let text = format!("{}\nshort", "x".repeat(400));
+        assert_eq!(truncate_to_tokens(&text, 20), ("... (truncated)".to_string(), 0));
+    }
+
+    #[test]
+    fn test_multibyte_text_at_the_boundary() {
+        // Three-byte characters: a byte-based cut would split one.
+        let text = format!("{}\n{}\n", "日本語".repeat(10), "中文".repeat(30));
+        let (cut, lines) = truncate_to_tokens(&text, 20);
+        assert_eq!(lines, 1);
+        assert!(cut.starts_with(&"日本語".repeat(10)));
+        assert!(cut.ends_with("... (truncated)"));
+        let emoji = "🦀".repeat(50);
+        let (cut, lines) = truncate_to_tokens(&emoji, 10);
+        assert_eq!((cut.as_str(), lines), ("... (truncated)", 0));
+    }
+
+    #[test]
+    fn test_pack_skips_items_too_large_for_the_room_left() {
+        let packed = pack(vec![5, 8, 3, 2], 10, |n| *n);
+        assert_eq!(packed.kept, vec![5, 3, 2]);
+        assert_eq!(packed.used_tokens, 10);
+        assert_eq!((packed.dropped, packed.dropped_tokens), (1, 8));
+        // One item larger than the whole budget.
+        let packed = pack(vec![50], 10, |n| *n);
+        assert!(packed.kept.is_empty());
+        assert_eq!(packed.dropped_tokens, 50);
+    }
+}
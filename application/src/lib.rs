@@ -1,4 +1,12 @@
 pub mod agent_service;
+pub mod citations;
+pub mod command_vote;
+pub mod context_packer;
 pub mod explain_service;
+pub mod query_rewriter;
+pub mod rag_prompts;
 pub mod rag_service;
+pub mod reranker;
 pub mod safety_service;
+pub mod script_export;
+pub mod usage_stats;
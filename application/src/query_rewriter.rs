@@ -0,0 +1,122 @@
+use domain::language_model::{GenerationOptions, LanguageModel};
+use domain::session::Message;
+use serde_json::json;
+use shared::types::Result;
+
+/// Most search queries taken from one rewrite.
+const MAX_QUERIES: usize = 3;
+
+/// Searches the model derived from a question, phrased closer to the code
+/// that answers it.
+#[derive(Debug, Clone, Default)]
+pub struct RewrittenQuery {
+    /// Short search queries naming likely identifiers.
+    pub queries: Vec<String>,
+    /// A made-up snippet of what the answering code might look like (HyDE);
+    /// its embedding lands near real code where a vague question would not.
+    pub hypothetical: Option<String>,
+}
+
+/// Ask the model to turn `question` into search queries and a hypothetical
+/// code snippet.
+pub async fn rewrite_query<L: LanguageModel>(
+    llm: &L,
+    options: &GenerationOptions,
+    question: &str,
+) -> Result<RewrittenQuery> {
+    let prompt = format!(
+        "Rewrite this question about a codebase into up to {} short search queries for finding the code that answers it. Name the identifiers, functions, types and constants such code would likely use. Then write a short hypothetical code snippet that could answer it.\n\nQuestion: {}",
+        MAX_QUERIES, question
+    );
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "queries": {
+                "type": "array",
+                "items": { "type": "string" },
+                "maxItems": MAX_QUERIES
+            },
+            "hypothetical_code": { "type": "string" }
+        },
+        "required": ["queries", "hypothetical_code"]
+    });
+    let reply = llm
+        .structured(&[Message::user(&prompt)], &schema, options)
+        .await?;
+
+    // Repeats of the question or of each other would only search twice.
+    let mut seen = vec![question.trim().to_lowercase()];
+    let queries = reply["queries"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|q| q.as_str())
+        .map(|q| q.trim().to_string())
+        .filter(|q| {
+            let key = q.to_lowercase();
+            if q.is_empty() || seen.contains(&key) {
+                return false;
+            }
+            seen.push(key);
+            true
+        })
+        .take(MAX_QUERIES)
+        .collect();
+    let hypothetical = reply["hypothetical_code"]
+        .as_str()
+        .map(|code| code.trim().to_string())
+        .filter(|code| !code.is_empty());
+    Ok(RewrittenQuery {
+        queries,
+        hypothetical,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain::mock::MockModel;
+
+    async fn rewrite(reply: &str, question: &str) -> Result<RewrittenQuery> {
+        rewrite_query(&MockModel::new(reply), &GenerationOptions::default(), question).await
+    }
+
+    #[tokio::test]
+    async fn test_queries_and_snippet_are_parsed() {
+        let reply = r#"{"queries":[" load_config ", "parse_toml", "Config::from_env"], "hypothetical_code":"fn load_config() -> Config {}\n"}"#;
+        let rewritten = rewrite(reply, "where is the config read?").await.unwrap();
+        assert_eq!(rewritten.queries, vec!["load_config", "parse_toml", "Config::from_env"]);
+        assert_eq!(rewritten.hypothetical.as_deref(), Some("fn load_config() -> Config {}"));
+    }
+
+    #[tokio::test]
+    async fn test_question_and_empty_queries_are_dropped() {
+        let reply = r#"{"queries":["where is the config read?", "", "  ", "load_config", 7, "a", "b", "c"], "hypothetical_code":"  "}"#;
+        let rewritten = rewrite(reply, " where is the config read? ").await.unwrap();
+        // At most three queries are kept.
+        assert_eq!(rewritten.queries, vec!["load_config", "a", "b"]);
+        assert!(rewritten.hypothetical.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_repeated_queries_are_dropped() {
+        let reply = r#"{"queries":["load_config", "Where is the config read?", "LOAD_CONFIG", "load_config", "parse_toml"], "hypothetical_code":""}"#;
+        let rewritten = rewrite(reply, "where is the config read?").await.unwrap();
+        assert_eq!(rewritten.queries, vec!["load_config", "parse_toml"]);
+    }
+
+    #[tokio::test]
+    async fn test_replies_missing_fields_give_no_rewrites() {
+        for reply in [r#"{}"#, r#"{"queries":"load_config","hypothetical_code":3}"#, "[]"] {
+            let rewritten = rewrite(reply, "question").await.unwrap();
+            assert!(rewritten.queries.is_empty(), "{}", reply);
+            assert!(rewritten.hypothetical.is_none(), "{}", reply);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_json_is_an_error() {
+        // The caller then searches for the question alone.
+        assert!(rewrite("Sure! Here are some queries:", "question").await.is_err());
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// What a RAG question is asking for; each kind gets its own prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuestionKind {
+    /// Where something lives in the code.
+    Locate,
+    /// How a mechanism works.
+    Explain,
+    /// How to build, run, install or configure the project.
+    HowToRun,
+    /// Differences between two or more things.
+    Compare,
+    /// Why something fails and how to fix it.
+    Debug,
+    /// What the project is, as a whole.
+    Summarize,
+}
+
+impl QuestionKind {
+    pub const ALL: [QuestionKind; 6] = [
+        QuestionKind::Locate,
+        QuestionKind::Explain,
+        QuestionKind::HowToRun,
+        QuestionKind::Compare,
+        QuestionKind::Debug,
+        QuestionKind::Summarize,
+    ];
+
+    /// File name stem of the override template, e.g. `.vibe_cli/prompts/debug.md`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Locate => "locate",
+            Self::Explain => "explain",
+            Self::HowToRun => "run",
+            Self::Compare => "compare",
+            Self::Debug => "debug",
+            Self::Summarize => "summarize",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Locate => "locate code",
+            Self::Explain => "explain mechanism",
+            Self::HowToRun => "how to run or build",
+            Self::Compare => "compare",
+            Self::Debug => "debug",
+            Self::Summarize => "summarize project",
+        }
+    }
+
+    fn default_template(self) -> &'static str {
+        match self {
+            Self::Locate => LOCATE_TEMPLATE,
+            Self::Explain => EXPLAIN_TEMPLATE,
+            Self::HowToRun => RUN_TEMPLATE,
+            Self::Compare => COMPARE_TEMPLATE,
+            Self::Debug => DEBUG_TEMPLATE,
+            Self::Summarize => SUMMARIZE_TEMPLATE,
+        }
+    }
+}
+
+const DEBUG_TERMS: &[&str] = &[
+    "error", "errors", "bug", "bugs", "fail", "fails", "failing", "failed", "failure", "crash",
+    "crashes", "panic", "panics", "broken", "exception", "wrong", "fix", "not working",
+    "doesn't work", "does not work", "stack trace", "hangs",
+];
+const COMPARE_TERMS: &[&str] = &[
+    "compare", "comparison", "difference", "differences", "differ", "vs", "versus",
+    "instead of", "better than", "pros and cons",
+];
+const RUN_VERBS: &[&str] = &[
+    "run", "build", "install", "start", "deploy", "compile", "configure", "setup", "set up",
+    "launch",
+];
+const LOCATE_TERMS: &[&str] = &[
+    "where", "which file", "which files", "which module", "locate", "find the", "defined",
+];
+/// Wordings asking how a mechanism works, even when it is about errors.
+const EXPLAIN_TERMS: &[&str] = &[
+    "how does", "how do the", "how is", "how are", "explain", "walk me through",
+];
+/// Questions about the project as a whole pair one of these with a `PROJECT_NOUNS` entry.
+const SUMMARY_LEADS: &[&str] = &[
+    "summarize", "summarise", "summary of", "overview of", "describe", "what is", "what does",
+];
+const PROJECT_NOUNS: &[&str] = &[
+    "this project", "the project", "this repo", "the repo", "this repository",
+    "the repository", "this codebase", "the codebase",
+];
+
+/// Whether `text` (lowercase words separated by single spaces) contains `term` as whole words.
+fn has_term(text: &str, term: &str) -> bool {
+    format!(" {} ", text).contains(&format!(" {} ", term))
+}
+
+/// Classify a question by its wording. Mechanism questions are the fallback.
+pub fn classify_question(question: &str) -> QuestionKind {
+    let text = question
+        .to_lowercase()
+        .split(|c: char| !(c.is_alphanumeric() || c == '\'' || c == '-'))
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let any = |terms: &[&str]| terms.iter().any(|t| has_term(&text, t));
+    let about_project = SUMMARY_LEADS.iter().any(|lead| {
+        PROJECT_NOUNS
+            .iter()
+            .any(|noun| has_term(&text, &format!("{} {}", lead, noun)))
+    }) || has_term(&text, "high-level overview")
+        || has_term(&text, "high level overview");
+
+    // Debug terms come late: "how does error handling work" asks for an
+    // explanation and "where is the error type defined" for a location.
+    if any(COMPARE_TERMS) || (has_term(&text, "between") && has_term(&text, "and")) {
+        QuestionKind::Compare
+    } else if any(LOCATE_TERMS) {
+        QuestionKind::Locate
+    } else if about_project {
+        QuestionKind::Summarize
+    } else if any(EXPLAIN_TERMS) {
+        QuestionKind::Explain
+    } else if any(DEBUG_TERMS) {
+        QuestionKind::Debug
+    } else if has_term(&text, "how") && any(RUN_VERBS) {
+        QuestionKind::HowToRun
+    } else {
+        QuestionKind::Explain
+    }
+}
+
+/// Prompt templates per question kind. Templates use the placeholders
+/// `{question}`, `{context}` and `{feedback}`.
+pub struct PromptTemplates {
+    overrides: HashMap<QuestionKind, String>,
+}
+
+impl PromptTemplates {
+    /// The built-in templates, replaced by any `.vibe_cli/prompts/<kind>.md`
+    /// found under `project_root`.
+    pub fn load(project_root: impl AsRef<Path>) -> Self {
+        let dir = project_root.as_ref().join(".vibe_cli").join("prompts");
+        let overrides = QuestionKind::ALL
+            .iter()
+            .filter_map(|kind| {
+                let text = std::fs::read_to_string(dir.join(format!("{}.md", kind.name()))).ok()?;
+                (!text.trim().is_empty()).then_some((*kind, text))
+            })
+            .collect();
+        Self { overrides }
+    }
+
+    pub fn is_overridden(&self, kind: QuestionKind) -> bool {
+        self.overrides.contains_key(&kind)
+    }
+
+    /// Fill in the template for `kind`. Context the template does not place
+    /// itself is appended, so an override cannot lose it.
+    pub fn render(&self, kind: QuestionKind, question: &str, context: &str, feedback: &str) -> String {
+        let template = self
+            .overrides
+            .get(&kind)
+            .map(String::as_str)
+            .unwrap_or_else(|| kind.default_template());
+        let feedback = if feedback.is_empty() {
+            String::new()
+        } else {
+            format!("User feedback on the previous answer: {}", feedback)
+        };
+        let mut prompt = fill(
+            template,
+            &[("{question}", question), ("{context}", context), ("{feedback}", &feedback)],
+        );
+        if !template.contains("{context}") {
+            prompt.push_str("\n\nContext:\n");
+            prompt.push_str(context);
+        }
+        prompt
+    }
+}
+
+/// Replace placeholders in one pass, so text substituted for one (a question
+/// quoting `{context}`, say) is never expanded again.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match values.iter().find(|(key, _)| rest.starts_with(key)) {
+            Some((key, value)) => {
+                out.push_str(value);
+                rest = &rest[key.len()..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+const LOCATE_TEMPLATE: &str = "You are an expert software engineer helping someone find their way around a codebase.
+
+Question: {question}
+{feedback}
+
+Context:
+{context}
+
+Answer with the places in the code that matter for the question, most relevant first. For each, give the file, the function or type, and one sentence on its role. Do not explain the implementation in depth. Base your answer only on the provided context; if it does not show where this lives, say so.";
+
+const EXPLAIN_TEMPLATE: &str = "You are an expert software engineer explaining how part of a codebase works.
+
+Question: {question}
+{feedback}
+
+Context:
+{context}
+
+Explain the mechanism step by step: where it starts, which types and functions are involved, how data and control flow between them, and any important edge cases. Quote short snippets from the context where they help. Base your answer only on the provided context; if something is not shown, say so instead of guessing.";
+
+const RUN_TEMPLATE: &str = "You are an expert software engineer helping someone build and run a project.
+
+Question: {question}
+{feedback}
+
+Context:
+{context}
+
+Give the exact steps in order, with commands in a bash code block, followed by the prerequisites, environment variables and configuration files they depend on. Use only commands and settings that appear in the context; if a step is missing from it, say so.";
+
+const COMPARE_TEMPLATE: &str = "You are an expert software engineer comparing parts of a codebase.
+
+Question: {question}
+{feedback}
+
+Context:
+{context}
+
+Compare the things asked about side by side: what each does, how they differ in behaviour and implementation, and when to use which. Use a short table or bullet list, then a one-line recommendation. Base your answer only on the provided context.";
+
+const DEBUG_TEMPLATE: &str = "You are an expert software engineer debugging a problem in a codebase.
+
+Problem: {question}
+{feedback}
+
+Context:
+{context}
+
+List the most likely causes, most likely first. For each, point to the code responsible and explain why it produces the problem, then propose a concrete fix as a code change or command. Base your answer only on the provided context; say what extra information would confirm the cause.";
+
+const SUMMARIZE_TEMPLATE: &str = "You are an expert software engineer. Based on the provided code context and directory structure, {question}
+{feedback}
+
+Context:
+{context}
+
+Provide a concise summary that includes:
+- Project purpose
+- Main features
+- Technologies used
+- Architecture
+- Complete directory structure (copy exactly from the DIRECTORY TREE section in the context)
+
+Be accurate and base your answer only on the provided context. Do not invent or modify the directory structure.";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_question() {
+        let cases = [
+            ("where is the error type defined?", QuestionKind::Locate),
+            ("which file parses the config", QuestionKind::Locate),
+            ("how does error handling work", QuestionKind::Explain),
+            ("how does session management work in this project?", QuestionKind::Explain),
+            ("explain the retry policy", QuestionKind::Explain),
+            ("why does the indexer panic on empty files", QuestionKind::Debug),
+            ("how do I fix the build error", QuestionKind::Debug),
+            ("how do I build the project", QuestionKind::HowToRun),
+            ("how to install it", QuestionKind::HowToRun),
+            ("difference between f16 and int8 vectors", QuestionKind::Compare),
+            ("summarize this project", QuestionKind::Summarize),
+            ("give me an overview of the repo", QuestionKind::Summarize),
+            ("what is this project?", QuestionKind::Summarize),
+            ("what does the cache store", QuestionKind::Explain),
+        ];
+        for (question, kind) in cases {
+            assert_eq!(classify_question(question), kind, "{}", question);
+        }
+    }
+}
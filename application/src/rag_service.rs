@@ -1,31 +1,75 @@
 use infrastructure::{
     config::Config,
     embedder::{Embedder, EmbeddingInput},
-    embedding_storage::EmbeddingStorage,
+    embedding_storage::{EmbeddingStorage, IndexInfo},
     file_scanner::FileScanner,
-    ollama_client::OllamaClient,
-    search::SearchEngine,
+    search::{Candidate, SearchEngine, Selection},
+    vector_codec::normalized,
+    vector_index::VectorIndex,
 };
+use crate::citations::{resolve_citations, RagAnswer, CITATION_INSTRUCTION};
+use crate::context_packer::{estimate_tokens, pack, truncate_to_tokens};
+use crate::query_rewriter::rewrite_query;
+use crate::rag_prompts::{classify_question, PromptTemplates, QuestionKind};
+use crate::reranker::Reranker;
+use domain::language_model::{EmbeddingModel, GenerationOptions, LanguageModel};
+use domain::models::SearchHit;
+use domain::session::Message;
 use md5;
 use shared::types::Result;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
-pub struct RagService {
+const NO_CONTEXT_ANSWER: &str = "No relevant code context found for this query.";
+/// Path under which the indexed directory overview chunk is stored.
+const DIR_OVERVIEW_PATH: &str = "__dir_overview__";
+
+pub struct RagService<L: LanguageModel, E: EmbeddingModel> {
     scanner: FileScanner,
     storage: EmbeddingStorage,
-    embedder: Embedder,
-    client: OllamaClient,
+    embedder: Embedder<E>,
+    llm: L,
+    templates: PromptTemplates,
+    /// Present when `RAG_RERANK` is set.
+    reranker: Option<Reranker>,
     config: Config,
+    /// The storage's vector index and the generation it was taken at, reused
+    /// across turns until indexing changes the stored vectors.
+    vectors: Mutex<Option<(u64, Arc<VectorIndex>)>>,
+    /// The last question and its searches; feedback turns ask it again.
+    last_query: Mutex<Option<(String, QuerySearches)>>,
+}
+
+/// What retrieval looks up for one question.
+#[derive(Clone)]
+struct QuerySearches {
+    /// Texts for keyword search: the question and any rewrites of it.
+    keywords: Vec<String>,
+    /// Embeddings of the question, its rewrites and the hypothetical answer.
+    embeddings: Vec<Vec<f32>>,
 }
 
-impl RagService {
-    pub async fn new(root_path: &str, db_path: &str, client: OllamaClient, config: Config) -> Result<Self> {
+impl<L: LanguageModel, E: EmbeddingModel> RagService<L, E> {
+    pub async fn new(
+        root_path: &str,
+        db_path: &str,
+        llm: L,
+        embeddings: E,
+        config: Config,
+    ) -> Result<Self> {
         Ok(Self {
             scanner: FileScanner::new(root_path),
-            storage: EmbeddingStorage::new(db_path).await?,
-            embedder: Embedder::new(client.clone()),
-            client: client,
+            storage: EmbeddingStorage::new(db_path, config.vector_format).await?,
+            embedder: Embedder::new(embeddings),
+            llm,
+            templates: PromptTemplates::load(root_path),
+            reranker: config
+                .rag_rerank
+                .then(|| Reranker::new(config.rag_rerank_candidates)),
             config,
+            vectors: Mutex::new(None),
+            last_query: Mutex::new(None),
         })
     }
 
@@ -85,38 +129,275 @@ impl RagService {
         self.build_index_with_files(&files).await
     }
 
-    pub async fn query(&self, question: &str) -> Result<String> {
+    /// How `question` will be answered, and whether the project overrides that template.
+    pub fn question_kind(&self, question: &str) -> (QuestionKind, bool) {
+        let kind = classify_question(question);
+        (kind, self.templates.is_overridden(kind))
+    }
+
+    pub async fn query(&self, question: &str) -> Result<RagAnswer> {
         self.query_with_feedback(question, "").await
     }
 
-    pub async fn query_with_feedback(&self, question: &str, feedback: &str) -> Result<String> {
-        let query_embedding = self.client.generate_embedding(question).await?;
-        let all_embeddings = self.storage.get_all_embeddings().await?;
-        let mut relevant_chunks =
-            SearchEngine::find_relevant_chunks(&query_embedding, &all_embeddings, 50);
+    pub async fn query_with_feedback(&self, question: &str, feedback: &str) -> Result<RagAnswer> {
+        match self.answer_prompt(question, feedback).await? {
+            Some((prompt, hits)) => {
+                let answer = self
+                    .llm
+                    .chat(&[Message::user(&prompt)], &self.generation_options())
+                    .await?;
+                Ok(resolve_citations(&answer, &hits))
+            }
+            None => Ok(RagAnswer::uncited(NO_CONTEXT_ANSWER)),
+        }
+    }
 
-        // For project-level questions, include README and directory tree if available
-        if question.to_lowercase().contains("project") || question.to_lowercase().contains("what is") {
-            if let Ok(readme_content) = std::fs::read_to_string("README.md") {
-                relevant_chunks.insert(0, format!("FILE: README.md\n{}", readme_content));
+    /// Like `query_with_feedback`, passing the answer to `on_token` as it is generated.
+    pub async fn stream_query_with_feedback<F>(
+        &self,
+        question: &str,
+        feedback: &str,
+        mut on_token: F,
+    ) -> Result<RagAnswer>
+    where
+        F: FnMut(&str) + Send,
+    {
+        match self.answer_prompt(question, feedback).await? {
+            Some((prompt, hits)) => {
+                let answer = self
+                    .llm
+                    .stream(&[Message::user(&prompt)], &self.generation_options(), on_token)
+                    .await?;
+                Ok(resolve_citations(&answer, &hits))
             }
+            None => {
+                on_token(NO_CONTEXT_ANSWER);
+                Ok(RagAnswer::uncited(NO_CONTEXT_ANSWER))
+            }
+        }
+    }
+
+    /// Answers use the context window the prompt was packed for.
+    fn generation_options(&self) -> GenerationOptions {
+        GenerationOptions::with_num_ctx(self.config.num_ctx)
+    }
+
+    /// The prompt sent to the model and the chunks it may cite, or `None`
+    /// when nothing relevant was retrieved.
+    async fn answer_prompt(
+        &self,
+        question: &str,
+        feedback: &str,
+    ) -> Result<Option<(String, Vec<SearchHit>)>> {
+        let kind = classify_question(question);
+        let hits = self.retrieve(question, kind).await?;
+
+        // Room for context: the window less the reply and the prompt around the context.
+        let frame = estimate_tokens(&self.templates.render(kind, question, "", feedback))
+            + estimate_tokens(CITATION_INSTRUCTION);
+        let mut budget = (self.config.num_ctx as usize)
+            .saturating_sub(self.config.rag_answer_tokens)
+            .saturating_sub(frame);
+        let mut trimmed = false;
+
+        // For project-level questions, include README and directory tree if available,
+        // each within a quarter of the budget.
+        let mut preamble = Vec::new();
+        let mut readme = None;
+        if kind == QuestionKind::Summarize {
+            let share = budget / 4;
             let dir_overview = self.scanner.directory_overview(8, 2000);
             if !dir_overview.is_empty() {
-                relevant_chunks.insert(0, format!("DIRECTORY TREE:\n{}", dir_overview));
+                let (tree, _) = truncate_to_tokens(&dir_overview, share);
+                trimmed |= tree.len() < dir_overview.len();
+                preamble.push(format!("DIRECTORY TREE:\n{}", tree));
+            }
+            if let Ok(readme_content) = std::fs::read_to_string("README.md") {
+                let (text, lines) = truncate_to_tokens(&readme_content, share);
+                trimmed |= text.len() < readme_content.len();
+                // Added without search, so it has no similarity score.
+                let hit = SearchHit {
+                    path: "README.md".to_string(),
+                    start_line: 1,
+                    end_line: lines.max(1),
+                    symbol: None,
+                    text,
+                    score: 0.0,
+                };
+                preamble.push(Self::context_block(&hit));
+                readme = Some(hit);
             }
+            let used: usize = preamble.iter().map(|block| estimate_tokens(block)).sum();
+            budget = budget.saturating_sub(used);
+        }
+
+        // Chunks arrive best first, so the least relevant are dropped first.
+        let retrieved = hits.len();
+        let packed = pack(hits, budget, |hit| estimate_tokens(&Self::context_block(hit)));
+        if trimmed || packed.dropped > 0 {
+            eprintln!(
+                "Context trimmed to fit NUM_CTX {}: kept {} of {} chunks (~{} tokens), dropped {} (~{} tokens)",
+                self.config.num_ctx,
+                packed.kept.len(),
+                retrieved,
+                packed.used_tokens,
+                packed.dropped,
+                packed.dropped_tokens
+            );
         }
+        let mut hits = packed.kept;
+        let mut relevant_chunks = preamble;
+        relevant_chunks.extend(hits.iter().map(Self::context_block));
+        hits.extend(readme);
 
         let context = relevant_chunks.join("\n\n");
         if context.is_empty() {
-            return Ok("No relevant code context found for this query.".to_string());
+            return Ok(None);
         }
-        let feedback_part = if feedback.is_empty() {
-            String::new()
-        } else {
-            format!("\n\nUser feedback for improvement: {}", feedback)
+        let mut prompt = self.templates.render(kind, question, &context, feedback);
+        prompt.push_str("\n\n");
+        prompt.push_str(CITATION_INSTRUCTION);
+        Ok(Some((prompt, hits)))
+    }
+
+    /// The chunks to put in the prompt: twice as many candidates as needed
+    /// from vector and keyword search, fused, then narrowed down to a
+    /// diverse selection.
+    async fn retrieve(&self, question: &str, kind: QuestionKind) -> Result<Vec<SearchHit>> {
+        let selection = Selection::from_config(&self.config);
+        let pool = selection.top_k * 2;
+        let searches = self.query_searches(question).await?;
+        let vectors = self.vectors().await;
+        let mut rankings: Vec<Vec<String>> = searches
+            .embeddings
+            .iter()
+            .map(|embedding| {
+                vectors
+                    .search(embedding, pool)
+                    .into_iter()
+                    .map(|(id, _)| id)
+                    .collect()
+            })
+            .collect();
+        let mut keyword_matches: HashSet<String> = HashSet::new();
+        for text in &searches.keywords {
+            let ranking = self.storage.keyword_search(text, pool).await?;
+            keyword_matches.extend(ranking.iter().cloned());
+            rankings.push(ranking);
+        }
+        let fused = SearchEngine::reciprocal_rank_fusion(&rankings, pool);
+        let best = fused.first().map(|(_, score)| *score).unwrap_or(1.0);
+        let relevance: HashMap<String, f32> =
+            fused.iter().map(|(id, score)| (id.clone(), score / best)).collect();
+        let ids: Vec<String> = fused.into_iter().map(|(id, _)| id).collect();
+
+        // Only the candidates' text is loaded; hits keep their cosine similarity
+        // to the closest of the searches as score.
+        let queries: Vec<Vec<f32>> = searches.embeddings.iter().map(|e| normalized(e)).collect();
+        let chunks = self.storage.get_embeddings(&ids).await?;
+        let candidates: Vec<Candidate> = chunks
+            .iter()
+            // Summaries get the full directory tree, so its indexed excerpt is redundant.
+            .filter(|chunk| !(kind == QuestionKind::Summarize && chunk.path == DIR_OVERVIEW_PATH))
+            .filter_map(|chunk| {
+                let vector = vectors.vector(&chunk.id)?;
+                let similarity = queries
+                    .iter()
+                    .filter_map(|query| vectors.similarity(&chunk.id, query))
+                    .reduce(f32::max);
+                Some(Candidate {
+                    id: &chunk.id,
+                    hit: SearchHit::from_embedding(chunk, similarity?),
+                    relevance: relevance.get(&chunk.id).copied().unwrap_or(0.0),
+                    vector,
+                    keyword_match: keyword_matches.contains(&chunk.id),
+                })
+            })
+            .filter(|candidate| selection.admits(candidate))
+            .collect();
+        let candidates = match &self.reranker {
+            Some(reranker) => {
+                reranker
+                    .rerank(
+                        &self.llm,
+                        &GenerationOptions {
+                            temperature: Some(0.0),
+                            ..self.generation_options()
+                        },
+                        self.storage.generation(),
+                        question,
+                        candidates,
+                    )
+                    .await
+            }
+            None => candidates,
         };
-        let prompt = format!("You are an expert software engineer. Based on the provided code context and directory structure, {}{} \n\nContext:\n{}\n\nProvide a concise summary that includes:\n- Project purpose\n- Main features\n- Technologies used\n- Architecture\n- Complete directory structure (copy exactly from the DIRECTORY TREE section in the context)\n\nBe accurate and base your answer only on the provided context. Do not invent or modify the directory structure.", question, feedback_part, context);
-        self.client.generate_response(&prompt).await
+        Ok(SearchEngine::select(candidates, &selection))
+    }
+
+    /// The stored vectors, taken from storage again only after indexing
+    /// has changed them.
+    async fn vectors(&self) -> Arc<VectorIndex> {
+        let generation = self.storage.generation();
+        if let Ok(cached) = self.vectors.lock() {
+            if let Some((taken_at, index)) = cached.as_ref() {
+                if *taken_at == generation {
+                    return Arc::clone(index);
+                }
+            }
+        }
+        let index = self.storage.snapshot().await;
+        if let Ok(mut cached) = self.vectors.lock() {
+            *cached = Some((generation, Arc::clone(&index)));
+        }
+        index
+    }
+
+    /// The searches for `question`: the question itself and, with
+    /// `RAG_QUERY_REWRITE`, the model's rewrites of it and a hypothetical
+    /// answer, all embedded in one request.
+    async fn query_searches(&self, question: &str) -> Result<QuerySearches> {
+        if let Ok(last) = self.last_query.lock() {
+            if let Some((asked, searches)) = last.as_ref() {
+                if asked == question {
+                    return Ok(searches.clone());
+                }
+            }
+        }
+        let mut keywords = vec![question.to_string()];
+        let mut texts = keywords.clone();
+        if self.config.rag_query_rewrite {
+            match rewrite_query(&self.llm, &self.generation_options(), question).await {
+                Ok(rewritten) => {
+                    if !rewritten.queries.is_empty() {
+                        eprintln!("Also searching for: {}", rewritten.queries.join(" | "));
+                    }
+                    keywords.extend(rewritten.queries.iter().cloned());
+                    texts.extend(rewritten.queries);
+                    texts.extend(rewritten.hypothetical);
+                }
+                Err(e) => eprintln!("Query rewriting failed, searching for the question only: {}", e),
+            }
+        }
+        let searches = QuerySearches {
+            keywords,
+            embeddings: self.embedder.embed_queries(&texts).await?,
+        };
+        if let Ok(mut last) = self.last_query.lock() {
+            *last = Some((question.to_string(), searches.clone()));
+        }
+        Ok(searches)
+    }
+
+    /// A retrieved chunk as shown to the model, labelled for citation.
+    fn context_block(hit: &SearchHit) -> String {
+        if !hit.is_citable() {
+            return hit.text.clone();
+        }
+        match &hit.symbol {
+            Some(symbol) => format!("SOURCE [{}] ({})\n{}", hit.location(), symbol, hit.text),
+            None => format!("SOURCE [{}]\n{}", hit.location(), hit.text),
+        }
     }
 
     fn filter_files_by_patterns(&self, files: &[PathBuf]) -> Vec<PathBuf> {
@@ -190,7 +471,36 @@ impl RagService {
             .collect()
     }
 
+    /// Make sure the stored vectors came from the current embedding model,
+    /// clearing the index when they did not so that it is rebuilt from scratch.
+    async fn ensure_index_matches_model(&self) -> Result<()> {
+        let current = IndexInfo {
+            model: self.embedder.model_name().to_string(),
+            dimension: self.embedder.dimension().await?,
+        };
+        match self.storage.get_index_info().await? {
+            Some(stored) if stored == current => return Ok(()),
+            Some(stored) => {
+                eprintln!(
+                    "Embedding index was built with {} ({} dimensions); rebuilding for {} ({} dimensions)...",
+                    stored.model, stored.dimension, current.model, current.dimension
+                );
+                self.storage.clear_index().await?;
+            }
+            None if self.storage.count_embeddings().await? > 0 => {
+                eprintln!(
+                    "Embedding index has no model information; rebuilding for {}...",
+                    current.model
+                );
+                self.storage.clear_index().await?;
+            }
+            None => {}
+        }
+        self.storage.set_index_info(current).await
+    }
+
     async fn build_index_with_files(&self, files: &[PathBuf]) -> Result<()> {
+        self.ensure_index_matches_model().await?;
         eprintln!("Scanning {} files...", files.len());
         let mut inputs: Vec<EmbeddingInput> = Vec::new();
 
@@ -198,17 +508,20 @@ impl RagService {
         let dir_overview = self.scanner.directory_overview(4, 400);
         if !dir_overview.is_empty() {
             let dir_hash = format!("{:x}", md5::compute(dir_overview.as_bytes()));
-            let meta = self.storage.get_file_hash("__dir_overview__".to_string()).await?;
+            let meta = self.storage.get_file_hash(DIR_OVERVIEW_PATH.to_string()).await?;
             if meta.as_deref() != Some(dir_hash.as_str()) {
                 self.storage
-                    .delete_embeddings_for_path("__dir_overview__".to_string()).await?;
+                    .delete_embeddings_for_path(DIR_OVERVIEW_PATH.to_string()).await?;
                 inputs.push(EmbeddingInput {
-                    id: format!("__dir_overview__:{dir_hash}"),
-                    path: "__dir_overview__".to_string(),
+                    id: format!("{}:{}", DIR_OVERVIEW_PATH, dir_hash),
+                    path: DIR_OVERVIEW_PATH.to_string(),
                     text: format!("DIRECTORY TREE:\n{}", dir_overview),
+                    start_line: 0,
+                    end_line: 0,
+                    symbol: None,
                 });
                 self.storage
-                    .upsert_file_hash("__dir_overview__".to_string(), dir_hash).await?;
+                    .upsert_file_hash(DIR_OVERVIEW_PATH.to_string(), dir_hash).await?;
             }
         }
 
@@ -230,13 +543,16 @@ impl RagService {
             for chunk in scan.chunks {
                 let id = format!("{}:{}", chunk.path, chunk.start_offset);
                 let text = format!(
-                    "FILE: {}\nOFFSET: {}\n{}",
-                    chunk.path, chunk.start_offset, chunk.text
+                    "FILE: {}\nLINES: {}-{}\n{}",
+                    chunk.path, chunk.start_line, chunk.end_line, chunk.text
                 );
                 inputs.push(EmbeddingInput {
                     id,
                     path: chunk.path,
                     text,
+                    start_line: chunk.start_line,
+                    end_line: chunk.end_line,
+                    symbol: chunk.symbol,
                 });
             }
 
@@ -253,3 +569,166 @@ impl RagService {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain::mock::MockModel;
+
+    /// A project directory holding `files`, and a database path beside it.
+    fn temp_project(name: &str, files: &[(&str, &str)]) -> (PathBuf, String) {
+        let dir = std::env::temp_dir().join(format!("rag_service_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("project")).unwrap();
+        for (path, content) in files {
+            std::fs::write(dir.join("project").join(path), content).unwrap();
+        }
+        let db = dir.join("index.db").to_string_lossy().to_string();
+        (dir, db)
+    }
+
+    fn test_config() -> Config {
+        Config {
+            rag_query_rewrite: false,
+            rag_rerank: false,
+            rag_min_similarity: 0.0,
+            ..Config::load()
+        }
+    }
+
+    /// Answers by citing the first SOURCE label in the prompt, and a file
+    /// that was never retrieved.
+    fn citing_model() -> MockModel {
+        MockModel::replying(|_, messages| {
+            let prompt = &messages[0].content;
+            let label = prompt
+                .split("SOURCE [")
+                .nth(1)
+                .and_then(|rest| rest.split(']').next())
+                .unwrap_or("none");
+            Ok(format!("It is read in load_settings [{}], not [missing.rs:9].", label))
+        })
+    }
+
+    async fn service(dir: &std::path::Path, db: &str, model: &MockModel) -> RagService<MockModel, MockModel> {
+        let root = dir.join("project").to_string_lossy().to_string();
+        RagService::new(&root, db, model.clone(), model.clone(), test_config())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_query_answers_from_the_index_with_citations() {
+        let (dir, db) = temp_project(
+            "query",
+            &[(
+                "settings.rs",
+                "pub fn load_settings(path: &str) -> Settings {\n    let text = read(path);\n    parse_settings(&text)\n}\n",
+            )],
+        );
+        let model = citing_model();
+        let rag = service(&dir, &db, &model).await;
+        rag.build_index().await.unwrap();
+
+        let answer = rag.query("where is load_settings defined").await.unwrap();
+        let chats = model.chats();
+        let prompt = &chats.last().unwrap().1[0].content;
+        assert!(prompt.contains("Context:"));
+        assert!(prompt.contains("SOURCE ["));
+        assert!(prompt.contains("pub fn load_settings"));
+        assert!(prompt.contains(CITATION_INSTRUCTION));
+        assert_eq!(answer.sources.len(), 1);
+        assert!(answer.sources[0].path.ends_with("settings.rs"));
+        assert_eq!(answer.dropped, vec!["missing.rs:9".to_string()]);
+        assert!(!answer.text.contains("missing.rs"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_empty_index_answers_without_the_model() {
+        let (dir, db) = temp_project("empty", &[]);
+        let model = citing_model();
+        let rag = service(&dir, &db, &model).await;
+
+        let answer = rag.query("where is load_settings defined").await.unwrap();
+        assert_eq!(answer.text, NO_CONTEXT_ANSWER);
+        assert!(answer.sources.is_empty());
+        assert!(model.chats().is_empty());
+
+        let mut streamed = String::new();
+        let answer = rag
+            .stream_query_with_feedback("where is load_settings defined", "", |t| streamed.push_str(t))
+            .await
+            .unwrap();
+        assert_eq!(streamed, NO_CONTEXT_ANSWER);
+        assert_eq!(answer.text, NO_CONTEXT_ANSWER);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_feedback_turn_reuses_the_searches() {
+        let (dir, db) = temp_project("feedback", &[("main.rs", "fn main() {\n    run_server();\n}\n")]);
+        let model = citing_model();
+        let rag = service(&dir, &db, &model).await;
+        rag.build_index().await.unwrap();
+        let indexed = model.embed_requests().len();
+
+        rag.query("how does run_server start").await.unwrap();
+        assert_eq!(model.embed_requests().len(), indexed + 1);
+        rag.query_with_feedback("how does run_server start", "shorter please")
+            .await
+            .unwrap();
+        assert_eq!(model.embed_requests().len(), indexed + 1);
+        let chats = model.chats();
+        assert!(chats.last().unwrap().1[0].content.contains("shorter please"));
+
+        // Unchanged files are not embedded again.
+        rag.build_index().await.unwrap();
+        assert_eq!(model.embed_requests().len(), indexed + 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_rewrites_widen_the_search_and_failures_fall_back() {
+        let (dir, db) = temp_project("rewrite", &[("main.rs", "fn main() {\n    run_server();\n}\n")]);
+        let model = MockModel::replying(|_, messages| {
+            let prompt = &messages[0].content;
+            Ok(if prompt.contains("search queries") {
+                r#"{"queries":["run_server", "How does run_server start"], "hypothetical_code":"fn run_server() {}"}"#
+                    .to_string()
+            } else {
+                "It starts in main.".to_string()
+            })
+        });
+        let root = dir.join("project").to_string_lossy().to_string();
+        let config = Config {
+            rag_query_rewrite: true,
+            ..test_config()
+        };
+        let rag = RagService::new(&root, &db, model.clone(), model.clone(), config.clone())
+            .await
+            .unwrap();
+        rag.build_index().await.unwrap();
+        rag.query("how does run_server start").await.unwrap();
+        // The question, one new query and the snippet, embedded together.
+        let requests = model.embed_requests();
+        assert_eq!(
+            requests.last().unwrap(),
+            &vec![
+                "how does run_server start".to_string(),
+                "run_server".to_string(),
+                "fn run_server() {}".to_string()
+            ]
+        );
+
+        // A reply that is not JSON leaves the question as the only search.
+        let model = MockModel::new("not json");
+        let rag = RagService::new(&root, &db, model.clone(), model.clone(), config)
+            .await
+            .unwrap();
+        let answer = rag.query("how does run_server start").await.unwrap();
+        assert_eq!(answer.text, "not json");
+        assert_eq!(model.embed_requests().last().unwrap(), &vec!["how does run_server start".to_string()]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
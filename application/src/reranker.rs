@@ -0,0 +1,311 @@
+use crate::context_packer::truncate_to_tokens;
+use domain::language_model::{GenerationOptions, LanguageModel};
+use domain::session::Message;
+use infrastructure::search::Candidate;
+use serde_json::json;
+use shared::types::Result;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Chunks scored per request.
+const BATCH_SIZE: usize = 8;
+/// Longest excerpt of a chunk shown to the reranker.
+const CHUNK_TOKENS: usize = 300;
+/// Highest score the model may give.
+const MAX_SCORE: u64 = 10;
+
+/// Scores that were asked for, keyed by question hash and chunk id.
+type ScoreCache = HashMap<(String, String), f32>;
+
+/// Second retrieval stage: the model reads the question and each candidate
+/// chunk and scores how useful the chunk is, which small embedding models
+/// judge poorly.
+pub struct Reranker {
+    /// Candidates scored per question, best fused rank first.
+    candidates: usize,
+    /// Scores by question and chunk, valid for one storage generation since
+    /// reindexed chunks keep their ids.
+    scores: Mutex<(u64, ScoreCache)>,
+}
+
+impl Reranker {
+    pub fn new(candidates: usize) -> Self {
+        Self {
+            candidates,
+            scores: Mutex::new((0, HashMap::new())),
+        }
+    }
+
+    /// The best-ranked candidates, up to the configured number, ordered by
+    /// the model's score, which replaces their relevance. Chunks the model rates 0 are dropped.
+    /// Chunks it did not score, for instance after a failed request, follow
+    /// in search order, their relevance scaled below the lowest score.
+    pub async fn rerank<'a, L: LanguageModel>(
+        &self,
+        llm: &L,
+        options: &GenerationOptions,
+        generation: u64,
+        question: &str,
+        mut candidates: Vec<Candidate<'a>>,
+    ) -> Vec<Candidate<'a>> {
+        candidates.truncate(self.candidates);
+        let question_hash = format!("{:x}", md5::compute(question.as_bytes()));
+        let mut known = self.cached(generation, &question_hash, &candidates);
+
+        let unscored: Vec<&Candidate> = candidates
+            .iter()
+            .filter(|c| !known.contains_key(c.id))
+            .collect();
+        if !unscored.is_empty() {
+            eprintln!("Reranking {} chunks...", unscored.len());
+        }
+        for batch in unscored.chunks(BATCH_SIZE) {
+            match Self::score_batch(llm, options, question, batch).await {
+                Ok(scores) => {
+                    for (candidate, score) in batch.iter().zip(scores) {
+                        if let Some(score) = score {
+                            known.insert(candidate.id.to_string(), score);
+                        }
+                    }
+                }
+                Err(e) => {
+                    // The remaining batches would most likely fail the same way.
+                    eprintln!("Reranking failed, keeping search order: {}", e);
+                    break;
+                }
+            }
+        }
+
+        if let Ok(mut cache) = self.scores.lock() {
+            if cache.0 == generation {
+                for (id, score) in &known {
+                    cache.1.insert((question_hash.clone(), id.clone()), *score);
+                }
+            }
+        }
+
+        candidates.retain(|c| known.get(c.id).is_none_or(|score| *score > 0.0));
+        // Search relevance reaches 1.0, so it is put on the score's scale
+        // before selection weighs both.
+        let lowest = candidates
+            .iter()
+            .filter_map(|c| known.get(c.id))
+            .fold(1.0f32, |lowest, score| lowest.min(*score));
+        for candidate in &mut candidates {
+            match known.get(candidate.id) {
+                Some(score) => candidate.relevance = *score,
+                None => candidate.relevance *= lowest,
+            }
+        }
+        // Scored chunks first. Stable, so equal scores and unscored chunks
+        // keep their search order.
+        candidates.sort_by(|a, b| {
+            known
+                .get(b.id)
+                .partial_cmp(&known.get(a.id))
+                .unwrap_or(Ordering::Equal)
+        });
+        candidates
+    }
+
+    /// Cached scores of `candidates` for the question, after dropping a cache
+    /// from another storage generation.
+    fn cached(
+        &self,
+        generation: u64,
+        question_hash: &str,
+        candidates: &[Candidate],
+    ) -> HashMap<String, f32> {
+        let Ok(mut cache) = self.scores.lock() else {
+            return HashMap::new();
+        };
+        if cache.0 != generation {
+            *cache = (generation, HashMap::new());
+        }
+        candidates
+            .iter()
+            .filter_map(|c| {
+                let key = (question_hash.to_string(), c.id.to_string());
+                Some((c.id.to_string(), *cache.1.get(&key)?))
+            })
+            .collect()
+    }
+
+    /// One structured request scoring `batch`; scores are scaled to 0..=1,
+    /// `None` where the model left a chunk out.
+    async fn score_batch<L: LanguageModel>(
+        llm: &L,
+        options: &GenerationOptions,
+        question: &str,
+        batch: &[&Candidate<'_>],
+    ) -> Result<Vec<Option<f32>>> {
+        let chunks: Vec<String> = batch
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let (text, _) = truncate_to_tokens(&c.hit.text, CHUNK_TOKENS);
+                format!("CHUNK {} ({})\n{}", i + 1, c.hit.location(), text)
+            })
+            .collect();
+        let prompt = format!(
+            "Rate how useful each code chunk is for answering the question, from 0 (unrelated) to {} (answers it directly).\n\nQuestion: {}\n\n{}\n\nGive a score for every chunk, by its number.",
+            MAX_SCORE,
+            question,
+            chunks.join("\n\n")
+        );
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "scores": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "chunk": { "type": "integer" },
+                            "score": { "type": "integer", "minimum": 0, "maximum": MAX_SCORE }
+                        },
+                        "required": ["chunk", "score"]
+                    }
+                }
+            },
+            "required": ["scores"]
+        });
+        let reply = llm
+            .structured(&[Message::user(&prompt)], &schema, options)
+            .await?;
+
+        let mut scores = vec![None; batch.len()];
+        for entry in reply["scores"].as_array().into_iter().flatten() {
+            let (Some(chunk), Some(score)) = (entry["chunk"].as_u64(), entry["score"].as_u64())
+            else {
+                continue;
+            };
+            if let Some(slot) = (chunk as usize).checked_sub(1).and_then(|i| scores.get_mut(i)) {
+                *slot = Some(score.min(MAX_SCORE) as f32 / MAX_SCORE as f32);
+            }
+        }
+        Ok(scores)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain::mock::MockModel;
+    use domain::models::SearchHit;
+
+    const VECTOR: [f32; 2] = [1.0, 0.0];
+
+    fn candidates(ids: &[&'static str]) -> Vec<Candidate<'static>> {
+        ids.iter()
+            .enumerate()
+            .map(|(i, id)| Candidate {
+                id,
+                hit: SearchHit {
+                    path: format!("src/{}.rs", id),
+                    start_line: 1,
+                    end_line: 5,
+                    symbol: None,
+                    text: format!("fn {}() {{}}", id),
+                    score: 0.5,
+                },
+                relevance: 1.0 - i as f32 * 0.1,
+                vector: &VECTOR,
+                keyword_match: false,
+            })
+            .collect()
+    }
+
+    fn ranked(candidates: &[Candidate]) -> Vec<(String, f32)> {
+        candidates.iter().map(|c| (c.id.to_string(), c.relevance)).collect()
+    }
+
+    /// Gives chunk n of every batch a score of n, up to 10.
+    fn counting_model() -> MockModel {
+        MockModel::replying(|_, messages| {
+            let chunks = messages[0].content.matches("CHUNK ").count();
+            let scores: Vec<String> = (1..=chunks)
+                .map(|n| format!(r#"{{"chunk":{},"score":{}}}"#, n, n))
+                .collect();
+            Ok(format!(r#"{{"scores":[{}]}}"#, scores.join(",")))
+        })
+    }
+
+    async fn rerank(
+        reranker: &Reranker,
+        model: &MockModel,
+        generation: u64,
+        question: &str,
+        ids: &[&'static str],
+    ) -> Vec<(String, f32)> {
+        let options = GenerationOptions::default();
+        ranked(&reranker.rerank(model, &options, generation, question, candidates(ids)).await)
+    }
+
+    #[tokio::test]
+    async fn test_scores_replace_relevance_and_zero_drops() {
+        let model = MockModel::new(
+            r#"{"scores":[{"chunk":1,"score":3},{"chunk":2,"score":15},{"chunk":3,"score":0},{"chunk":9,"score":5},{"chunk":"4","score":1}]}"#,
+        );
+        let reranked = rerank(&Reranker::new(10), &model, 1, "q", &["a", "b", "c", "d"]).await;
+        // b is capped at the top score; d was not scored, so it follows the
+        // scored chunks with its relevance scaled by the lowest score.
+        assert_eq!(
+            reranked,
+            vec![
+                ("b".into(), 1.0),
+                ("a".into(), 0.3),
+                ("d".into(), (1.0 - 3.0f32 * 0.1) * 0.3)
+            ]
+        );
+        let prompt = &model.chats()[0].1[0].content;
+        assert!(prompt.contains("CHUNK 1 (src/a.rs:1-5)\nfn a() {}"));
+        assert!(prompt.contains("Question: q"));
+    }
+
+    #[tokio::test]
+    async fn test_candidates_are_limited_and_batched() {
+        let model = counting_model();
+        let ids = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k"];
+        let reranked = rerank(&Reranker::new(10), &model, 1, "q", &ids).await;
+        assert_eq!(reranked.len(), 10);
+        assert_eq!(model.chats().len(), 2);
+        // Chunk 8 of the first batch scores highest; k was never a candidate.
+        assert_eq!(reranked[0], ("h".into(), 0.8));
+        assert!(!reranked.iter().any(|(id, _)| id == "k"));
+    }
+
+    #[tokio::test]
+    async fn test_scores_are_cached_per_question_and_generation() {
+        let model = counting_model();
+        let reranker = Reranker::new(10);
+        let first = rerank(&reranker, &model, 1, "q", &["a", "b"]).await;
+        assert_eq!(rerank(&reranker, &model, 1, "q", &["a", "b"]).await, first);
+        assert_eq!(model.chats().len(), 1);
+
+        // Only the new candidate is scored.
+        rerank(&reranker, &model, 1, "q", &["c", "a"]).await;
+        assert_eq!(model.chats().len(), 2);
+        assert_eq!(model.chats()[1].1[0].content.matches("CHUNK ").count(), 1);
+
+        rerank(&reranker, &model, 1, "other question", &["a"]).await;
+        assert_eq!(model.chats().len(), 3);
+        // Reindexing invalidates every score.
+        rerank(&reranker, &model, 2, "q", &["a", "b"]).await;
+        assert_eq!(model.chats().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_failed_request_keeps_search_order() {
+        let model = MockModel::new("not json");
+        let reranker = Reranker::new(10);
+        let ids = ["a", "b", "c", "d", "e", "f", "g", "h", "i"];
+        let reranked = rerank(&reranker, &model, 1, "q", &ids).await;
+        assert_eq!(reranked, ranked(&candidates(&ids)));
+        // The second batch is not tried, and nothing is cached.
+        assert_eq!(model.chats().len(), 1);
+        rerank(&reranker, &model, 1, "q", &ids).await;
+        assert_eq!(model.chats().len(), 2);
+    }
+}
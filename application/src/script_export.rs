@@ -0,0 +1,208 @@
+use domain::command_plan::CommandPlan;
+use shared::utils::format_utc;
+
+/// Quote `text` as a single bash word.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+fn comment_lines(label: &str, text: &str) -> String {
+    let mut out = String::new();
+    for (i, line) in text.lines().enumerate() {
+        let prefix = if i == 0 { label } else { "" };
+        out.push_str(&format!("# {:<9}{}\n", prefix, line));
+    }
+    out
+}
+
+/// Render an agent plan as a standalone, reviewable bash script.
+///
+/// The script stops on the first error, verifies each step that declares a
+/// `verify` command, and on failure runs the `rollback` commands of the steps
+/// that were started, newest first. A step's rollback is registered before the
+/// step runs, so a step that partly applied and then failed is undone too.
+pub fn render_plan_script(plan: &CommandPlan, model: &str, created_at: u64) -> String {
+    let total = plan.steps.len();
+    let mut script = String::from("#!/usr/bin/env bash\n");
+    script.push_str("#\n# Generated by vibe_cli from an agent plan. Review every step before running.\n#\n");
+    script.push_str(&comment_lines("Request:", &plan.description));
+    script.push_str(&comment_lines("Model:", model));
+    script.push_str(&comment_lines("Date:", &format_utc(created_at)));
+    script.push_str(&comment_lines("Plan id:", &plan.id));
+    script.push_str(&format!("# Steps:   {}\n", total));
+    script.push_str(
+        r#"
+set -euo pipefail
+set -o errtrace
+
+STEP=0
+ROLLBACKS=()
+
+log() {
+  printf '\n==> %s\n' "$*" >&2
+}
+
+verify_step() {
+  if ! bash -c "$1"; then
+    printf 'Verification failed: %s\n' "$1" >&2
+    return 1
+  fi
+}
+
+on_failure() {
+  local status=$?
+  trap - ERR INT TERM
+  log "Step ${STEP} failed (exit ${status})"
+  local i
+  for (( i=${#ROLLBACKS[@]}-1; i>=0; i-- )); do
+    log "Rolling back: ${ROLLBACKS[i]}"
+    bash -c "${ROLLBACKS[i]}" || printf 'Rollback failed: %s\n' "${ROLLBACKS[i]}" >&2
+  done
+  exit "${status}"
+}
+trap on_failure ERR INT TERM
+"#,
+    );
+
+    for (i, step) in plan.steps.iter().enumerate() {
+        let number = i + 1;
+        script.push_str(&format!(
+            "\n# --- Step {}/{} {}\nSTEP={}\nlog {}\n",
+            number,
+            total,
+            "-".repeat(50),
+            number,
+            shell_quote(&format!("Step {}/{}: {}", number, total, step.command)),
+        ));
+        if let Some(rollback) = step.rollback.as_deref().filter(|r| !r.trim().is_empty()) {
+            script.push_str(&format!("ROLLBACKS+=({})\n", shell_quote(rollback.trim())));
+        }
+        script.push_str(step.command.trim());
+        script.push('\n');
+        if let Some(verify) = step.verify.as_deref().filter(|v| !v.trim().is_empty()) {
+            script.push_str(&format!("verify_step {}\n", shell_quote(verify.trim())));
+        }
+    }
+
+    script.push_str("\ntrap - ERR INT TERM\nlog \"All steps completed\"\n");
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_plan_script, shell_quote};
+    use domain::command_plan::{CommandPlan, PlanStep};
+    use std::path::{Path, PathBuf};
+
+    fn step(command: &str, verify: Option<&str>, rollback: Option<&str>) -> PlanStep {
+        PlanStep {
+            verify: verify.map(str::to_string),
+            rollback: rollback.map(str::to_string),
+            ..PlanStep::new(command)
+        }
+    }
+
+    fn plan(steps: Vec<PlanStep>) -> CommandPlan {
+        CommandPlan::new("1-abcd".into(), "clean up\nold logs".into(), steps)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("script_export_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Run `script` with bash in `dir`, returning its exit code.
+    fn run(script: &str, dir: &Path) -> Option<i32> {
+        let path = dir.join("plan.sh");
+        std::fs::write(&path, script).unwrap();
+        std::process::Command::new("bash")
+            .arg(&path)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status
+            .code()
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("ls -la"), "'ls -la'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("$HOME"), "'$HOME'");
+    }
+
+    #[test]
+    fn test_header_and_strict_mode() {
+        let script = render_plan_script(&plan(vec![step("ls", None, None)]), "qwen", 0);
+        assert!(script.starts_with("#!/usr/bin/env bash\n"));
+        assert!(script.contains("# Request: clean up\n#          old logs\n"));
+        assert!(script.contains("# Model:   qwen\n"));
+        assert!(script.contains("\nset -euo pipefail\n"));
+        assert!(script.contains("trap on_failure ERR INT TERM\n"));
+        assert!(script.ends_with("log \"All steps completed\"\n"));
+    }
+
+    #[test]
+    fn test_step_text_is_quoted() {
+        let script = render_plan_script(
+            &plan(vec![step("echo 'hi' > out", Some("test -f out"), Some("rm -f 'out'"))]),
+            "qwen",
+            0,
+        );
+        assert!(script.contains(r"log 'Step 1/1: echo '\''hi'\'' > out'"));
+        assert!(script.contains("\necho 'hi' > out\n"));
+        assert!(script.contains("verify_step 'test -f out'\n"));
+        assert!(script.contains(r"ROLLBACKS+=('rm -f '\''out'\''')"));
+    }
+
+    #[test]
+    fn test_rollback_is_registered_before_the_step() {
+        let script = render_plan_script(
+            &plan(vec![step("touch a", Some("test -f a"), Some("rm -f a"))]),
+            "qwen",
+            0,
+        );
+        let rollback = script.find("ROLLBACKS+=").unwrap();
+        let command = script.find("\ntouch a\n").unwrap();
+        let verify = script.find("verify_step 'test -f a'").unwrap();
+        assert!(rollback < command && command < verify);
+    }
+
+    #[test]
+    fn test_script_runs_all_steps() {
+        let dir = temp_dir("success");
+        let script = render_plan_script(
+            &plan(vec![
+                step("touch a", Some("test -f a"), Some("rm -f a")),
+                step("touch b", None, None),
+            ]),
+            "qwen",
+            0,
+        );
+        assert_eq!(run(&script, &dir), Some(0));
+        assert!(dir.join("a").exists() && dir.join("b").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_failed_verify_rolls_back_the_step_and_earlier_ones() {
+        let dir = temp_dir("rollback");
+        let script = render_plan_script(
+            &plan(vec![
+                step("touch a", None, Some("rm -f a")),
+                // Applies, then fails its verification.
+                step("touch b", Some("test -f missing"), Some("rm -f b")),
+                step("touch c", None, None),
+            ]),
+            "qwen",
+            0,
+        );
+        assert_eq!(run(&script, &dir), Some(1));
+        assert!(!dir.join("a").exists());
+        assert!(!dir.join("b").exists());
+        assert!(!dir.join("c").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
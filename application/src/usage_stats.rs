@@ -0,0 +1,277 @@
+use domain::usage::{RequestKind, StatsEvent, Usage};
+use std::collections::BTreeMap;
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Latency percentiles in milliseconds.
+#[derive(Debug, Clone, Copy)]
+pub struct Percentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles, or `None` for an empty sample.
+    pub fn of(mut samples: Vec<u64>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let rank = |p: f64| {
+            let index = ((p * samples.len() as f64).ceil() as usize).clamp(1, samples.len());
+            samples[index - 1]
+        };
+        Some(Self {
+            p50: rank(0.50),
+            p90: rank(0.90),
+            p99: rank(0.99),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModeStats {
+    pub mode: String,
+    pub chat_requests: usize,
+    pub embed_requests: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Latency of chat requests; embedding batches would drown it out.
+    pub latency: Option<Percentiles>,
+    pub cache_hits: usize,
+    pub cache_lookups: usize,
+}
+
+impl ModeStats {
+    pub fn cache_hit_rate(&self) -> Option<f64> {
+        (self.cache_lookups > 0).then(|| self.cache_hits as f64 / self.cache_lookups as f64)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ModelStats {
+    pub model: String,
+    pub requests: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub latency: Option<Percentiles>,
+    pub avg_load_ms: u64,
+    pub max_load_ms: u64,
+    /// Completion tokens per second of server time, when the backend reports it.
+    pub tokens_per_sec: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DayStats {
+    /// Unix timestamp of the start of the (UTC) day.
+    pub day: u64,
+    pub chat_requests: usize,
+    pub tokens: u64,
+    pub latency: Option<Percentiles>,
+    pub avg_load_ms: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StatsReport {
+    pub modes: Vec<ModeStats>,
+    pub models: Vec<ModelStats>,
+    pub days: Vec<DayStats>,
+}
+
+fn average(values: impl Iterator<Item = u64>) -> u64 {
+    let (sum, count) = values.fold((0u64, 0u64), |(s, c), v| (s + v, c + 1));
+    sum.checked_div(count).unwrap_or(0)
+}
+
+fn chat_latencies<'a>(usages: impl Iterator<Item = &'a Usage>) -> Option<Percentiles> {
+    Percentiles::of(
+        usages
+            .filter(|u| u.kind == RequestKind::Chat)
+            .map(|u| u.wall_ms)
+            .collect(),
+    )
+}
+
+/// Aggregate raw events per mode, per model and per day.
+pub fn summarize(events: &[StatsEvent]) -> StatsReport {
+    let mut by_mode: BTreeMap<&str, (Vec<&Usage>, usize, usize)> = BTreeMap::new();
+    let mut by_model: BTreeMap<&str, Vec<&Usage>> = BTreeMap::new();
+    let mut by_day: BTreeMap<u64, Vec<&Usage>> = BTreeMap::new();
+
+    for event in events {
+        let entry = by_mode.entry(event.mode()).or_default();
+        match event {
+            StatsEvent::Request {
+                timestamp, usage, ..
+            } => {
+                entry.0.push(usage);
+                by_model.entry(usage.model.as_str()).or_default().push(usage);
+                by_day
+                    .entry(timestamp - timestamp % SECS_PER_DAY)
+                    .or_default()
+                    .push(usage);
+            }
+            StatsEvent::Cache { hit, .. } => {
+                entry.2 += 1;
+                if *hit {
+                    entry.1 += 1;
+                }
+            }
+        }
+    }
+
+    let modes = by_mode
+        .into_iter()
+        .map(|(mode, (usages, hits, lookups))| ModeStats {
+            mode: mode.to_string(),
+            chat_requests: usages.iter().filter(|u| u.kind == RequestKind::Chat).count(),
+            embed_requests: usages.iter().filter(|u| u.kind == RequestKind::Embed).count(),
+            prompt_tokens: usages.iter().map(|u| u.prompt_tokens).sum(),
+            completion_tokens: usages.iter().map(|u| u.completion_tokens).sum(),
+            latency: chat_latencies(usages.iter().copied()),
+            cache_hits: hits,
+            cache_lookups: lookups,
+        })
+        .collect();
+
+    let models = by_model
+        .into_iter()
+        .map(|(model, usages)| {
+            let completion_tokens: u64 = usages.iter().map(|u| u.completion_tokens).sum();
+            let server_ms: u64 = usages
+                .iter()
+                .filter(|u| u.kind == RequestKind::Chat)
+                .map(|u| u.total_ms.saturating_sub(u.load_ms))
+                .sum();
+            ModelStats {
+                model: model.to_string(),
+                requests: usages.len(),
+                prompt_tokens: usages.iter().map(|u| u.prompt_tokens).sum(),
+                completion_tokens,
+                latency: chat_latencies(usages.iter().copied()),
+                avg_load_ms: average(usages.iter().map(|u| u.load_ms)),
+                max_load_ms: usages.iter().map(|u| u.load_ms).max().unwrap_or(0),
+                tokens_per_sec: (server_ms > 0 && completion_tokens > 0)
+                    .then(|| completion_tokens as f64 * 1000.0 / server_ms as f64),
+            }
+        })
+        .collect();
+
+    let days = by_day
+        .into_iter()
+        .map(|(day, usages)| DayStats {
+            day,
+            chat_requests: usages.iter().filter(|u| u.kind == RequestKind::Chat).count(),
+            tokens: usages
+                .iter()
+                .map(|u| u.prompt_tokens + u.completion_tokens)
+                .sum(),
+            latency: chat_latencies(usages.iter().copied()),
+            avg_load_ms: average(usages.iter().map(|u| u.load_ms)),
+        })
+        .collect();
+
+    StatsReport {
+        modes,
+        models,
+        days,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(timestamp: u64, mode: &str, model: &str, kind: RequestKind, wall_ms: u64) -> StatsEvent {
+        StatsEvent::Request {
+            timestamp,
+            mode: mode.to_string(),
+            usage: Usage {
+                model: model.to_string(),
+                kind,
+                prompt_tokens: 100,
+                completion_tokens: 20,
+                total_ms: 1_200,
+                load_ms: 200,
+                wall_ms,
+            },
+        }
+    }
+
+    fn cache(mode: &str, hit: bool) -> StatsEvent {
+        StatsEvent::Cache {
+            timestamp: 0,
+            mode: mode.to_string(),
+            hit,
+        }
+    }
+
+    #[test]
+    fn test_percentiles_of_empty_and_single_samples() {
+        assert!(Percentiles::of(Vec::new()).is_none());
+        let one = Percentiles::of(vec![42]).unwrap();
+        assert_eq!((one.p50, one.p90, one.p99), (42, 42, 42));
+    }
+
+    #[test]
+    fn test_percentiles_use_nearest_rank() {
+        let p = Percentiles::of((1..=100).rev().collect()).unwrap();
+        assert_eq!((p.p50, p.p90, p.p99), (50, 90, 99));
+        let p = Percentiles::of(vec![30, 10, 20]).unwrap();
+        assert_eq!((p.p50, p.p90, p.p99), (20, 30, 30));
+    }
+
+    #[test]
+    fn test_summarize_empty_log() {
+        let report = summarize(&[]);
+        assert!(report.modes.is_empty());
+        assert!(report.models.is_empty());
+        assert!(report.days.is_empty());
+    }
+
+    #[test]
+    fn test_summarize_groups_by_mode_model_and_day() {
+        let day = 20_000 * SECS_PER_DAY;
+        let events = vec![
+            request(day + 10, "query", "small", RequestKind::Chat, 300),
+            request(day + 20, "query", "small", RequestKind::Chat, 100),
+            request(day + 30, "rag", "embed", RequestKind::Embed, 5_000),
+            request(day + SECS_PER_DAY + 5, "rag", "small", RequestKind::Chat, 200),
+            cache("query", true),
+            cache("query", false),
+            cache("explain", false),
+        ];
+        let report = summarize(&events);
+
+        let modes: Vec<&str> = report.modes.iter().map(|m| m.mode.as_str()).collect();
+        assert_eq!(modes, vec!["explain", "query", "rag"]);
+        let explain = &report.modes[0];
+        assert_eq!(explain.chat_requests, 0);
+        assert!(explain.latency.is_none());
+        assert_eq!(explain.cache_hit_rate(), Some(0.0));
+        let query = &report.modes[1];
+        assert_eq!(query.chat_requests, 2);
+        assert_eq!(query.prompt_tokens, 200);
+        assert_eq!(query.latency.unwrap().p50, 100);
+        assert_eq!(query.cache_hit_rate(), Some(0.5));
+        let rag = &report.modes[2];
+        assert_eq!((rag.chat_requests, rag.embed_requests), (1, 1));
+        // Embedding batches do not count towards latency.
+        assert_eq!(rag.latency.unwrap().p99, 200);
+        assert_eq!(rag.cache_hit_rate(), None);
+
+        let small = report.models.iter().find(|m| m.model == "small").unwrap();
+        assert_eq!(small.requests, 3);
+        assert_eq!(small.completion_tokens, 60);
+        assert_eq!((small.avg_load_ms, small.max_load_ms), (200, 200));
+        // 60 tokens in 3 x (1200 - 200) ms of generation.
+        assert_eq!(small.tokens_per_sec, Some(20.0));
+
+        assert_eq!(report.days.len(), 2);
+        assert_eq!(report.days[0].day, day);
+        assert_eq!(report.days[0].chat_requests, 2);
+        assert_eq!(report.days[0].tokens, 360);
+        assert_eq!(report.days[1].day, day + SECS_PER_DAY);
+    }
+}
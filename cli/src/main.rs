@@ -1,10 +1,15 @@
-use clap::Parser;
-use presentation::cli::{Cli, CliApp};
+use presentation::cli::{report_error, Cli, CliApp};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
-    let mut app = CliApp::new();
-    app.run(cli).await?;
+    let cli = Cli::parse_args(std::env::args_os()).unwrap_or_else(|err| err.exit());
+    let result = match CliApp::new() {
+        Ok(mut app) => app.run(cli).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        report_error(&err);
+        std::process::exit(1);
+    }
     Ok(())
 }
@@ -0,0 +1,309 @@
+use super::command_plan::CommandPlan;
+use serde::{Deserialize, Serialize};
+use shared::utils::unix_timestamp;
+use std::collections::HashSet;
+
+/// A persisted agent run: the plan that was approved plus the outcome of each step.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRun {
+    pub id: String,
+    pub task: String,
+    pub model: String,
+    pub plan: CommandPlan,
+    /// One record per plan step, in the same order as `plan.steps`.
+    pub steps: Vec<StepRecord>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub status: StepStatus,
+    pub exit_code: Option<i32>,
+    pub output: String,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+}
+
+impl StepRecord {
+    pub fn pending() -> Self {
+        Self {
+            status: StepStatus::Pending,
+            exit_code: None,
+            output: String::new(),
+            started_at: None,
+            finished_at: None,
+        }
+    }
+}
+
+impl AgentRun {
+    pub fn new(task: &str, model: &str, plan: CommandPlan) -> Self {
+        let now = unix_timestamp();
+        let steps = plan.steps.iter().map(|_| StepRecord::pending()).collect();
+        Self {
+            id: plan.id.clone(),
+            task: task.to_string(),
+            model: model.to_string(),
+            plan,
+            steps,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Index of the first step that has not completed successfully.
+    pub fn first_incomplete_step(&self) -> Option<usize> {
+        self.steps
+            .iter()
+            .position(|s| s.status != StepStatus::Completed)
+    }
+
+    pub fn completed_steps(&self) -> usize {
+        self.steps
+            .iter()
+            .filter(|s| s.status == StepStatus::Completed)
+            .count()
+    }
+
+    /// The next steps to execute: either a batch of independent read-only steps
+    /// that can run in parallel, or a single mutating step. Steps already in
+    /// `attempted` are not offered again; a failed or skipped dependency only
+    /// counts as satisfied when it is in `waived`. Empty when nothing can run.
+    pub fn next_wave(&self, attempted: &HashSet<usize>, waived: &HashSet<usize>) -> Vec<usize> {
+        let deps = self.plan.dependencies();
+        let ready: Vec<usize> = (0..self.plan.steps.len())
+            .filter(|i| !attempted.contains(i))
+            .filter(|&i| self.steps.get(i).map(|s| s.status) != Some(StepStatus::Completed))
+            .filter(|&i| {
+                deps[i].iter().all(|d| {
+                    waived.contains(d)
+                        || self.steps.get(*d).map(|s| s.status) == Some(StepStatus::Completed)
+                })
+            })
+            .collect();
+
+        let Some(&first) = ready.first() else {
+            return Vec::new();
+        };
+        if self.plan.steps[first].runs_concurrently() {
+            ready
+                .into_iter()
+                .filter(|&i| self.plan.steps[i].runs_concurrently())
+                .collect()
+        } else {
+            vec![first]
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.first_incomplete_step().is_none()
+    }
+
+    pub fn mark_started(&mut self, index: usize) {
+        let now = unix_timestamp();
+        if let Some(step) = self.steps.get_mut(index) {
+            step.status = StepStatus::Running;
+            step.started_at = Some(now);
+            step.finished_at = None;
+        }
+        self.updated_at = now;
+    }
+
+    pub fn mark_finished(&mut self, index: usize, exit_code: Option<i32>, output: String) {
+        let now = unix_timestamp();
+        if let Some(step) = self.steps.get_mut(index) {
+            step.status = if exit_code == Some(0) {
+                StepStatus::Completed
+            } else {
+                StepStatus::Failed
+            };
+            step.exit_code = exit_code;
+            step.output = output;
+            step.finished_at = Some(now);
+        }
+        self.updated_at = now;
+    }
+
+    pub fn mark_skipped(&mut self, index: usize) {
+        let now = unix_timestamp();
+        if let Some(step) = self.steps.get_mut(index) {
+            step.status = StepStatus::Skipped;
+            step.finished_at = Some(now);
+        }
+        self.updated_at = now;
+    }
+}
+
+/// Generate a sortable, reasonably unique run id from the current time.
+pub fn new_run_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    format!("{}-{:04x}", unix_timestamp(), nanos & 0xffff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_plan::PlanStep;
+
+    fn run_of(commands: &[&str]) -> AgentRun {
+        let steps = commands.iter().map(|c| PlanStep::new(*c)).collect();
+        AgentRun::new("task", "model", CommandPlan::new("run-1".into(), "task".into(), steps))
+    }
+
+    fn step(command: &str, depends_on: Option<Vec<usize>>, read_only: bool) -> PlanStep {
+        PlanStep {
+            depends_on,
+            read_only,
+            ..PlanStep::new(command)
+        }
+    }
+
+    fn run_with(steps: Vec<PlanStep>) -> AgentRun {
+        AgentRun::new("task", "model", CommandPlan::new("run-1".into(), "task".into(), steps))
+    }
+
+    fn set(items: &[usize]) -> HashSet<usize> {
+        items.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_new_run_is_pending() {
+        let run = run_of(&["ls", "pwd"]);
+        assert_eq!(run.id, "run-1");
+        assert_eq!(run.steps.len(), 2);
+        assert!(run.steps.iter().all(|s| s.status == StepStatus::Pending));
+        assert_eq!(run.first_incomplete_step(), Some(0));
+        assert!(!run.is_finished());
+    }
+
+    #[test]
+    fn test_step_transitions() {
+        let mut run = run_of(&["ls", "false", "pwd"]);
+        run.mark_started(0);
+        assert_eq!(run.steps[0].status, StepStatus::Running);
+        assert!(run.steps[0].started_at.is_some());
+        assert!(run.steps[0].finished_at.is_none());
+
+        run.mark_finished(0, Some(0), "out".into());
+        assert_eq!(run.steps[0].status, StepStatus::Completed);
+        assert_eq!(run.steps[0].output, "out");
+        assert!(run.steps[0].finished_at.is_some());
+
+        run.mark_started(1);
+        run.mark_finished(1, Some(1), String::new());
+        assert_eq!(run.steps[1].status, StepStatus::Failed);
+        assert_eq!(run.steps[1].exit_code, Some(1));
+
+        run.mark_skipped(2);
+        assert_eq!(run.steps[2].status, StepStatus::Skipped);
+        assert_eq!(run.completed_steps(), 1);
+        assert_eq!(run.first_incomplete_step(), Some(1));
+    }
+
+    #[test]
+    fn test_killed_step_fails() {
+        let mut run = run_of(&["sleep 10"]);
+        run.mark_started(0);
+        run.mark_finished(0, None, String::new());
+        assert_eq!(run.steps[0].status, StepStatus::Failed);
+    }
+
+    #[test]
+    fn test_retried_step_finishes_run() {
+        let mut run = run_of(&["ls"]);
+        run.mark_started(0);
+        run.mark_finished(0, Some(2), String::new());
+        run.mark_started(0);
+        assert_eq!(run.steps[0].status, StepStatus::Running);
+        assert!(run.steps[0].finished_at.is_none());
+        run.mark_finished(0, Some(0), String::new());
+        assert!(run.is_finished());
+    }
+
+    #[test]
+    fn test_out_of_range_index_is_ignored() {
+        let mut run = run_of(&["ls"]);
+        run.mark_started(5);
+        run.mark_finished(5, Some(0), String::new());
+        run.mark_skipped(5);
+        assert_eq!(run.steps[0].status, StepStatus::Pending);
+    }
+
+    #[test]
+    fn test_read_only_steps_form_one_wave() {
+        let run = run_with(vec![
+            step("ls", Some(vec![]), true),
+            step("df -h", Some(vec![]), true),
+            step("touch a", Some(vec![1, 2]), false),
+        ]);
+        assert_eq!(run.next_wave(&set(&[]), &set(&[])), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_mutating_step_runs_alone() {
+        let mut run = run_with(vec![
+            step("touch a", Some(vec![]), false),
+            step("ls", Some(vec![]), true),
+        ]);
+        assert_eq!(run.next_wave(&set(&[]), &set(&[])), vec![0]);
+        run.mark_finished(0, Some(0), String::new());
+        assert_eq!(run.next_wave(&set(&[0]), &set(&[])), vec![1]);
+    }
+
+    #[test]
+    fn test_waves_follow_dependencies() {
+        let mut run = run_with(vec![
+            step("ls a", Some(vec![]), true),
+            step("ls b", Some(vec![1]), true),
+            step("ls c", Some(vec![2]), true),
+        ]);
+        assert_eq!(run.next_wave(&set(&[]), &set(&[])), vec![0]);
+        run.mark_finished(0, Some(0), String::new());
+        assert_eq!(run.next_wave(&set(&[0]), &set(&[])), vec![1]);
+        run.mark_finished(1, Some(0), String::new());
+        assert_eq!(run.next_wave(&set(&[0, 1]), &set(&[])), vec![2]);
+    }
+
+    #[test]
+    fn test_failed_dependency_blocks_until_waived() {
+        let mut run = run_with(vec![
+            step("ls a", Some(vec![]), true),
+            step("ls b", Some(vec![1]), true),
+        ]);
+        run.mark_finished(0, Some(2), String::new());
+        assert!(run.next_wave(&set(&[0]), &set(&[])).is_empty());
+        assert_eq!(run.next_wave(&set(&[0]), &set(&[0])), vec![1]);
+    }
+
+    #[test]
+    fn test_cyclic_plan_still_progresses() {
+        let mut run = run_with(vec![
+            step("ls a", Some(vec![2]), true),
+            step("ls b", Some(vec![1]), true),
+        ]);
+        assert_eq!(run.next_wave(&set(&[]), &set(&[])), vec![0]);
+        run.mark_finished(0, Some(0), String::new());
+        assert_eq!(run.next_wave(&set(&[0]), &set(&[])), vec![1]);
+    }
+
+    #[test]
+    fn test_resumed_run_skips_completed_steps() {
+        let mut run = run_with(vec![step("touch a", None, false), step("touch b", None, false)]);
+        run.mark_finished(0, Some(0), String::new());
+        assert_eq!(run.next_wave(&set(&[]), &set(&[])), vec![1]);
+    }
+}
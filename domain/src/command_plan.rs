@@ -1,14 +1,242 @@
 use serde::{Deserialize, Serialize};
+use shared::shell::{tokenize, Token};
 use shared::types::Result;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CommandPlan {
     pub id: String,
     pub description: String,
-    pub steps: Vec<String>,
+    pub steps: Vec<PlanStep>,
     pub safety_checks: Vec<SafetyCheck>,
 }
 
+/// A single step of an agent plan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanStep {
+    pub command: String,
+    /// Read-only command that exits 0 when the step had the intended effect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify: Option<String>,
+    /// Command that undoes the step.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollback: Option<String>,
+    /// 1-based numbers of earlier steps that must complete first.
+    /// `None` means "after the previous step", which keeps old plans sequential.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<usize>>,
+    /// Declared by the model when the command only inspects state.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub read_only: bool,
+}
+
+impl PlanStep {
+    pub fn new(command: impl Into<String>) -> Self {
+        Self {
+            command: command.into(),
+            verify: None,
+            rollback: None,
+            depends_on: None,
+            read_only: false,
+        }
+    }
+
+    /// Whether the step may run alongside other steps. The model's `read_only`
+    /// claim is only trusted when the command has no obvious side effects.
+    pub fn runs_concurrently(&self) -> bool {
+        self.read_only && !looks_mutating(&self.command)
+    }
+}
+
+/// Programs that only inspect state, whatever their arguments, apart from
+/// the exceptions in `writes_anyway`.
+const READ_ONLY_PROGRAMS: [&str; 64] = [
+    "basename", "cat", "cmp", "column", "cut", "date", "df", "diff", "dig", "dirname", "du",
+    "echo", "egrep", "env", "false", "fgrep", "file", "find", "free", "getent", "grep", "head",
+    "host", "hostname", "id", "jq", "less", "locale", "ls", "lsblk", "lscpu", "lsof", "lspci",
+    "lsusb", "md5sum", "more", "nl", "nproc", "nslookup", "od", "pgrep", "printenv", "printf",
+    "ps", "pwd", "readlink", "realpath", "rg", "sed", "sha1sum", "sha256sum", "sort", "ss",
+    "stat", "tac", "tail", "test", "tr", "tree", "true", "uname", "uniq", "wc", "whoami",
+];
+
+/// Subcommands that only inspect state, for programs that also have writing ones.
+const READ_ONLY_SUBCOMMANDS: [(&str, &[&str]); 4] = [
+    ("git", &["status", "log", "diff", "show", "rev-parse", "ls-files", "blame", "grep"]),
+    (
+        "systemctl",
+        &["status", "is-active", "is-enabled", "is-failed", "list-units", "show", "cat"],
+    ),
+    ("docker", &["ps", "images", "inspect", "logs", "version", "info"]),
+    ("apt", &["list", "show", "search", "policy"]),
+];
+
+/// Whether a listed read-only program writes anyway because of its arguments.
+fn writes_anyway(program: &str, args: &[&str]) -> bool {
+    match program {
+        "find" => args.iter().any(|a| {
+            matches!(
+                *a,
+                "-delete" | "-exec" | "-execdir" | "-ok" | "-okdir" | "-fprint" | "-fprint0"
+                    | "-fprintf" | "-fls"
+            )
+        }),
+        "sort" | "tree" => args
+            .iter()
+            .any(|a| a.starts_with("-o") || a.starts_with("--output")),
+        "sed" => args.iter().any(|a| {
+            a.starts_with("--in-place")
+                || (a.starts_with('-') && !a.starts_with("--") && a.contains('i'))
+        }),
+        // `uniq IN OUT` writes OUT.
+        "uniq" => args.iter().filter(|a| !a.starts_with('-')).count() > 1,
+        // `env CMD` runs CMD.
+        "env" => args.iter().any(|a| !a.starts_with('-') && !a.contains('=')),
+        "date" => args.iter().any(|a| *a == "-s" || a.starts_with("--set")),
+        "hostname" => args.iter().any(|a| !a.starts_with('-')),
+        "ss" => args.iter().any(|a| *a == "-K" || *a == "--kill"),
+        _ => false,
+    }
+}
+
+/// Whether `command` may change anything. Only commands made of known
+/// read-only programs, piped or chained, without redirection to a file or
+/// command substitution, count as read-only; everything else is assumed
+/// to write.
+fn looks_mutating(command: &str) -> bool {
+    if command.contains("$(") || command.contains('`') {
+        return true;
+    }
+    let Ok(tokens) = tokenize(command) else {
+        return true;
+    };
+    let mut segments: Vec<Vec<&str>> = vec![Vec::new()];
+    let mut tokens = tokens.iter();
+    while let Some(token) = tokens.next() {
+        let op = match token {
+            Token::Word(word) => {
+                if let Some(segment) = segments.last_mut() {
+                    segment.push(word);
+                }
+                continue;
+            }
+            // `2>` and the like name a descriptor ahead of the operator.
+            Token::Operator(op) => op.trim_start_matches(|c: char| c.is_ascii_digit()),
+        };
+        match op {
+            "|" | "&&" | "||" | ";" | "&" => segments.push(Vec::new()),
+            // The file read from is not an argument.
+            "<" => {
+                tokens.next();
+            }
+            // Discarding output is harmless; any other redirection writes a file.
+            ">" | ">>" | "&>" | ">&" => {
+                let harmless = matches!(tokens.next(), Some(Token::Word(target))
+                    if target == "/dev/null"
+                        || (op == ">&" && target.chars().all(|c| c.is_ascii_digit())));
+                if !harmless {
+                    return true;
+                }
+            }
+            _ => return true,
+        }
+    }
+    segments.iter().filter(|words| !words.is_empty()).any(|words| {
+        // Leading `NAME=value` assignments only set the environment.
+        let mut words = words.iter().copied().skip_while(|w| {
+            w.split_once('=').is_some_and(|(name, _)| {
+                !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+            })
+        });
+        let Some(program) = words.next() else {
+            return false;
+        };
+        let program = program.rsplit('/').next().unwrap_or(program);
+        let args: Vec<&str> = words.collect();
+        if let Some((_, subcommands)) = READ_ONLY_SUBCOMMANDS.iter().find(|(p, _)| *p == program) {
+            let subcommand = args.iter().find(|a| !a.starts_with('-'));
+            return !subcommand.is_some_and(|s| subcommands.contains(s));
+        }
+        !READ_ONLY_PROGRAMS.contains(&program) || writes_anyway(program, &args)
+    })
+}
+
+impl CommandPlan {
+    /// 0-based dependencies of every step. Only references to earlier steps are
+    /// kept, so the graph is always acyclic (see `ignored_dependencies` for the
+    /// rest), and each mutating step also waits for the previous mutating step
+    /// so side effects keep their plan order.
+    pub fn dependencies(&self) -> Vec<Vec<usize>> {
+        let mut last_mutating = None;
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let mut deps: Vec<usize> = match &step.depends_on {
+                    Some(numbers) => numbers
+                        .iter()
+                        .filter(|&&n| n >= 1 && n - 1 < i)
+                        .map(|n| n - 1)
+                        .collect(),
+                    None => i.checked_sub(1).into_iter().collect(),
+                };
+                if !step.runs_concurrently() {
+                    if let Some(prev) = last_mutating {
+                        if !deps.contains(&prev) {
+                            deps.push(prev);
+                        }
+                    }
+                    last_mutating = Some(i);
+                }
+                deps.sort_unstable();
+                deps.dedup();
+                deps
+            })
+            .collect()
+    }
+
+    /// `(step, number)` for every `depends_on` number that `dependencies`
+    /// cannot honour because it does not name an earlier step.
+    pub fn ignored_dependencies(&self) -> Vec<(usize, usize)> {
+        self.steps
+            .iter()
+            .enumerate()
+            .flat_map(|(i, step)| {
+                step.depends_on
+                    .iter()
+                    .flatten()
+                    .filter(move |&&n| n == 0 || n > i)
+                    .map(move |&n| (i, n))
+            })
+            .collect()
+    }
+
+    /// Every step that directly or transitively depends on step `index`, sorted.
+    pub fn dependents_of(&self, index: usize) -> Vec<usize> {
+        let deps = self.dependencies();
+        let mut affected = vec![false; self.steps.len()];
+        if let Some(slot) = affected.get_mut(index) {
+            *slot = true;
+        }
+        // Dependencies only point backwards, so one forward pass suffices.
+        for (i, step_deps) in deps.iter().enumerate() {
+            if i != index && step_deps.iter().any(|&d| affected[d]) {
+                affected[i] = true;
+            }
+        }
+        (0..self.steps.len())
+            .filter(|&i| i != index && affected[i])
+            .collect()
+    }
+
+    pub fn new(id: String, description: String, steps: Vec<PlanStep>) -> Self {
+        Self {
+            id,
+            description,
+            steps,
+            safety_checks: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SafetyCheck {
     pub check_type: String,
@@ -21,3 +249,146 @@ pub trait CommandPlanner {
         input: &str,
     ) -> impl std::future::Future<Output = Result<CommandPlan>> + Send;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(command: &str, depends_on: Option<Vec<usize>>, read_only: bool) -> PlanStep {
+        PlanStep {
+            depends_on,
+            read_only,
+            ..PlanStep::new(command)
+        }
+    }
+
+    fn plan(steps: Vec<PlanStep>) -> CommandPlan {
+        CommandPlan::new("id".into(), "task".into(), steps)
+    }
+
+    #[test]
+    fn test_looks_mutating() {
+        assert!(!looks_mutating("ls -la /var/log"));
+        assert!(!looks_mutating("grep foo bar 2>/dev/null"));
+        assert!(!looks_mutating("find . -name x > /dev/null 2>&1"));
+        assert!(looks_mutating("echo hi > file"));
+        assert!(looks_mutating("sudo apt update"));
+        assert!(looks_mutating("sed -i s/a/b/ file"));
+        assert!(looks_mutating("systemctl restart nginx"));
+    }
+
+    #[test]
+    fn test_read_only_pipelines() {
+        for command in [
+            "ps aux | grep nginx | head -5",
+            "df -h && du -sh /var/log/* | sort -h",
+            "cat /etc/os-release; uname -a",
+            "LC_ALL=C ls -l /usr/bin/ls",
+            "/usr/bin/find . -name '*.rs' -newer Cargo.toml",
+            "grep -c error < /var/log/syslog",
+            "git status --short && git log -5",
+            "systemctl status nginx",
+            "echo 'a > b'",
+        ] {
+            assert!(!looks_mutating(command), "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_unlisted_programs_are_mutating() {
+        for command in [
+            "wget https://example.com/file",
+            "curl -o out.html https://example.com",
+            "curl -O https://example.com/file",
+            "ln -s a b",
+            "git commit -m wip",
+            "git checkout main",
+            "git reset --hard",
+            "git push",
+            "cargo build",
+            "npm install",
+            "pip install requests",
+            "truncate -s 0 log",
+            "patch -p1 < fix.diff",
+            "unzip archive.zip",
+            "tar xf archive.tar",
+            "shred secret",
+            "ls | xargs rm",
+            "echo $(rm -rf x)",
+            "env rm x",
+        ] {
+            assert!(looks_mutating(command), "{}", command);
+        }
+    }
+
+    #[test]
+    fn test_writing_arguments_of_read_only_programs() {
+        assert!(looks_mutating("find . -name '*.tmp' -delete"));
+        assert!(looks_mutating("find . -exec chmod 644 {} ;"));
+        assert!(looks_mutating("sort -o sorted.txt input.txt"));
+        assert!(looks_mutating("sed -i.bak s/a/b/ file"));
+        assert!(looks_mutating("uniq input.txt output.txt"));
+        assert!(looks_mutating("date -s '2020-01-01'"));
+        assert!(looks_mutating("hostname web1"));
+        assert!(!looks_mutating("sed -n 1,5p file | uniq -c"));
+        assert!(looks_mutating("systemctl daemon-reload"));
+        assert!(looks_mutating("git"));
+    }
+
+    #[test]
+    fn test_markers_are_not_substrings() {
+        // These used to match "install", "remove" and "kill" inside other words.
+        assert!(!looks_mutating("grep -r install_dir ."));
+        assert!(!looks_mutating("cat removed.txt"));
+        assert!(!looks_mutating("pgrep -l skill"));
+        assert!(looks_mutating("pkill nginx"));
+    }
+
+    #[test]
+    fn test_read_only_claim_is_checked() {
+        assert!(step("df -h", None, true).runs_concurrently());
+        assert!(!step("df -h", None, false).runs_concurrently());
+        assert!(!step("rm -rf build", None, true).runs_concurrently());
+    }
+
+    #[test]
+    fn test_implicit_dependencies_are_sequential() {
+        let plan = plan(vec![step("a", None, false), step("b", None, false), step("c", None, false)]);
+        assert_eq!(plan.dependencies(), vec![vec![], vec![0], vec![1]]);
+    }
+
+    #[test]
+    fn test_mutating_steps_keep_their_order() {
+        let plan = plan(vec![
+            step("touch a", Some(vec![]), false),
+            step("ls", Some(vec![]), true),
+            step("touch b", Some(vec![]), false),
+        ]);
+        assert_eq!(plan.dependencies(), vec![vec![], vec![], vec![0]]);
+    }
+
+    #[test]
+    fn test_cyclic_references_are_ignored() {
+        // 1 waits for 2 and 2 waits for 1; only the backward edge survives.
+        let plan = plan(vec![
+            step("ls a", Some(vec![2]), true),
+            step("ls b", Some(vec![1]), true),
+            step("ls c", Some(vec![3, 0, 9]), true),
+        ]);
+        assert_eq!(plan.dependencies(), vec![vec![], vec![0], vec![]]);
+        assert_eq!(plan.ignored_dependencies(), vec![(0, 2), (2, 3), (2, 0), (2, 9)]);
+    }
+
+    #[test]
+    fn test_dependents_are_transitive() {
+        let plan = plan(vec![
+            step("ls a", Some(vec![]), true),
+            step("ls b", Some(vec![1]), true),
+            step("ls c", Some(vec![2]), true),
+            step("ls d", Some(vec![]), true),
+        ]);
+        assert_eq!(plan.dependents_of(0), vec![1, 2]);
+        assert_eq!(plan.dependents_of(3), Vec::<usize>::new());
+        assert_eq!(plan.dependents_of(9), Vec::<usize>::new());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::session::Message;
+use shared::types::Result;
+use std::future::Future;
+
+/// Sampling parameters for a single request; `None` keeps the backend default.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationOptions {
+    pub temperature: Option<f32>,
+    /// Context window in tokens, for backends that size it per request.
+    pub num_ctx: Option<u32>,
+}
+
+impl GenerationOptions {
+    pub fn with_temperature(temperature: f32) -> Self {
+        Self {
+            temperature: Some(temperature),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_num_ctx(num_ctx: u32) -> Self {
+        Self {
+            num_ctx: Some(num_ctx),
+            ..Self::default()
+        }
+    }
+}
+
+/// A chat model backend.
+pub trait LanguageModel: Clone + Send + Sync {
+    /// Name of the model answering requests.
+    fn model_name(&self) -> &str;
+
+    /// The same backend bound to a different model.
+    fn with_model(&self, model: &str) -> Self;
+
+    fn chat(
+        &self,
+        messages: &[Message],
+        options: &GenerationOptions,
+    ) -> impl Future<Output = Result<String>> + Send;
+
+    /// Like `chat`, but calls `on_token` with each piece of the answer as it
+    /// arrives. Returns the assembled answer.
+    fn stream<F>(
+        &self,
+        messages: &[Message],
+        options: &GenerationOptions,
+        on_token: F,
+    ) -> impl Future<Output = Result<String>> + Send
+    where
+        F: FnMut(&str) + Send;
+
+    /// Ask for JSON conforming to `schema` and return the parsed value.
+    fn structured(
+        &self,
+        messages: &[Message],
+        schema: &serde_json::Value,
+        options: &GenerationOptions,
+    ) -> impl Future<Output = Result<serde_json::Value>> + Send;
+
+    /// Single-turn convenience wrapper around `chat`.
+    fn complete(&self, prompt: &str) -> impl Future<Output = Result<String>> + Send {
+        let messages = vec![Message::user(prompt)];
+        async move { self.chat(&messages, &GenerationOptions::default()).await }
+    }
+}
+
+/// A text embedding backend.
+pub trait EmbeddingModel: Send + Sync {
+    /// Name of the model producing the vectors; stored with the index.
+    fn embedding_model_name(&self) -> &str;
+
+    fn embed(&self, text: &str) -> impl Future<Output = Result<Vec<f32>>> + Send;
+
+    /// Embed several texts, returning vectors in input order.
+    fn embed_batch(&self, texts: &[String]) -> impl Future<Output = Result<Vec<Vec<f32>>>> + Send {
+        async move {
+            let mut vectors = Vec::with_capacity(texts.len());
+            for text in texts {
+                vectors.push(self.embed(text).await?);
+            }
+            Ok(vectors)
+        }
+    }
+
+    /// Length of the vectors this model produces.
+    fn dimension(&self) -> impl Future<Output = Result<usize>> + Send;
+}
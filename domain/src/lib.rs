@@ -1,4 +1,9 @@
+pub mod agent_run;
 pub mod command_plan;
+pub mod language_model;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 pub mod models;
 pub mod safety_policy;
 pub mod session;
+pub mod usage;
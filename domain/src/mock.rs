@@ -0,0 +1,141 @@
+//! A scripted model backend for testing services without a server.
+
+use crate::language_model::{EmbeddingModel, GenerationOptions, LanguageModel};
+use crate::session::Message;
+use shared::types::Result;
+use std::sync::{Arc, Mutex};
+
+/// Builds the reply to a chat request from the model name and the messages.
+type Responder = dyn Fn(&str, &[Message]) -> Result<String> + Send + Sync;
+/// Model name and messages of a chat request.
+type Chat = (String, Vec<Message>);
+
+/// Length of the vectors `MockModel` produces.
+pub const MOCK_DIMENSION: usize = 32;
+
+/// A chat and embedding backend answering from a closure. Embeddings are
+/// bags of hashed words, so texts sharing words are similar. Every request
+/// is recorded, and clones share the record.
+#[derive(Clone)]
+pub struct MockModel {
+    name: String,
+    responder: Arc<Responder>,
+    chats: Arc<Mutex<Vec<Chat>>>,
+    embedded: Arc<Mutex<Vec<Vec<String>>>>,
+}
+
+impl MockModel {
+    /// A model that answers every request with `reply`.
+    pub fn new(reply: &str) -> Self {
+        let reply = reply.to_string();
+        Self::replying(move |_, _| Ok(reply.clone()))
+    }
+
+    /// A model that answers with `responder(model name, messages)`.
+    pub fn replying(
+        responder: impl Fn(&str, &[Message]) -> Result<String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: "mock".to_string(),
+            responder: Arc::new(responder),
+            chats: Arc::new(Mutex::new(Vec::new())),
+            embedded: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Model name and messages of every chat request so far.
+    pub fn chats(&self) -> Vec<Chat> {
+        self.chats.lock().map(|c| c.clone()).unwrap_or_default()
+    }
+
+    /// The texts of every embedding request so far, one entry per request.
+    pub fn embed_requests(&self) -> Vec<Vec<String>> {
+        self.embedded.lock().map(|e| e.clone()).unwrap_or_default()
+    }
+
+    fn respond(&self, messages: &[Message]) -> Result<String> {
+        if let Ok(mut chats) = self.chats.lock() {
+            chats.push((self.name.clone(), messages.to_vec()));
+        }
+        (self.responder)(&self.name, messages)
+    }
+
+    fn vector(text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; MOCK_DIMENSION];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|w| !w.is_empty())
+        {
+            // FNV-1a, so vectors are the same in every run.
+            let hash = word
+                .to_lowercase()
+                .bytes()
+                .fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+            vector[(hash % MOCK_DIMENSION as u64) as usize] += 1.0;
+        }
+        vector
+    }
+}
+
+impl LanguageModel for MockModel {
+    fn model_name(&self) -> &str {
+        &self.name
+    }
+
+    fn with_model(&self, model: &str) -> Self {
+        Self {
+            name: model.to_string(),
+            ..self.clone()
+        }
+    }
+
+    async fn chat(&self, messages: &[Message], _options: &GenerationOptions) -> Result<String> {
+        self.respond(messages)
+    }
+
+    async fn stream<F>(
+        &self,
+        messages: &[Message],
+        _options: &GenerationOptions,
+        mut on_token: F,
+    ) -> Result<String>
+    where
+        F: FnMut(&str) + Send,
+    {
+        let answer = self.respond(messages)?;
+        for piece in answer.split_inclusive(' ') {
+            on_token(piece);
+        }
+        Ok(answer)
+    }
+
+    async fn structured(
+        &self,
+        messages: &[Message],
+        _schema: &serde_json::Value,
+        _options: &GenerationOptions,
+    ) -> Result<serde_json::Value> {
+        Ok(serde_json::from_str(&self.respond(messages)?)?)
+    }
+}
+
+impl EmbeddingModel for MockModel {
+    fn embedding_model_name(&self) -> &str {
+        &self.name
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        Ok(self.embed_batch(&[text.to_string()]).await?.remove(0))
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if let Ok(mut embedded) = self.embedded.lock() {
+            embedded.push(texts.to_vec());
+        }
+        Ok(texts.iter().map(|t| Self::vector(t)).collect())
+    }
+
+    async fn dimension(&self) -> Result<usize> {
+        Ok(MOCK_DIMENSION)
+    }
+}
@@ -12,4 +12,51 @@ pub struct Embedding {
     pub vector: Vec<f32>,
     pub text: String,
     pub path: String,
+    /// First and last line of the chunk (1-based); 0 for synthetic chunks.
+    #[serde(default)]
+    pub start_line: usize,
+    #[serde(default)]
+    pub end_line: usize,
+    /// The function, type or heading the chunk starts in, when known.
+    #[serde(default)]
+    pub symbol: Option<String>,
+}
+
+/// A chunk retrieved for a question, with its similarity to the question.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub symbol: Option<String>,
+    pub text: String,
+    pub score: f32,
+}
+
+impl SearchHit {
+    pub fn from_embedding(embedding: &Embedding, score: f32) -> Self {
+        Self {
+            path: embedding.path.clone(),
+            start_line: embedding.start_line,
+            end_line: embedding.end_line,
+            symbol: embedding.symbol.clone(),
+            text: embedding.text.clone(),
+            score,
+        }
+    }
+
+    /// Whether the hit maps to real lines of a file and can be cited.
+    pub fn is_citable(&self) -> bool {
+        self.start_line > 0
+    }
+
+    /// `path:start-end`, the form used in citations.
+    pub fn location(&self) -> String {
+        let path = self.path.trim_start_matches("./");
+        if self.start_line == self.end_line {
+            format!("{}:{}", path, self.start_line)
+        } else {
+            format!("{}:{}-{}", path, self.start_line, self.end_line)
+        }
+    }
 }
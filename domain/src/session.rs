@@ -14,6 +14,23 @@ pub struct Message {
     pub content: String,
 }
 
+impl Message {
+    pub fn new(role: &str, content: &str) -> Self {
+        Self {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    pub fn system(content: &str) -> Self {
+        Self::new("system", content)
+    }
+
+    pub fn user(content: &str) -> Self {
+        Self::new("user", content)
+    }
+}
+
 impl Session {
     pub fn new(id: String) -> Self {
         Self {
@@ -24,9 +41,6 @@ impl Session {
     }
 
     pub fn add_message(&mut self, role: &str, content: &str) {
-        self.history.push(Message {
-            role: role.to_string(),
-            content: content.to_string(),
-        });
+        self.history.push(Message::new(role, content));
     }
 }
@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+
+/// What a model request was for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RequestKind {
+    Chat,
+    Embed,
+}
+
+/// Token counts and timings for one model request. Server-side fields are
+/// zero when the backend does not report them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Usage {
+    pub model: String,
+    pub kind: RequestKind,
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default)]
+    pub completion_tokens: u64,
+    /// Time the server spent on the request, in milliseconds.
+    #[serde(default)]
+    pub total_ms: u64,
+    /// Part of `total_ms` spent loading the model into memory.
+    #[serde(default)]
+    pub load_ms: u64,
+    /// Latency seen by the client, retries included.
+    pub wall_ms: u64,
+}
+
+/// Collects `Usage` from every client sharing it until drained.
+#[derive(Debug, Clone, Default)]
+pub struct UsageMeter {
+    records: Arc<Mutex<Vec<Usage>>>,
+}
+
+impl UsageMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, usage: Usage) {
+        if let Ok(mut records) = self.records.lock() {
+            records.push(usage);
+        }
+    }
+
+    /// Everything recorded since the last call.
+    pub fn take(&self) -> Vec<Usage> {
+        self.records
+            .lock()
+            .map(|mut records| std::mem::take(&mut *records))
+            .unwrap_or_default()
+    }
+}
+
+/// One entry of the local statistics log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum StatsEvent {
+    Request {
+        timestamp: u64,
+        mode: String,
+        usage: Usage,
+    },
+    Cache {
+        timestamp: u64,
+        mode: String,
+        hit: bool,
+    },
+}
+
+impl StatsEvent {
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            Self::Request { timestamp, .. } | Self::Cache { timestamp, .. } => *timestamp,
+        }
+    }
+
+    pub fn mode(&self) -> &str {
+        match self {
+            Self::Request { mode, .. } | Self::Cache { mode, .. } => mode,
+        }
+    }
+}
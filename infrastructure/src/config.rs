@@ -1,5 +1,7 @@
+use super::vector_codec::VectorFormat;
 use dotenvy::dotenv;
 use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::env;
 use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
@@ -47,28 +49,118 @@ fn project_cache_suffix() -> String {
     }
 }
 
+/// `name` inside the data directory, `~/.local/share/vibe_cli`.
+fn data_path(name: &str) -> String {
+    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    let mut path = PathBuf::from(home);
+    path.push(".local");
+    path.push("share");
+    path.push("vibe_cli");
+    path.push(name);
+    path.to_string_lossy().to_string()
+}
+
+/// Environment variables overriding `BASE_MODEL` (or `OPENAI_MODEL`) for a single mode.
+const MODE_MODEL_VARS: [(&str, &str); 5] = [
+    ("query", "QUERY_MODEL"),
+    ("chat", "CHAT_MODEL"),
+    ("agent", "AGENT_MODEL"),
+    ("explain", "EXPLAIN_MODEL"),
+    ("rag", "RAG_MODEL"),
+];
+
+/// Which kind of server answers model requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LlmBackend {
+    Ollama,
+    /// Any server speaking `/v1/chat/completions` (llama.cpp server, vLLM, LM Studio).
+    OpenAi,
+}
+
 #[derive(Clone)]
 pub struct Config {
+    pub llm_backend: LlmBackend,
     pub ollama_base_url: String,
+    pub openai_base_url: String,
+    pub openai_api_key: Option<String>,
+    pub connect_timeout_secs: u64,
+    /// Longest silence tolerated while waiting for response bytes.
+    pub read_timeout_secs: u64,
+    /// Retries for connection failures and transient HTTP statuses.
+    pub max_retries: u32,
     pub ollama_model: String,
+    /// Chat model name sent to an OpenAI-compatible server.
+    pub openai_model: String,
+    /// Larger models to retry on when the base model produces an unusable answer.
+    pub fallback_models: Vec<String>,
+    /// Per-mode replacements for the base model, keyed by mode name.
+    pub mode_models: HashMap<String, String>,
+    /// Model used to embed code chunks and questions for RAG.
+    pub embed_model: String,
     pub db_path: String,
+    /// How new vectors are stored in the embedding database.
+    pub vector_format: VectorFormat,
+    pub runs_dir: String,
+    pub stats_path: String,
+    pub vote_temperature: f32,
     pub rag_include_patterns: Vec<String>,
     pub rag_exclude_patterns: Vec<String>,
+    /// Retrieved chunks per RAG prompt.
+    pub rag_top_k: usize,
+    /// Relevance weight in maximal marginal relevance selection; 1.0 disables diversification.
+    pub rag_mmr_lambda: f32,
+    /// Most chunks per file in a RAG prompt; 0 for no limit.
+    pub rag_max_chunks_per_file: usize,
+    /// Cosine similarity below which vector-only matches are dropped.
+    pub rag_min_similarity: f32,
+    /// Whether the model rewrites questions into extra searches before retrieval.
+    pub rag_query_rewrite: bool,
+    /// Whether the model rescores retrieved chunks before selection.
+    pub rag_rerank: bool,
+    /// Best-ranked candidates the reranker scores.
+    pub rag_rerank_candidates: usize,
+    /// Context window requested from the model, in tokens.
+    pub num_ctx: u32,
+    /// Tokens of the context window kept free for a RAG answer.
+    pub rag_answer_tokens: usize,
 }
 
 impl Config {
+    /// The chat model of the configured backend.
+    pub fn base_model(&self) -> &str {
+        match self.llm_backend {
+            LlmBackend::Ollama => &self.ollama_model,
+            LlmBackend::OpenAi => &self.openai_model,
+        }
+    }
+
+    /// The primary model followed by the fallback chain, without duplicates.
+    pub fn model_chain(&self) -> Vec<String> {
+        let mut chain = vec![self.base_model().to_string()];
+        for model in &self.fallback_models {
+            if !chain.contains(model) {
+                chain.push(model.clone());
+            }
+        }
+        chain
+    }
+
+    /// The chat model for `mode` (query, chat, agent, explain or rag).
+    /// `--context` sessions are chats and use the chat model.
+    pub fn model_for(&self, mode: &str) -> &str {
+        let mode = if mode == "context" { "chat" } else { mode };
+        self.mode_models
+            .get(mode)
+            .map(String::as_str)
+            .unwrap_or(self.base_model())
+    }
+
     pub fn load() -> Self {
         dotenv().ok();
-        let db_path = env::var("DB_PATH").unwrap_or_else(|_| {
-            let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
-            let mut path = PathBuf::from(home);
-            path.push(".local");
-            path.push("share");
-            path.push("vibe_cli");
-            let suffix = project_cache_suffix();
-            path.push(format!("{}_embeddings.db", suffix));
-            path.to_string_lossy().to_string()
-        });
+        let db_path = env::var("DB_PATH")
+            .unwrap_or_else(|_| data_path(&format!("{}_embeddings.db", project_cache_suffix())));
+        let runs_dir = env::var("RUNS_DIR").unwrap_or_else(|_| data_path("runs"));
+        let stats_path = env::var("STATS_PATH").unwrap_or_else(|_| data_path("stats.jsonl"));
 
         // Default include patterns for common code files
         let rag_include_patterns = env::var("RAG_INCLUDE_PATTERNS")
@@ -79,19 +171,120 @@ impl Config {
 
         // Default exclude patterns for build artifacts and common irrelevant files
         let rag_exclude_patterns = env::var("RAG_EXCLUDE_PATTERNS")
-            .unwrap_or_else(|_| "target/**,node_modules/**,*.lock,Cargo.lock,.git/**,__pycache__/**,*.pyc,dist/**,build/**,.next/**,.cache/**".to_string())
+            .unwrap_or_else(|_| "target/**,node_modules/**,*.lock,Cargo.lock,.git/**,__pycache__/**,*.pyc,dist/**,build/**,.next/**,.cache/**,.vibe_cli/**".to_string())
             .split(',')
             .map(|s| s.trim().to_string())
             .collect();
 
+        let mode_models = MODE_MODEL_VARS
+            .iter()
+            .filter_map(|(mode, var)| {
+                let model = env::var(var).ok()?.trim().to_string();
+                (!model.is_empty()).then(|| (mode.to_string(), model))
+            })
+            .collect();
+
+        let llm_backend = match env::var("LLM_BACKEND")
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase()
+            .as_str()
+        {
+            "openai" | "openai-compatible" => LlmBackend::OpenAi,
+            _ => LlmBackend::Ollama,
+        };
+
         Self {
+            llm_backend,
             ollama_base_url: env::var("OLLAMA_BASE_URL")
                 .unwrap_or_else(|_| "http://localhost:11434".to_string()),
+            openai_base_url: env::var("OPENAI_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:8080/v1".to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            openai_api_key: env::var("OPENAI_API_KEY").ok().filter(|k| !k.trim().is_empty()),
+            connect_timeout_secs: env::var("HTTP_CONNECT_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5),
+            read_timeout_secs: env::var("HTTP_READ_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(300),
+            max_retries: env::var("HTTP_MAX_RETRIES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(3),
             ollama_model: env::var("BASE_MODEL")
                 .unwrap_or_else(|_| "qwen2.5:1.5b-instruct".to_string()),
+            openai_model: env::var("OPENAI_MODEL")
+                .ok()
+                .map(|m| m.trim().to_string())
+                .filter(|m| !m.is_empty())
+                .unwrap_or_else(|| "qwen2.5-1.5b-instruct".to_string()),
+            fallback_models: env::var("FALLBACK_MODELS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            mode_models,
+            embed_model: env::var("EMBED_MODEL")
+                .ok()
+                .map(|m| m.trim().to_string())
+                .filter(|m| !m.is_empty())
+                .unwrap_or_else(|| "nomic-embed-text".to_string()),
             db_path,
+            vector_format: env::var("VECTOR_FORMAT")
+                .ok()
+                .and_then(|v| VectorFormat::parse(&v))
+                .unwrap_or(VectorFormat::F32),
+            runs_dir,
+            stats_path,
+            vote_temperature: env::var("VOTE_TEMPERATURE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0.8),
             rag_include_patterns,
             rag_exclude_patterns,
+            rag_top_k: env::var("RAG_TOP_K")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|k| *k > 0)
+                .unwrap_or(50),
+            rag_mmr_lambda: env::var("RAG_MMR_LAMBDA")
+                .ok()
+                .and_then(|v| v.parse::<f32>().ok())
+                .map(|l| l.clamp(0.0, 1.0))
+                .unwrap_or(0.7),
+            rag_max_chunks_per_file: env::var("RAG_MAX_CHUNKS_PER_FILE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(4),
+            rag_min_similarity: env::var("RAG_MIN_SIMILARITY")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0.25),
+            rag_query_rewrite: env::var("RAG_QUERY_REWRITE")
+                .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+                .unwrap_or(false),
+            rag_rerank: env::var("RAG_RERANK")
+                .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+                .unwrap_or(false),
+            rag_rerank_candidates: env::var("RAG_RERANK_CANDIDATES")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(40),
+            num_ctx: env::var("NUM_CTX")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(4096),
+            rag_answer_tokens: env::var("RAG_ANSWER_TOKENS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1024),
         }
     }
 }
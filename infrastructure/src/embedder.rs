@@ -1,10 +1,34 @@
-use super::ollama_client::OllamaClient;
+use domain::language_model::EmbeddingModel;
 use domain::models::Embedding;
-use futures::stream::{self, StreamExt};
 use shared::types::Result;
 
-pub struct Embedder {
-    client: OllamaClient,
+/// Most chunks sent in one embedding request.
+const MAX_BATCH_ITEMS: usize = 64;
+/// Most text sent in one embedding request, in bytes; long chunks make for smaller batches.
+const MAX_BATCH_BYTES: usize = 64 * 1024;
+
+/// Split `inputs` into consecutive batches within both limits.
+fn plan_batches(inputs: &[EmbeddingInput]) -> Vec<&[EmbeddingInput]> {
+    let mut batches = Vec::new();
+    let mut start = 0;
+    let mut bytes = 0;
+    for (i, input) in inputs.iter().enumerate() {
+        let full = i - start >= MAX_BATCH_ITEMS || bytes + input.text.len() > MAX_BATCH_BYTES;
+        if full && i > start {
+            batches.push(&inputs[start..i]);
+            start = i;
+            bytes = 0;
+        }
+        bytes += input.text.len();
+    }
+    if start < inputs.len() {
+        batches.push(&inputs[start..]);
+    }
+    batches
+}
+
+pub struct Embedder<E: EmbeddingModel> {
+    model: E,
 }
 
 #[derive(Clone)]
@@ -12,18 +36,45 @@ pub struct EmbeddingInput {
     pub id: String,
     pub path: String,
     pub text: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub symbol: Option<String>,
 }
 
-impl Embedder {
-    pub fn new(client: OllamaClient) -> Self {
-        Self { client }
+impl<E: EmbeddingModel> Embedder<E> {
+    pub fn new(model: E) -> Self {
+        Self { model }
+    }
+
+    pub fn model_name(&self) -> &str {
+        self.model.embedding_model_name()
+    }
+
+    pub async fn dimension(&self) -> Result<usize> {
+        self.model.dimension().await
+    }
+
+    pub async fn embed_query(&self, text: &str) -> Result<Vec<f32>> {
+        self.model.embed(text).await
+    }
+
+    /// Embed several search texts in one request, in input order.
+    pub async fn embed_queries(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let vectors = self.model.embed_batch(texts).await?;
+        if vectors.len() != texts.len() {
+            return Err(anyhow::anyhow!(
+                "Embedding backend returned {} vectors for {} inputs",
+                vectors.len(),
+                texts.len()
+            ));
+        }
+        Ok(vectors)
     }
 
     pub async fn generate_embeddings(&self, inputs: &[EmbeddingInput]) -> Result<Vec<Embedding>> {
-        const BATCH_SIZE: usize = 32;
         let mut embeddings = Vec::with_capacity(inputs.len());
 
-        for chunk in inputs.chunks(BATCH_SIZE) {
+        for chunk in plan_batches(inputs) {
             eprintln!("Generating embeddings for {} chunks...", chunk.len());
             let batch_embeddings = self.generate_batch_embeddings(chunk).await?;
             embeddings.extend(batch_embeddings);
@@ -32,27 +83,121 @@ impl Embedder {
     }
 
     async fn generate_batch_embeddings(&self, inputs: &[EmbeddingInput]) -> Result<Vec<Embedding>> {
-        let futures: Vec<_> = inputs
+        let texts: Vec<String> = inputs.iter().map(|input| input.text.clone()).collect();
+        let vectors = self.model.embed_batch(&texts).await?;
+        if vectors.len() != inputs.len() {
+            return Err(anyhow::anyhow!(
+                "Embedding backend returned {} vectors for {} inputs",
+                vectors.len(),
+                inputs.len()
+            ));
+        }
+        Ok(inputs
             .iter()
-            .map(|input| {
-                let client = &self.client;
-                async move {
-                    let vector = client.generate_embedding(&input.text).await?;
-                    Ok(Embedding {
-                        id: input.id.clone(),
-                        vector,
-                        text: input.text.clone(),
-                        path: input.path.clone(),
-                    }) as Result<Embedding>
-                }
+            .zip(vectors)
+            .map(|(input, vector)| Embedding {
+                id: input.id.clone(),
+                vector,
+                text: input.text.clone(),
+                path: input.path.clone(),
+                start_line: input.start_line,
+                end_line: input.end_line,
+                symbol: input.symbol.clone(),
             })
-            .collect();
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain::mock::{MockModel, MOCK_DIMENSION};
 
-        let results = stream::iter(futures)
-            .buffer_unordered(8)
-            .collect::<Vec<_>>()
-            .await;
+    fn input(i: usize, text: &str) -> EmbeddingInput {
+        EmbeddingInput {
+            id: format!("src/lib.rs:{}", i),
+            path: "src/lib.rs".to_string(),
+            text: text.to_string(),
+            start_line: i + 1,
+            end_line: i + 2,
+            symbol: (i == 0).then(|| "fn main".to_string()),
+        }
+    }
+
+    /// A backend that loses the last vector of every batch.
+    struct ShortBackend;
+
+    impl EmbeddingModel for ShortBackend {
+        fn embedding_model_name(&self) -> &str {
+            "short"
+        }
+
+        async fn embed(&self, _text: &str) -> Result<Vec<f32>> {
+            Ok(vec![1.0])
+        }
+
+        async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(vec![vec![1.0]; texts.len().saturating_sub(1)])
+        }
+
+        async fn dimension(&self) -> Result<usize> {
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_batches_respect_item_and_byte_limits() {
+        let many: Vec<EmbeddingInput> = (0..130).map(|i| input(i, "x")).collect();
+        let sizes: Vec<usize> = plan_batches(&many).iter().map(|b| b.len()).collect();
+        assert_eq!(sizes, vec![64, 64, 2]);
+
+        let big = "y".repeat(MAX_BATCH_BYTES / 2 + 1);
+        let large: Vec<EmbeddingInput> = (0..3).map(|i| input(i, &big)).collect();
+        let sizes: Vec<usize> = plan_batches(&large).iter().map(|b| b.len()).collect();
+        assert_eq!(sizes, vec![1, 1, 1]);
+
+        // A chunk over the byte limit still gets a batch of its own.
+        let huge = vec![input(0, &"z".repeat(MAX_BATCH_BYTES * 2))];
+        assert_eq!(plan_batches(&huge).len(), 1);
+        assert!(plan_batches(&[]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_generate_embeddings_keeps_order_and_metadata() {
+        let model = MockModel::new("");
+        let embedder = Embedder::new(model.clone());
+        let inputs: Vec<EmbeddingInput> = (0..70).map(|i| input(i, &format!("chunk {}", i))).collect();
+        let embeddings = embedder.generate_embeddings(&inputs).await.unwrap();
+
+        assert_eq!(embeddings.len(), 70);
+        for (embedding, input) in embeddings.iter().zip(&inputs) {
+            assert_eq!(embedding.id, input.id);
+            assert_eq!(embedding.text, input.text);
+            assert_eq!(embedding.start_line, input.start_line);
+            assert_eq!(embedding.vector.len(), MOCK_DIMENSION);
+        }
+        assert_eq!(embeddings[0].symbol.as_deref(), Some("fn main"));
+        let requests: Vec<usize> = model.embed_requests().iter().map(|r| r.len()).collect();
+        assert_eq!(requests, vec![64, 6]);
+    }
+
+    #[tokio::test]
+    async fn test_embed_queries_is_one_request() {
+        let model = MockModel::new("");
+        let embedder = Embedder::new(model.clone());
+        let texts = vec!["parse config".to_string(), "load_config".to_string()];
+        let vectors = embedder.embed_queries(&texts).await.unwrap();
+        assert_eq!(vectors.len(), 2);
+        assert_eq!(model.embed_requests(), vec![texts]);
+        assert_eq!(embedder.dimension().await.unwrap(), MOCK_DIMENSION);
+    }
 
-        results.into_iter().collect()
+    #[tokio::test]
+    async fn test_missing_vectors_are_an_error() {
+        let embedder = Embedder::new(ShortBackend);
+        let inputs = vec![input(0, "a"), input(1, "b")];
+        let err = embedder.generate_embeddings(&inputs).await.unwrap_err();
+        assert!(err.to_string().contains("returned 1 vectors for 2 inputs"));
+        assert!(embedder.embed_queries(&["q".to_string()]).await.is_err());
     }
 }
@@ -1,31 +1,150 @@
+use super::vector_codec::{self, VectorFormat};
+use super::vector_index::VectorIndex;
 use domain::models::Embedding;
-use rusqlite::{params, Connection, Result as SqlResult};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Result as SqlResult};
 use shared::types::Result;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::task;
 
+/// Version of the table layout created by `setup_db`, kept in `PRAGMA user_version`.
+pub const SCHEMA_VERSION: i64 = 5;
+
 pub struct EmbeddingStorage {
     conn: Arc<Mutex<Connection>>,
+    /// Nearest-neighbour index over the stored vectors, saved at `index_path`.
+    /// Copied on write, so snapshots handed out stay valid while it changes.
+    index: Arc<Mutex<Arc<VectorIndex>>>,
+    index_path: PathBuf,
+    /// Bumped whenever stored vectors change; see `generation`.
+    generation: Arc<AtomicU64>,
+    /// Encoding of newly written vectors.
+    format: VectorFormat,
+}
+
+/// Words too common in questions to help a keyword search.
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "can", "do", "does", "for", "from", "how",
+    "i", "in", "is", "it", "its", "me", "my", "of", "on", "or", "the", "this", "that", "there",
+    "to", "what", "when", "where", "which", "who", "why", "with", "you",
+];
+
+/// An FTS5 query matching any meaningful word of `question`, or `None` when
+/// it has none. Each word is quoted, so identifiers such as `upsert_file_hash`
+/// become phrases of their parts and FTS5 operators in the question are inert.
+pub fn keyword_query(question: &str) -> Option<String> {
+    let mut words: Vec<String> = Vec::new();
+    for word in question.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        let word = word.trim_matches('_').to_lowercase();
+        if word.chars().count() < 2 || STOPWORDS.contains(&word.as_str()) || words.contains(&word) {
+            continue;
+        }
+        words.push(word);
+    }
+    if words.is_empty() {
+        return None;
+    }
+    Some(
+        words
+            .iter()
+            .map(|word| format!("\"{}\"", word))
+            .collect::<Vec<_>>()
+            .join(" OR "),
+    )
+}
+
+/// Which embedding model produced the stored vectors. Vectors from different
+/// models (or of different lengths) cannot be compared with each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexInfo {
+    pub model: String,
+    pub dimension: usize,
+}
+
+/// The state of an index database, as checked by `vibe_cli doctor`.
+#[derive(Debug, Clone)]
+pub struct DbHealth {
+    /// Problems reported by `PRAGMA integrity_check`; empty when the file is sound.
+    pub integrity_errors: Vec<String>,
+    pub schema_version: i64,
+    pub embeddings: usize,
+    /// Rows in the keyword index, or `None` when the database predates it.
+    pub keyword_rows: Option<usize>,
+    pub files: usize,
+    pub index_info: Option<IndexInfo>,
+    /// Number of stored vectors of each length.
+    pub dimensions: Vec<(usize, usize)>,
+    /// Number of stored vectors in each format.
+    pub formats: Vec<(VectorFormat, usize)>,
+    /// Vectors whose bytes are not in a known format.
+    pub malformed_vectors: usize,
 }
 
 impl EmbeddingStorage {
-    pub async fn new(db_path: impl AsRef<Path>) -> Result<Self> {
+    pub async fn new(db_path: impl AsRef<Path>, format: VectorFormat) -> Result<Self> {
         let db_path = db_path.as_ref().to_path_buf();
-        let conn = task::spawn_blocking(move || -> Result<Connection> {
+        let index_path = Self::index_path(&db_path);
+        let load_path = index_path.clone();
+        let (conn, index) = task::spawn_blocking(move || -> Result<(Connection, VectorIndex)> {
             if let Some(parent) = db_path.parent() {
                 std::fs::create_dir_all(parent)?;
             }
             let conn = Connection::open(&db_path)?;
-            Self::setup_db(&conn)?;
-            Ok(conn)
+            Self::setup_db(&conn, format)?;
+            let index = Self::load_index(&conn, &load_path)?;
+            Ok((conn, index))
         }).await??;
-        Ok(Self { conn: Arc::new(Mutex::new(conn)) })
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            index: Arc::new(Mutex::new(Arc::new(index))),
+            index_path,
+            generation: Arc::new(AtomicU64::new(0)),
+            format,
+        })
+    }
+
+    /// Where the vector index of the database at `db_path` is kept.
+    pub fn index_path(db_path: impl AsRef<Path>) -> PathBuf {
+        db_path.as_ref().with_extension("ivf")
     }
 
-    fn setup_db(conn: &Connection) -> SqlResult<()> {
+    /// The saved index, rebuilt from the database when it is missing or
+    /// out of step with it (after a crash between a delete and the next save).
+    fn load_index(conn: &Connection, index_path: &Path) -> Result<VectorIndex> {
+        let rows: i64 = conn.query_row("SELECT COUNT(*) FROM embeddings", [], |row| row.get(0))?;
+        if let Some(index) = VectorIndex::load(index_path) {
+            if index.len() == rows as usize {
+                return Ok(index);
+            }
+        }
+        if rows > 0 {
+            eprintln!("Building vector index for {} chunks...", rows);
+        }
+        let mut index = VectorIndex::default();
+        let mut stmt = conn.prepare("SELECT id, path, vector FROM embeddings")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let id: String = row.get(0)?;
+            let path: String = row.get(1)?;
+            let vector_bytes: Vec<u8> = row.get(2)?;
+            // Rows that do not decode or that the index refuses are left to
+            // `doctor`, which reports them.
+            let inserted = vector_codec::decode(&vector_bytes)
+                .and_then(|vector| index.insert(&id, &path, &vector));
+            if let Err(e) = inserted {
+                eprintln!("Skipping chunk {}: {}", id, e);
+            }
+        }
+        index.maybe_retrain();
+        index.save(index_path)?;
+        Ok(index)
+    }
+
+    fn setup_db(conn: &Connection, format: VectorFormat) -> Result<()> {
+        let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
         conn.execute_batch(
             "
             PRAGMA journal_mode=WAL;
@@ -36,26 +155,48 @@ impl EmbeddingStorage {
                 id TEXT PRIMARY KEY,
                 vector BLOB NOT NULL,
                 text TEXT NOT NULL,
-                path TEXT NOT NULL DEFAULT ''
+                path TEXT NOT NULL DEFAULT '',
+                start_line INTEGER NOT NULL DEFAULT 0,
+                end_line INTEGER NOT NULL DEFAULT 0,
+                symbol TEXT
             );
-            CREATE INDEX IF NOT EXISTS idx_embeddings_vector ON embeddings(vector);
+            DROP INDEX IF EXISTS idx_embeddings_vector;
             CREATE TABLE IF NOT EXISTS file_meta (
                 path TEXT PRIMARY KEY,
                 hash TEXT NOT NULL
             );
+            CREATE TABLE IF NOT EXISTS index_meta (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );
         ",
         )?;
+        // Chunk text for BM25 keyword search. Rows share their rowid with
+        // `embeddings`, which is how the two tables are kept in step.
+        let has_fts: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'embeddings_fts')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_fts {
+            conn.execute_batch(
+                "
+                CREATE VIRTUAL TABLE embeddings_fts USING fts5(text);
+                INSERT INTO embeddings_fts (rowid, text) SELECT rowid, text FROM embeddings;
+            ",
+            )?;
+        }
         // Backfill missing path column for existing DBs.
         let mut stmt = conn.prepare("PRAGMA table_info(embeddings)")?;
         let mut rows = stmt.query([])?;
-        let mut has_path = false;
+        let mut columns = Vec::new();
         while let Some(row) = rows.next()? {
             let col_name: String = row.get(1)?;
-            if col_name == "path" {
-                has_path = true;
-                break;
-            }
+            columns.push(col_name);
         }
+        drop(rows);
+        drop(stmt);
+        let has_path = columns.iter().any(|c| c == "path");
         if !has_path {
             conn.execute(
                 "ALTER TABLE embeddings ADD COLUMN path TEXT NOT NULL DEFAULT ''",
@@ -67,60 +208,272 @@ impl EmbeddingStorage {
             "CREATE INDEX IF NOT EXISTS idx_embeddings_path ON embeddings(path)",
             [],
         )?;
+        // Version 2 added line ranges. Older rows cannot be cited, so drop them
+        // together with the file hashes to have every file chunked again.
+        if !columns.iter().any(|c| c == "start_line") {
+            conn.execute_batch(
+                "
+                ALTER TABLE embeddings ADD COLUMN start_line INTEGER NOT NULL DEFAULT 0;
+                ALTER TABLE embeddings ADD COLUMN end_line INTEGER NOT NULL DEFAULT 0;
+                ALTER TABLE embeddings ADD COLUMN symbol TEXT;
+            ",
+            )?;
+            let stale: i64 = conn.query_row("SELECT COUNT(*) FROM embeddings", [], |row| row.get(0))?;
+            if stale > 0 {
+                eprintln!("Embedding index predates line-aware chunks; rebuilding it...");
+                conn.execute_batch(
+                    "DELETE FROM embeddings; DELETE FROM embeddings_fts; DELETE FROM file_meta;",
+                )?;
+            }
+        }
+        if version < 5 {
+            Self::convert_vectors(conn, format)?;
+        }
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        Ok(())
+    }
+
+    /// Version 5 replaced bincode `Vec<f32>` blobs with `vector_codec`
+    /// blobs of unit vectors. Rewrite older rows in place.
+    fn convert_vectors(conn: &Connection, format: VectorFormat) -> Result<()> {
+        let tx = conn.unchecked_transaction()?;
+        let rows: Vec<(i64, Vec<u8>)> = tx
+            .prepare("SELECT rowid, vector FROM embeddings")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqlResult<_>>()?;
+        if rows.is_empty() {
+            return Ok(());
+        }
+        eprintln!("Converting {} stored vectors to the {} format...", rows.len(), format.name());
+        {
+            let mut update = tx.prepare("UPDATE embeddings SET vector = ?1 WHERE rowid = ?2")?;
+            for (rowid, bytes) in rows {
+                let vector: Vec<f32> = bincode::deserialize(&bytes)?;
+                let encoded = vector_codec::encode(&vector_codec::normalized(&vector), format);
+                update.execute(params![encoded, rowid])?;
+            }
+        }
+        tx.commit()?;
         Ok(())
     }
 
+    /// Inspect the database at `db_path` without creating or migrating it.
+    pub fn check_health(db_path: impl AsRef<Path>) -> Result<DbHealth> {
+        let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let integrity_errors: Vec<String> = conn
+            .prepare("PRAGMA integrity_check")?
+            .query_map([], |row| row.get(0))?
+            .collect::<SqlResult<Vec<String>>>()?
+            .into_iter()
+            .filter(|line| line != "ok")
+            .collect();
+        let schema_version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        let embeddings: i64 = conn.query_row("SELECT COUNT(*) FROM embeddings", [], |row| row.get(0))?;
+        let files: i64 = conn.query_row("SELECT COUNT(*) FROM file_meta", [], |row| row.get(0))?;
+        let keyword_rows = conn
+            .query_row("SELECT COUNT(*) FROM embeddings_fts", [], |row| row.get::<_, i64>(0))
+            .ok()
+            .map(|rows| rows as usize);
+
+        let meta = |key: &str| -> SqlResult<Option<String>> {
+            conn.query_row("SELECT value FROM index_meta WHERE key = ?1", [key], |row| row.get(0))
+                .optional()
+        };
+        // Databases from before index metadata existed have no such table.
+        let index_info = match (meta("embed_model"), meta("dimension")) {
+            (Ok(Some(model)), Ok(Some(dimension))) => dimension
+                .parse()
+                .ok()
+                .map(|dimension| IndexInfo { model, dimension }),
+            _ => None,
+        };
+
+        let mut dimensions: Vec<(usize, usize)> = Vec::new();
+        let mut formats: Vec<(VectorFormat, usize)> = Vec::new();
+        let mut malformed_vectors = 0;
+        let mut stmt = conn.prepare(
+            "SELECT substr(vector, 1, 2), length(vector), COUNT(*) FROM embeddings GROUP BY 1, 2",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let header: Vec<u8> = row.get(0)?;
+            let bytes: i64 = row.get(1)?;
+            let count = row.get::<_, i64>(2)? as usize;
+            // Before version 5 a vector was a bincode `Vec<f32>`: an 8-byte
+            // length followed by 4 bytes per value.
+            let described = if schema_version < 5 {
+                (bytes >= 8 && (bytes - 8) % 4 == 0)
+                    .then(|| (VectorFormat::F32, (bytes as usize - 8) / 4))
+            } else {
+                vector_codec::describe(&header, bytes as usize)
+            };
+            let Some((format, dimension)) = described else {
+                malformed_vectors += count;
+                continue;
+            };
+            match dimensions.iter_mut().find(|(d, _)| *d == dimension) {
+                Some((_, n)) => *n += count,
+                None => dimensions.push((dimension, count)),
+            }
+            match formats.iter_mut().find(|(f, _)| *f == format) {
+                Some((_, n)) => *n += count,
+                None => formats.push((format, count)),
+            }
+        }
+        dimensions.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        formats.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+        Ok(DbHealth {
+            integrity_errors,
+            schema_version,
+            embeddings: embeddings as usize,
+            keyword_rows,
+            files: files as usize,
+            index_info,
+            dimensions,
+            formats,
+            malformed_vectors,
+        })
+    }
+
     pub async fn insert_embeddings(&self, embeddings: Vec<Embedding>) -> Result<()> {
         let conn = Arc::clone(&self.conn);
+        let index = Arc::clone(&self.index);
+        let index_path = self.index_path.clone();
+        let generation = Arc::clone(&self.generation);
+        let format = self.format;
         task::spawn_blocking(move || -> Result<()> {
             let conn = conn.blocking_lock();
+            let mut guard = index.blocking_lock();
+            // Checked before writing, so the table never holds rows the index refused.
+            let dimension = guard
+                .dimension()
+                .or_else(|| embeddings.first().map(|e| e.vector.len()));
+            if let Some(bad) = embeddings
+                .iter()
+                .find(|e| e.vector.is_empty() || Some(e.vector.len()) != dimension)
+            {
+                return Err(anyhow::anyhow!(
+                    "Embedding for {} has {} dimensions; expected {}",
+                    bad.id,
+                    bad.vector.len(),
+                    dimension.unwrap_or(0)
+                ));
+            }
             let tx = conn.unchecked_transaction()?;
             {
                 let mut stmt = tx.prepare(
-                    "INSERT OR REPLACE INTO embeddings (id, vector, text, path) VALUES (?, ?, ?, ?)",
+                    "INSERT OR REPLACE INTO embeddings (id, vector, text, path, start_line, end_line, symbol) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )?;
+                // A replaced row gets a new rowid, so its old text is removed first.
+                let mut fts_delete = tx.prepare(
+                    "DELETE FROM embeddings_fts WHERE rowid = (SELECT rowid FROM embeddings WHERE id = ?1)",
                 )?;
+                let mut fts_insert =
+                    tx.prepare("INSERT INTO embeddings_fts (rowid, text) VALUES (?1, ?2)")?;
                 for embedding in &embeddings {
-                    let vector_bytes = bincode::serialize(&embedding.vector)?;
+                    let vector_bytes =
+                        vector_codec::encode(&vector_codec::normalized(&embedding.vector), format);
+                    fts_delete.execute(params![&embedding.id])?;
                     stmt.execute(params![
                         &embedding.id,
                         vector_bytes,
                         &embedding.text,
-                        &embedding.path
+                        &embedding.path,
+                        embedding.start_line as i64,
+                        embedding.end_line as i64,
+                        &embedding.symbol
                     ])?;
+                    fts_insert.execute(params![tx.last_insert_rowid(), &embedding.text])?;
                 }
             }
             tx.commit()?;
+
+            let index = Arc::make_mut(&mut guard);
+            for embedding in &embeddings {
+                index.insert(&embedding.id, &embedding.path, &embedding.vector)?;
+            }
+            index.maybe_retrain();
+            generation.fetch_add(1, Ordering::SeqCst);
+            index.save(&index_path)?;
             Ok(())
-        }).await?;
+        }).await??;
         eprintln!("Embeddings stored successfully");
         Ok(())
     }
 
-    pub async fn get_all_embeddings(&self) -> Result<Vec<Embedding>> {
+    /// The vector index as it is now. Later changes do not affect it; compare
+    /// `generation` to tell when it has gone stale.
+    pub async fn snapshot(&self) -> Arc<VectorIndex> {
+        Arc::clone(&*self.index.lock().await)
+    }
+
+    /// Counter bumped by every change to the stored vectors.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// The chunks with the given ids, in the same order, with unit-length
+    /// vectors. Ids that are no longer stored are skipped.
+    pub async fn get_embeddings(&self, ids: &[String]) -> Result<Vec<Embedding>> {
         let conn = Arc::clone(&self.conn);
+        let ids = ids.to_vec();
         task::spawn_blocking(move || {
             let conn = conn.blocking_lock();
-            let mut stmt = conn
-                .prepare("SELECT id, vector, text, path FROM embeddings")?;
-            let mut rows = stmt.query([])?;
-            let mut embeddings = Vec::new();
-            while let Some(row) = rows.next()? {
-                let id: String = row.get(0)?;
-                let vector_bytes: Vec<u8> = row.get(1)?;
-                let text: String = row.get(2)?;
-                let path: String = row.get(3)?;
-                let vector: Vec<f32> = bincode::deserialize(&vector_bytes)?;
+            let mut stmt = conn.prepare(
+                "SELECT vector, text, path, start_line, end_line, symbol FROM embeddings WHERE id = ?1",
+            )?;
+            let mut embeddings = Vec::with_capacity(ids.len());
+            for id in ids {
+                let row = stmt
+                    .query_row([&id], |row| {
+                        Ok((
+                            row.get::<_, Vec<u8>>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get::<_, i64>(3)?,
+                            row.get::<_, i64>(4)?,
+                            row.get::<_, Option<String>>(5)?,
+                        ))
+                    })
+                    .optional()?;
+                let Some((vector_bytes, text, path, start_line, end_line, symbol)) = row else {
+                    continue;
+                };
                 embeddings.push(Embedding {
                     id,
-                    vector,
+                    vector: vector_codec::decode(&vector_bytes)?,
                     text,
                     path,
+                    start_line: start_line as usize,
+                    end_line: end_line as usize,
+                    symbol,
                 });
             }
             Ok(embeddings)
         }).await?
     }
 
+    /// Ids of the chunks that best match the words of `question` by BM25,
+    /// best first, at most `limit` of them.
+    pub async fn keyword_search(&self, question: &str, limit: usize) -> Result<Vec<String>> {
+        let Some(query) = keyword_query(question) else {
+            return Ok(Vec::new());
+        };
+        let conn = Arc::clone(&self.conn);
+        task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            let mut stmt = conn.prepare(
+                "SELECT e.id FROM embeddings_fts JOIN embeddings e ON e.rowid = embeddings_fts.rowid
+                 WHERE embeddings_fts MATCH ?1 ORDER BY bm25(embeddings_fts) LIMIT ?2",
+            )?;
+            let ids = stmt
+                .query_map(params![query, limit as i64], |row| row.get(0))?
+                .collect::<SqlResult<Vec<String>>>()?;
+            Ok(ids)
+        }).await?
+    }
+
     pub async fn get_file_hash(&self, path: String) -> Result<Option<String>> {
         let conn = Arc::clone(&self.conn);
         task::spawn_blocking(move || {
@@ -148,12 +501,192 @@ impl EmbeddingStorage {
         }).await?
     }
 
+    /// Remove every chunk of the file at `path`. The vector index is updated
+    /// in memory and saved by the next `insert_embeddings`, which always
+    /// follows when a file is re-indexed.
     pub async fn delete_embeddings_for_path(&self, path: String) -> Result<()> {
         let conn = Arc::clone(&self.conn);
+        let index = Arc::clone(&self.index);
+        let generation = Arc::clone(&self.generation);
         task::spawn_blocking(move || {
             let conn = conn.blocking_lock();
-            conn.execute("DELETE FROM embeddings WHERE path = ?1", params![path])?;
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(
+                "DELETE FROM embeddings_fts WHERE rowid IN (SELECT rowid FROM embeddings WHERE path = ?1)",
+                params![path],
+            )?;
+            tx.execute("DELETE FROM embeddings WHERE path = ?1", params![path])?;
+            tx.commit()?;
+            Arc::make_mut(&mut index.blocking_lock()).remove_path(&path);
+            generation.fetch_add(1, Ordering::SeqCst);
             Ok(())
         }).await?
     }
+
+    /// The model and dimension recorded for the stored vectors, if any.
+    pub async fn get_index_info(&self) -> Result<Option<IndexInfo>> {
+        let conn = Arc::clone(&self.conn);
+        task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            let mut stmt = conn.prepare("SELECT value FROM index_meta WHERE key = ?1")?;
+            let model: Option<String> = stmt
+                .query_row(["embed_model"], |row| row.get(0))
+                .optional()?;
+            let dimension: Option<String> = stmt
+                .query_row(["dimension"], |row| row.get(0))
+                .optional()?;
+            match (model, dimension.and_then(|d| d.parse().ok())) {
+                (Some(model), Some(dimension)) => Ok(Some(IndexInfo { model, dimension })),
+                _ => Ok(None),
+            }
+        }).await?
+    }
+
+    pub async fn set_index_info(&self, info: IndexInfo) -> Result<()> {
+        let conn = Arc::clone(&self.conn);
+        task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            let tx = conn.unchecked_transaction()?;
+            tx.execute(
+                "INSERT OR REPLACE INTO index_meta (key, value) VALUES ('embed_model', ?1)",
+                params![info.model],
+            )?;
+            tx.execute(
+                "INSERT OR REPLACE INTO index_meta (key, value) VALUES ('dimension', ?1)",
+                params![info.dimension.to_string()],
+            )?;
+            tx.commit()?;
+            Ok(())
+        }).await?
+    }
+
+    pub async fn count_embeddings(&self) -> Result<usize> {
+        let conn = Arc::clone(&self.conn);
+        task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            let count: i64 = conn.query_row("SELECT COUNT(*) FROM embeddings", [], |row| row.get(0))?;
+            Ok(count as usize)
+        }).await?
+    }
+
+    /// Drop every vector and file hash so the next indexing pass starts over.
+    pub async fn clear_index(&self) -> Result<()> {
+        let conn = Arc::clone(&self.conn);
+        let index = Arc::clone(&self.index);
+        let index_path = self.index_path.clone();
+        let generation = Arc::clone(&self.generation);
+        task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            conn.execute_batch(
+                "
+                BEGIN;
+                DELETE FROM embeddings;
+                DELETE FROM embeddings_fts;
+                DELETE FROM file_meta;
+                DELETE FROM index_meta;
+                COMMIT;
+            ",
+            )?;
+            let mut index = index.blocking_lock();
+            *index = Arc::new(VectorIndex::default());
+            generation.fetch_add(1, Ordering::SeqCst);
+            index.save(&index_path)
+        }).await?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn embedding(id: &str, vector: Vec<f32>) -> Embedding {
+        Embedding {
+            id: id.to_string(),
+            vector,
+            text: format!("text of {}", id),
+            path: "src/lib.rs".to_string(),
+            start_line: 1,
+            end_line: 2,
+            symbol: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_insert_embeddings_reports_failures() {
+        let dir = std::env::temp_dir().join(format!("embedding_storage_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let db = dir.join("index.db");
+        let storage = EmbeddingStorage::new(&db, VectorFormat::F32).await.unwrap();
+
+        storage.insert_embeddings(vec![embedding("a", vec![1.0, 0.0])]).await.unwrap();
+        assert_eq!(storage.count_embeddings().await.unwrap(), 1);
+        assert_eq!(storage.snapshot().await.len(), 1);
+
+        // Vectors of another length are refused before anything is written.
+        let result = storage.insert_embeddings(vec![embedding("b", vec![1.0, 0.0, 0.0])]).await;
+        assert!(result.is_err());
+        assert_eq!(storage.count_embeddings().await.unwrap(), 1);
+        assert_eq!(storage.snapshot().await.len(), 1);
+
+        // The vector index cannot be saved over a directory.
+        std::fs::remove_file(EmbeddingStorage::index_path(&db)).unwrap();
+        std::fs::create_dir(EmbeddingStorage::index_path(&db)).unwrap();
+        let result = storage.insert_embeddings(vec![embedding("b", vec![0.0, 1.0])]).await;
+        assert!(result.is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_vectors_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("embedding_storage_skip_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let db = dir.join("index.db");
+        let storage = EmbeddingStorage::new(&db, VectorFormat::F32).await.unwrap();
+        storage.insert_embeddings(vec![embedding("a", vec![1.0, 0.0])]).await.unwrap();
+        drop(storage);
+
+        let conn = Connection::open(&db).unwrap();
+        conn.execute(
+            "INSERT INTO embeddings (id, vector, text) VALUES ('b', ?1, 'b')",
+            [vec![1u8, 0, 0, 0]],
+        )
+        .unwrap();
+        drop(conn);
+
+        let storage = EmbeddingStorage::new(&db, VectorFormat::F32).await.unwrap();
+        assert_eq!(storage.count_embeddings().await.unwrap(), 2);
+        assert_eq!(storage.snapshot().await.len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_bincode_vectors_are_converted() {
+        let dir = std::env::temp_dir().join(format!("embedding_storage_v4_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let db = dir.join("index.db");
+        drop(EmbeddingStorage::new(&db, VectorFormat::F32).await.unwrap());
+
+        // A row as version 4 stored it: a bincode `Vec<f32>`, not normalized.
+        let conn = Connection::open(&db).unwrap();
+        conn.execute(
+            "INSERT INTO embeddings (id, vector, text, path, start_line, end_line)
+             VALUES ('a', ?1, 'a', 'src/lib.rs', 1, 2)",
+            [bincode::serialize(&vec![3.0f32, 4.0]).unwrap()],
+        )
+        .unwrap();
+        conn.pragma_update(None, "user_version", 4).unwrap();
+        drop(conn);
+
+        let storage = EmbeddingStorage::new(&db, VectorFormat::F16).await.unwrap();
+        let stored: Vec<u8> = {
+            let conn = storage.conn.lock().await;
+            conn.query_row("SELECT vector FROM embeddings WHERE id = 'a'", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(vector_codec::describe(&stored[..2], stored.len()), Some((VectorFormat::F16, 2)));
+        let vector = &storage.get_embeddings(&["a".to_string()]).await.unwrap()[0].vector;
+        assert!((vector[0] - 0.6).abs() < 1e-3 && (vector[1] - 0.8).abs() < 1e-3);
+        assert_eq!(storage.snapshot().await.len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -193,66 +193,41 @@ impl FileScanner {
         const MAX_CHUNK_SIZE: usize = 2000;
         const MIN_CHUNK_SIZE: usize = 500;
 
-        let mut chunks = Vec::new();
-        let mut seen_hashes = HashSet::new();
-        let path_str = path.to_string_lossy().to_string();
-
-        // Split text into paragraphs (double newlines)
-        let paragraphs: Vec<&str> = text.split("\n\n").collect();
-        let mut current_chunk = String::new();
-        let mut start_offset = 0;
-
-        for paragraph in paragraphs {
-            if current_chunk.len() + paragraph.len() > MAX_CHUNK_SIZE && !current_chunk.is_empty() {
-                // Check deduplication
-                let hash = format!("{:x}", md5::compute(current_chunk.as_bytes()));
-                if seen_hashes.insert(hash) {
-                    chunks.push(FileChunk {
-                        path: path_str.clone(),
-                        text: current_chunk.clone(),
-                        start_offset,
-                    });
+        let mut builder = ChunkBuilder::new(text, path);
+
+        // Group paragraphs (separated by blank lines) into chunks. A chunk is
+        // always a contiguous slice of the file, so its offsets map to lines.
+        let mut current: Option<(usize, usize)> = None;
+        for paragraph in text.split("\n\n") {
+            let para_start = paragraph.as_ptr() as usize - text.as_ptr() as usize;
+            let para_end = para_start + paragraph.len();
+
+            if let Some((start, end)) = current {
+                if end - start + paragraph.len() > MAX_CHUNK_SIZE {
+                    builder.push(start, end);
+                    current = None;
                 }
-                current_chunk.clear();
-                start_offset += paragraph.as_ptr() as usize - text.as_ptr() as usize;
             }
 
-            if !current_chunk.is_empty() {
-                current_chunk.push_str("\n\n");
-            }
-            current_chunk.push_str(paragraph);
-
-            if current_chunk.len() >= MIN_CHUNK_SIZE {
-                let hash = format!("{:x}", md5::compute(current_chunk.as_bytes()));
-                if seen_hashes.insert(hash) {
-                    chunks.push(FileChunk {
-                        path: path_str.clone(),
-                        text: current_chunk.clone(),
-                        start_offset,
-                    });
-                }
-                current_chunk.clear();
-                start_offset += paragraph.as_ptr() as usize - text.as_ptr() as usize + paragraph.len();
+            let start = current.map_or(para_start, |(start, _)| start);
+            current = Some((start, para_end));
+
+            if para_end - start >= MIN_CHUNK_SIZE {
+                builder.push(start, para_end);
+                current = None;
             }
         }
 
         // Add remaining chunk
-        if !current_chunk.is_empty() {
-            let hash = format!("{:x}", md5::compute(current_chunk.as_bytes()));
-            if seen_hashes.insert(hash) {
-                chunks.push(FileChunk {
-                    path: path_str.clone(),
-                    text: current_chunk,
-                    start_offset,
-                });
-            }
+        if let Some((start, end)) = current {
+            builder.push(start, end);
         }
 
         // If no chunks, fallback to fixed size
-        if chunks.is_empty() {
+        if builder.chunks.is_empty() {
             self.chunk_fixed_size_dedup(text, path)
         } else {
-            chunks
+            builder.chunks
         }
     }
 
@@ -260,12 +235,10 @@ impl FileScanner {
         const CHUNK_SIZE: usize = 1000;
         const OVERLAP: usize = 200;
 
-        let mut chunks = Vec::new();
-        let mut seen_hashes = HashSet::new();
+        let mut builder = ChunkBuilder::new(text, path);
         let mut start = 0;
-        let path_str = path.to_string_lossy().to_string();
         let estimated = (text.len() / (CHUNK_SIZE.saturating_sub(OVERLAP)).max(1)) + 2;
-        chunks.reserve(estimated);
+        builder.chunks.reserve(estimated);
 
         while start < text.len() {
             let mut end = (start + CHUNK_SIZE).min(text.len());
@@ -273,15 +246,7 @@ impl FileScanner {
             while end < text.len() && !text.is_char_boundary(end) {
                 end += 1;
             }
-            let chunk_text = text[start..end].to_string();
-            let hash = format!("{:x}", md5::compute(chunk_text.as_bytes()));
-            if seen_hashes.insert(hash) {
-                chunks.push(FileChunk {
-                    path: path_str.clone(),
-                    text: chunk_text,
-                    start_offset: start,
-                });
-            }
+            builder.push(start, end);
 
             if end == text.len() {
                 break;
@@ -292,8 +257,125 @@ impl FileScanner {
             }
             start = next_start;
         }
-        chunks
+        builder.chunks
+    }
+}
+
+/// Collects deduplicated chunks of one file, locating each by line.
+struct ChunkBuilder<'a> {
+    text: &'a str,
+    path: String,
+    markdown: bool,
+    /// Byte offsets of every newline, for offset-to-line lookups.
+    newlines: Vec<usize>,
+    seen_hashes: HashSet<String>,
+    chunks: Vec<FileChunk>,
+}
+
+impl<'a> ChunkBuilder<'a> {
+    fn new(text: &'a str, path: &Path) -> Self {
+        Self {
+            text,
+            path: path.to_string_lossy().to_string(),
+            markdown: path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("md")),
+            newlines: text.match_indices('\n').map(|(i, _)| i).collect(),
+            seen_hashes: HashSet::new(),
+            chunks: Vec::new(),
+        }
+    }
+
+    /// 1-based line containing the byte at `offset`.
+    fn line_of(&self, offset: usize) -> usize {
+        self.newlines.partition_point(|&newline| newline < offset) + 1
+    }
+
+    fn push(&mut self, start: usize, end: usize) {
+        let chunk_text = &self.text[start..end];
+        if chunk_text.trim().is_empty() {
+            return;
+        }
+        let hash = format!("{:x}", md5::compute(chunk_text.as_bytes()));
+        if !self.seen_hashes.insert(hash) {
+            return;
+        }
+        self.chunks.push(FileChunk {
+            path: self.path.clone(),
+            text: chunk_text.to_string(),
+            start_offset: start,
+            start_line: self.line_of(start),
+            end_line: self.line_of(end - 1),
+            symbol: detect_symbol(chunk_text, self.markdown),
+        });
+    }
+}
+
+/// Keywords that introduce a named definition in common languages.
+const DEFINITION_KEYWORDS: &[&str] = &[
+    "fn", "struct", "enum", "trait", "impl", "mod", "union", "class", "def", "function", "func",
+    "interface", "type",
+];
+/// Modifiers that may precede a definition keyword.
+const DEFINITION_MODIFIERS: &[&str] = &[
+    "pub", "pub(crate)", "pub(super)", "async", "unsafe", "const", "extern", "export", "default",
+    "public", "private", "protected", "static", "abstract", "final",
+];
+
+/// Skip a leading `<...>` or `(...)` group, as in `impl<T> Foo` or `func (r *T) Name`.
+fn skip_group(rest: &str) -> &str {
+    let (open, close) = match rest.chars().next() {
+        Some('<') => ('<', '>'),
+        Some('(') => ('(', ')'),
+        _ => return rest,
+    };
+    let mut depth = 0;
+    for (i, c) in rest.char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return rest[i + 1..].trim_start();
+            }
+        }
+    }
+    rest
+}
+
+/// The first definition (or Markdown heading) in a chunk, e.g. `fn main`.
+fn detect_symbol(text: &str, markdown: bool) -> Option<String> {
+    for line in text.lines() {
+        let line = line.trim();
+        if markdown {
+            if line.starts_with('#') {
+                let heading = line.trim_start_matches('#').trim();
+                if !heading.is_empty() {
+                    return Some(heading.to_string());
+                }
+            }
+            continue;
+        }
+        let mut words = line.split_whitespace().peekable();
+        while words.peek().is_some_and(|w| DEFINITION_MODIFIERS.contains(w)) {
+            words.next();
+        }
+        let Some(keyword) = words.next() else { continue };
+        let keyword = keyword.split('<').next().unwrap_or(keyword);
+        if !DEFINITION_KEYWORDS.contains(&keyword) {
+            continue;
+        }
+        let rest = line[line.find(keyword).unwrap_or(0) + keyword.len()..].trim_start();
+        let name: String = skip_group(rest)
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == ':')
+            .collect();
+        let name = name.trim_end_matches(':');
+        if !name.is_empty() {
+            return Some(format!("{} {}", keyword, name));
+        }
     }
+    None
 }
 
 #[derive(Debug, Clone)]
@@ -301,6 +383,11 @@ pub struct FileChunk {
     pub path: String,
     pub text: String,
     pub start_offset: usize,
+    /// First and last line of the chunk, 1-based and inclusive.
+    pub start_line: usize,
+    pub end_line: usize,
+    /// The function, type or heading the chunk starts in, when one is recognised.
+    pub symbol: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,209 @@
+use super::config::Config;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use shared::types::Result;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+/// Longest pause between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// How often and how patiently transient HTTP failures are retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_retries: config.max_retries,
+            base_delay: Duration::from_millis(500),
+        }
+    }
+
+    /// Exponential backoff with full jitter: a random delay of up to
+    /// `base_delay * 2^attempt`, capped at `MAX_BACKOFF`.
+    fn delay(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1 << attempt.min(16))
+            .min(MAX_BACKOFF);
+        let ceiling_ms = ceiling.as_millis().max(1) as u64;
+        Duration::from_millis(random_u64() % (ceiling_ms + 1))
+    }
+}
+
+/// A random number from the hasher seed std draws per `RandomState`.
+fn random_u64() -> u64 {
+    RandomState::new().hash_one(std::time::Instant::now())
+}
+
+/// A client with the configured connect and read timeouts. Fails rather
+/// than fall back to a client without them, which could wait forever.
+pub fn build_client(config: &Config) -> Result<Client> {
+    Client::builder()
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(config.read_timeout_secs))
+        .build()
+        .map_err(|e| anyhow::anyhow!("Cannot set up the HTTP client: {}", root_cause(&e)))
+}
+
+/// The innermost error message, e.g. "Connection refused (os error 111)".
+pub(crate) fn root_cause(err: &reqwest::Error) -> String {
+    let mut source: &dyn std::error::Error = err;
+    while let Some(inner) = source.source() {
+        source = inner;
+    }
+    source.to_string()
+}
+
+/// Statuses that usually clear up on their own.
+fn is_transient_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Send the request built by `build`, retrying connection failures and
+/// transient statuses. Read timeouts are not retried: the server was busy
+/// generating and would most likely time out again.
+pub async fn send_with_retry<F>(policy: &RetryPolicy, build: F) -> reqwest::Result<Response>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let mut attempt = 0;
+    loop {
+        let problem = match build().send().await {
+            Ok(response) if is_transient_status(response.status()) => {
+                if attempt >= policy.max_retries {
+                    return Ok(response);
+                }
+                format!("HTTP {}", response.status())
+            }
+            Ok(response) => return Ok(response),
+            Err(e) if e.is_connect() && attempt < policy.max_retries => root_cause(&e),
+            Err(e) => return Err(e),
+        };
+        let delay = policy.delay(attempt);
+        attempt += 1;
+        eprintln!(
+            "Request failed ({}); retrying in {:.1}s ({}/{})...",
+            problem,
+            delay.as_secs_f32(),
+            attempt,
+            policy.max_retries
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn policy(max_retries: u32, base_ms: u64) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay: Duration::from_millis(base_ms),
+        }
+    }
+
+    /// A server answering one connection per status. Yields how many
+    /// requests it received.
+    fn serve(statuses: Vec<u16>) -> (String, thread::JoinHandle<usize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut served = 0;
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).unwrap();
+                let reply = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                );
+                stream.write_all(reply.as_bytes()).unwrap();
+                served += 1;
+            }
+            served
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_delay_stays_under_the_doubling_ceiling() {
+        let policy = policy(5, 500);
+        for attempt in 0..6 {
+            let ceiling = Duration::from_millis(500 << attempt).min(MAX_BACKOFF);
+            for _ in 0..50 {
+                assert!(policy.delay(attempt) <= ceiling);
+            }
+        }
+        // Large attempt numbers neither overflow nor pass the cap.
+        assert!(policy.delay(40) <= MAX_BACKOFF);
+        assert!(self::policy(1, 0).delay(3) <= Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_transient_statuses() {
+        for status in [408, 429, 502, 503, 504] {
+            assert!(is_transient_status(StatusCode::from_u16(status).unwrap()));
+        }
+        for status in [200, 400, 401, 404, 500] {
+            assert!(!is_transient_status(StatusCode::from_u16(status).unwrap()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transient_status_is_retried_until_success() {
+        let (url, server) = serve(vec![503, 429, 200]);
+        let client = Client::new();
+        let response = send_with_retry(&policy(3, 1), || client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(server.join().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_the_limit_or_a_final_status() {
+        let (url, server) = serve(vec![503, 503]);
+        let client = Client::new();
+        let response = send_with_retry(&policy(1, 1), || client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(server.join().unwrap(), 2);
+
+        let (url, server) = serve(vec![500]);
+        let response = send_with_retry(&policy(3, 1), || client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(server.join().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_connection_failures_are_retried() {
+        // Nothing listens on a port that was just released.
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/", listener.local_addr().unwrap())
+        };
+        let client = Client::new();
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let err = send_with_retry(&policy(2, 1), || {
+            attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            client.get(&url)
+        })
+        .await
+        .unwrap_err();
+        assert!(err.is_connect());
+        assert_eq!(attempts.into_inner(), 3);
+    }
+}
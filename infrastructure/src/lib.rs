@@ -2,5 +2,14 @@ pub mod config;
 pub mod embedder;
 pub mod embedding_storage;
 pub mod file_scanner;
+pub mod http;
+pub mod model_backend;
 pub mod ollama_client;
+pub mod ollama_error;
+pub mod ollama_models;
+pub mod openai_client;
+pub mod run_store;
 pub mod search;
+pub mod stats_store;
+pub mod vector_codec;
+pub mod vector_index;
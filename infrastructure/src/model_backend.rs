@@ -0,0 +1,116 @@
+use super::config::{Config, LlmBackend};
+use super::ollama_client::OllamaClient;
+use super::openai_client::OpenAiClient;
+use domain::language_model::{EmbeddingModel, GenerationOptions, LanguageModel};
+use domain::session::Message;
+use domain::usage::UsageMeter;
+use shared::types::Result;
+
+/// The model backend selected by `LLM_BACKEND`.
+#[derive(Clone)]
+pub enum ModelBackend {
+    Ollama(OllamaClient),
+    OpenAi(OpenAiClient),
+}
+
+impl ModelBackend {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(match config.llm_backend {
+            LlmBackend::Ollama => Self::Ollama(OllamaClient::from_config(config)?),
+            LlmBackend::OpenAi => Self::OpenAi(OpenAiClient::from_config(config)?),
+        })
+    }
+
+    /// Report token counts and timings of every request to `meter`.
+    pub fn with_meter(self, meter: UsageMeter) -> Self {
+        match self {
+            Self::Ollama(client) => Self::Ollama(client.with_meter(meter)),
+            Self::OpenAi(client) => Self::OpenAi(client.with_meter(meter)),
+        }
+    }
+
+    /// The selected backend bound to the configured embedding model.
+    pub fn embeddings_from_config(config: &Config) -> Result<Self> {
+        Ok(Self::from_config(config)?.with_model(&config.embed_model))
+    }
+}
+
+impl LanguageModel for ModelBackend {
+    fn model_name(&self) -> &str {
+        match self {
+            Self::Ollama(client) => client.model_name(),
+            Self::OpenAi(client) => client.model_name(),
+        }
+    }
+
+    fn with_model(&self, model: &str) -> Self {
+        match self {
+            Self::Ollama(client) => Self::Ollama(client.with_model(model)),
+            Self::OpenAi(client) => Self::OpenAi(client.with_model(model)),
+        }
+    }
+
+    async fn chat(&self, messages: &[Message], options: &GenerationOptions) -> Result<String> {
+        match self {
+            Self::Ollama(client) => client.chat(messages, options).await,
+            Self::OpenAi(client) => client.chat(messages, options).await,
+        }
+    }
+
+    async fn stream<F>(
+        &self,
+        messages: &[Message],
+        options: &GenerationOptions,
+        on_token: F,
+    ) -> Result<String>
+    where
+        F: FnMut(&str) + Send,
+    {
+        match self {
+            Self::Ollama(client) => client.stream(messages, options, on_token).await,
+            Self::OpenAi(client) => client.stream(messages, options, on_token).await,
+        }
+    }
+
+    async fn structured(
+        &self,
+        messages: &[Message],
+        schema: &serde_json::Value,
+        options: &GenerationOptions,
+    ) -> Result<serde_json::Value> {
+        match self {
+            Self::Ollama(client) => client.structured(messages, schema, options).await,
+            Self::OpenAi(client) => client.structured(messages, schema, options).await,
+        }
+    }
+}
+
+impl EmbeddingModel for ModelBackend {
+    fn embedding_model_name(&self) -> &str {
+        match self {
+            Self::Ollama(client) => client.embedding_model_name(),
+            Self::OpenAi(client) => client.embedding_model_name(),
+        }
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        match self {
+            Self::Ollama(client) => client.embed(text).await,
+            Self::OpenAi(client) => client.embed(text).await,
+        }
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        match self {
+            Self::Ollama(client) => client.embed_batch(texts).await,
+            Self::OpenAi(client) => client.embed_batch(texts).await,
+        }
+    }
+
+    async fn dimension(&self) -> Result<usize> {
+        match self {
+            Self::Ollama(client) => client.dimension().await,
+            Self::OpenAi(client) => client.dimension().await,
+        }
+    }
+}
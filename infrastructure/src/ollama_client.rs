@@ -1,8 +1,17 @@
-use reqwest::Client;
+use super::config::Config;
+use super::http::{build_client, send_with_retry, RetryPolicy};
+use super::ollama_error::OllamaError;
+use domain::language_model::{EmbeddingModel, GenerationOptions, LanguageModel};
+use domain::session::Message;
+use domain::usage::{RequestKind, Usage, UsageMeter};
+use futures::stream::{self, StreamExt};
+use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
 use shared::types::Result;
-use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::OnceCell;
 
 #[derive(Serialize)]
 struct EmbeddingRequest {
@@ -15,10 +24,38 @@ struct EmbeddingResponse {
     embedding: Vec<f32>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct Message {
-    role: String,
-    content: String,
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+    #[serde(flatten)]
+    timings: Timings,
+}
+
+/// Counters Ollama adds to final responses; durations are in nanoseconds.
+#[derive(Deserialize, Default, Clone, Copy)]
+struct Timings {
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
+    #[serde(default)]
+    total_duration: u64,
+    #[serde(default)]
+    load_duration: u64,
+}
+
+#[derive(Serialize)]
+struct ChatOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_ctx: Option<u32>,
 }
 
 #[derive(Serialize)]
@@ -26,12 +63,25 @@ struct ChatRequest {
     model: String,
     messages: Vec<Message>,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<ChatOptions>,
+    /// JSON schema the answer must follow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<serde_json::Value>,
 }
 
 #[derive(Deserialize)]
 struct ChatResponse {
     message: Message,
     done: bool,
+    #[serde(flatten)]
+    timings: Timings,
+}
+
+/// Sent in place of a `ChatResponse` when generation fails mid-stream.
+#[derive(Deserialize)]
+struct StreamError {
+    error: String,
 }
 
 #[derive(Clone)]
@@ -39,71 +89,417 @@ pub struct OllamaClient {
     client: Arc<Client>,
     base_url: String,
     model: String,
+    retry: RetryPolicy,
+    dimension: Arc<OnceCell<usize>>,
+    /// Set once the server turns out to predate the batched `/api/embed` endpoint.
+    legacy_embeddings: Arc<AtomicBool>,
+    meter: UsageMeter,
 }
 
 impl OllamaClient {
-    pub fn new() -> Result<Self> {
-        let base_url =
-            env::var("OLLAMA_BASE_URL").unwrap_or_else(|_| "http://localhost:11434".to_string());
-        let model = env::var("BASE_MODEL").unwrap_or_else(|_| "qwen2.5:1.5b-instruct".to_string());
+    pub fn from_config(config: &Config) -> Result<Self> {
         Ok(Self {
-            client: Arc::new(Client::new()),
-            base_url,
-            model,
+            client: Arc::new(build_client(config)?),
+            base_url: config.ollama_base_url.clone(),
+            model: config.ollama_model.clone(),
+            retry: RetryPolicy::from_config(config),
+            dimension: Arc::new(OnceCell::new()),
+            legacy_embeddings: Arc::new(AtomicBool::new(false)),
+            meter: UsageMeter::new(),
         })
     }
 
+    /// Report token counts and timings of every request to `meter`.
+    pub fn with_meter(mut self, meter: UsageMeter) -> Self {
+        self.meter = meter;
+        self
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn record_usage(&self, kind: RequestKind, timings: Timings, started: Instant) {
+        const NANOS_PER_MS: u64 = 1_000_000;
+        self.meter.record(Usage {
+            model: self.model.clone(),
+            kind,
+            prompt_tokens: timings.prompt_eval_count,
+            completion_tokens: timings.eval_count,
+            total_ms: timings.total_duration / NANOS_PER_MS,
+            load_ms: timings.load_duration / NANOS_PER_MS,
+            wall_ms: started.elapsed().as_millis() as u64,
+        });
+    }
+
+    fn transport_error(&self, err: reqwest::Error) -> OllamaError {
+        OllamaError::from_transport(err, &self.base_url, &self.model)
+    }
+
+    /// POST `body` to `path`, retrying transient failures. Error statuses are
+    /// turned into an `OllamaError`.
+    async fn post<T: Serialize + Sync>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> std::result::Result<Response, OllamaError> {
+        let url = format!("{}{}", self.base_url, path);
+        let response = send_with_retry(&self.retry, || self.client.post(&url).json(body))
+            .await
+            .map_err(|e| self.transport_error(e))?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(OllamaError::from_api(Some(status.as_u16()), &text, &self.model));
+        }
+        Ok(response)
+    }
+
+    /// Embed all `texts` with one `/api/embed` request, or with one legacy
+    /// `/api/embeddings` request per text on servers without the batched endpoint.
+    pub async fn generate_embeddings(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        if !self.legacy_embeddings.load(Ordering::Relaxed) {
+            let started = Instant::now();
+            let request = EmbedRequest {
+                model: &self.model,
+                input: texts,
+            };
+            match self.post("/api/embed", &request).await {
+                Ok(response) => {
+                    let embed_response: EmbedResponse =
+                        response.json().await.map_err(|e| self.transport_error(e))?;
+                    if embed_response.embeddings.len() != texts.len()
+                        || embed_response.embeddings.iter().any(|v| v.is_empty())
+                    {
+                        return Err(OllamaError::bad_response(
+                            &self.model,
+                            format!(
+                                "expected {} embeddings, got {}",
+                                texts.len(),
+                                embed_response.embeddings.len()
+                            ),
+                        )
+                        .into());
+                    }
+                    self.record_usage(RequestKind::Embed, embed_response.timings, started);
+                    return Ok(embed_response.embeddings);
+                }
+                Err(OllamaError::Api { status: 404, .. }) => {
+                    eprintln!("Ollama server has no /api/embed; embedding one chunk per request.");
+                    self.legacy_embeddings.store(true, Ordering::Relaxed);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let requests: Vec<_> = texts
+            .iter()
+            .map(|text| self.generate_embedding(text))
+            .collect();
+        stream::iter(requests)
+            .buffered(8)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    /// Embed one text through the legacy `/api/embeddings` endpoint.
     pub async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>> {
-        let url = format!("{}/api/embeddings", self.base_url);
+        let started = Instant::now();
         let request = EmbeddingRequest {
             model: self.model.clone(),
             prompt: text.to_string(),
         };
-        let response = self.client.post(&url).json(&request).send().await?;
-        let embedding_response: EmbeddingResponse = response.json().await?;
+        let response = self.post("/api/embeddings", &request).await?;
+        let embedding_response: EmbeddingResponse =
+            response.json().await.map_err(|e| self.transport_error(e))?;
+        if embedding_response.embedding.is_empty() {
+            return Err(OllamaError::bad_response(&self.model, "empty embedding").into());
+        }
+        self.record_usage(RequestKind::Embed, Timings::default(), started);
         Ok(embedding_response.embedding)
     }
 
-    pub async fn generate_response(&self, prompt: &str) -> Result<String> {
-        self.generate_response_with_system(prompt, "").await
-    }
-
-    pub async fn generate_response_with_system(&self, prompt: &str, system: &str) -> Result<String> {
-        let url = format!("{}/api/chat", self.base_url);
-        let mut messages = Vec::new();
-        if !system.is_empty() {
-            messages.push(Message {
-                role: "system".to_string(),
-                content: system.to_string(),
-            });
-        }
-        messages.push(Message {
-            role: "user".to_string(),
-            content: prompt.to_string(),
-        });
-        let request = ChatRequest {
+    fn chat_request(
+        &self,
+        messages: &[Message],
+        options: &GenerationOptions,
+        format: Option<serde_json::Value>,
+        stream: bool,
+    ) -> ChatRequest {
+        let options = (options.temperature.is_some() || options.num_ctx.is_some()).then_some(
+            ChatOptions {
+                temperature: options.temperature,
+                num_ctx: options.num_ctx,
+            },
+        );
+        ChatRequest {
             model: self.model.clone(),
-            messages,
-            stream: false,
-        };
-        let response = self.client.post(&url).json(&request).send().await?;
-        let status = response.status();
-        let text = response.text().await?;
-        if !status.is_success() {
-            return Err(anyhow::anyhow!("Ollama API error: {}", text));
+            messages: messages.to_vec(),
+            stream,
+            options,
+            format,
         }
+    }
+
+    async fn send_chat(
+        &self,
+        messages: &[Message],
+        options: &GenerationOptions,
+        format: Option<serde_json::Value>,
+    ) -> Result<String> {
+        let started = Instant::now();
+        let request = self.chat_request(messages, options, format, false);
+        let response = self.post("/api/chat", &request).await?;
+        let text = response.text().await.map_err(|e| self.transport_error(e))?;
         let mut full_content = String::new();
+        let mut timings = Timings::default();
+        let mut answered = false;
         for line in text.lines() {
             if line.trim().is_empty() {
                 continue;
             }
             if let Ok(chat_resp) = serde_json::from_str::<ChatResponse>(line) {
+                answered = true;
                 full_content.push_str(&chat_resp.message.content);
                 if chat_resp.done {
+                    timings = chat_resp.timings;
                     break;
                 }
+            } else if let Ok(failure) = serde_json::from_str::<StreamError>(line) {
+                return Err(OllamaError::from_api(None, &failure.error, &self.model).into());
             }
         }
+        if !answered {
+            let body: String = text.trim().chars().take(200).collect();
+            return Err(OllamaError::bad_response(
+                &self.model,
+                format!("no chat message in response: {}", body),
+            )
+            .into());
+        }
+        self.record_usage(RequestKind::Chat, timings, started);
         Ok(full_content)
     }
 }
+
+/// Handle one NDJSON line of a streamed chat. Returns the final counters once the model is done.
+fn handle_stream_line<F: FnMut(&str)>(
+    line: &[u8],
+    model: &str,
+    answer: &mut String,
+    on_token: &mut F,
+) -> Result<Option<Timings>> {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if let Ok(chunk) = serde_json::from_str::<ChatResponse>(line) {
+        if !chunk.message.content.is_empty() {
+            on_token(&chunk.message.content);
+            answer.push_str(&chunk.message.content);
+        }
+        return Ok(chunk.done.then_some(chunk.timings));
+    }
+    if let Ok(failure) = serde_json::from_str::<StreamError>(line) {
+        return Err(OllamaError::from_api(None, &failure.error, model).into());
+    }
+    Ok(None)
+}
+
+impl LanguageModel for OllamaClient {
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    fn with_model(&self, model: &str) -> Self {
+        Self {
+            client: Arc::clone(&self.client),
+            base_url: self.base_url.clone(),
+            model: model.to_string(),
+            retry: self.retry,
+            dimension: Arc::new(OnceCell::new()),
+            legacy_embeddings: Arc::clone(&self.legacy_embeddings),
+            meter: self.meter.clone(),
+        }
+    }
+
+    async fn chat(&self, messages: &[Message], options: &GenerationOptions) -> Result<String> {
+        self.send_chat(messages, options, None).await
+    }
+
+    async fn stream<F>(
+        &self,
+        messages: &[Message],
+        options: &GenerationOptions,
+        mut on_token: F,
+    ) -> Result<String>
+    where
+        F: FnMut(&str) + Send,
+    {
+        let started = Instant::now();
+        let request = self.chat_request(messages, options, None, true);
+        let mut response = self.post("/api/chat", &request).await?;
+
+        // One JSON object per line; a line may span several network chunks.
+        let mut pending: Vec<u8> = Vec::new();
+        let mut answer = String::new();
+        let mut timings = None;
+        'read: while let Some(bytes) = response.chunk().await.map_err(|e| self.transport_error(e))? {
+            pending.extend_from_slice(&bytes);
+            while let Some(newline) = pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=newline).collect();
+                timings = handle_stream_line(&line, &self.model, &mut answer, &mut on_token)?;
+                if timings.is_some() {
+                    break 'read;
+                }
+            }
+        }
+        if timings.is_none() && !pending.is_empty() {
+            timings = handle_stream_line(&pending, &self.model, &mut answer, &mut on_token)?;
+        }
+        self.record_usage(RequestKind::Chat, timings.unwrap_or_default(), started);
+        Ok(answer)
+    }
+
+    async fn structured(
+        &self,
+        messages: &[Message],
+        schema: &serde_json::Value,
+        options: &GenerationOptions,
+    ) -> Result<serde_json::Value> {
+        let answer = self
+            .send_chat(messages, options, Some(schema.clone()))
+            .await?;
+        serde_json::from_str(&answer).map_err(|e| {
+            OllamaError::bad_response(&self.model, format!("invalid JSON: {}", e)).into()
+        })
+    }
+}
+
+impl EmbeddingModel for OllamaClient {
+    fn embedding_model_name(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.generate_embeddings(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| OllamaError::bad_response(&self.model, "no embedding returned").into())
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.generate_embeddings(texts).await
+    }
+
+    async fn dimension(&self) -> Result<usize> {
+        self.dimension
+            .get_or_try_init(|| async { Ok(self.embed("dimension probe").await?.len()) })
+            .await
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// A server answering one request with status 200 and `body`.
+    fn serve(body: &'static str) -> (String, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut raw = Vec::new();
+            let mut buf = [0u8; 4096];
+            while let Ok(n) = stream.read(&mut buf) {
+                raw.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&raw);
+                let complete = text.find("\r\n\r\n").is_some_and(|end| {
+                    let length = text[..end]
+                        .lines()
+                        .find_map(|l| {
+                            let l = l.to_lowercase();
+                            l.strip_prefix("content-length:")?.trim().parse::<usize>().ok()
+                        })
+                        .unwrap_or(0);
+                    raw.len() >= end + 4 + length
+                });
+                if complete || n == 0 {
+                    break;
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        (url, handle)
+    }
+
+    fn client(url: &str) -> OllamaClient {
+        OllamaClient::from_config(&Config {
+            ollama_base_url: url.to_string(),
+            ollama_model: "test-model".to_string(),
+            max_retries: 0,
+            ..Config::load()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_chat_joins_message_lines() {
+        let (url, server) = serve(concat!(
+            r#"{"message":{"role":"assistant","content":"hello "},"done":false}"#,
+            "\n",
+            r#"{"message":{"role":"assistant","content":"there"},"done":true,"eval_count":2}"#,
+            "\n",
+        ));
+        let answer = client(&url)
+            .chat(&[Message::user("hi")], &GenerationOptions::default())
+            .await
+            .unwrap();
+        server.join().unwrap();
+        assert_eq!(answer, "hello there");
+    }
+
+    #[tokio::test]
+    async fn test_chat_error_body_is_an_error() {
+        let (url, server) = serve(r#"{"error":"model 'test-model' not found"}"#);
+        let err = client(&url)
+            .chat(&[Message::user("hi")], &GenerationOptions::default())
+            .await
+            .unwrap_err();
+        server.join().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<OllamaError>(),
+            Some(OllamaError::ModelNotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_chat_without_message_is_a_bad_response() {
+        let (url, server) = serve("<html>proxy error</html>");
+        let err = client(&url)
+            .chat(&[Message::user("hi")], &GenerationOptions::default())
+            .await
+            .unwrap_err();
+        server.join().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<OllamaError>(),
+            Some(OllamaError::BadResponse { .. })
+        ));
+        assert!(err.to_string().contains("proxy error"));
+    }
+}
@@ -0,0 +1,177 @@
+use super::http::root_cause;
+use serde::Deserialize;
+use std::fmt;
+
+/// Failures talking to an Ollama server, classified so the CLI can suggest a fix.
+#[derive(Debug)]
+pub enum OllamaError {
+    /// Nothing answered at the configured address.
+    Unreachable { base_url: String, reason: String },
+    /// The server stopped sending data for longer than the read timeout.
+    Timeout { base_url: String },
+    /// The model has not been pulled.
+    ModelNotFound { model: String },
+    /// The prompt or input is longer than the model's context window.
+    ContextOverflow { model: String, message: String },
+    /// The server answered with something we could not use.
+    BadResponse { model: String, message: String },
+    /// Any other error reported by the server.
+    Api { status: u16, message: String },
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl OllamaError {
+    pub(crate) fn from_transport(err: reqwest::Error, base_url: &str, model: &str) -> Self {
+        if err.is_timeout() {
+            Self::Timeout {
+                base_url: base_url.to_string(),
+            }
+        } else if err.is_connect() {
+            Self::Unreachable {
+                base_url: base_url.to_string(),
+                reason: root_cause(&err),
+            }
+        } else if err.is_decode() {
+            Self::bad_response(model, err.to_string())
+        } else {
+            Self::Unreachable {
+                base_url: base_url.to_string(),
+                reason: root_cause(&err),
+            }
+        }
+    }
+
+    /// Classify an error reported by the server. `status` is `None` for
+    /// errors sent in the middle of a streamed response.
+    pub(crate) fn from_api(status: Option<u16>, body: &str, model: &str) -> Self {
+        let message = serde_json::from_str::<ErrorBody>(body)
+            .map(|b| b.error)
+            .unwrap_or_else(|_| body.trim().to_string());
+        let lower = message.to_lowercase();
+        // Older servers answer unknown endpoints with a bare "404 page not found".
+        if lower.contains("not found") && lower.contains("model") {
+            Self::ModelNotFound {
+                model: model.to_string(),
+            }
+        } else if lower.contains("context length")
+            || lower.contains("context window")
+            || lower.contains("exceeds the context")
+        {
+            Self::ContextOverflow {
+                model: model.to_string(),
+                message,
+            }
+        } else if let Some(status) = status {
+            Self::Api { status, message }
+        } else {
+            Self::BadResponse {
+                model: model.to_string(),
+                message,
+            }
+        }
+    }
+
+    pub(crate) fn bad_response(model: &str, message: impl Into<String>) -> Self {
+        Self::BadResponse {
+            model: model.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// A suggestion for the user, when there is an obvious one.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Self::Unreachable { base_url, .. } => Some(format!(
+                "Start the server with `ollama serve`, or point OLLAMA_BASE_URL at it (currently {}).",
+                base_url
+            )),
+            Self::Timeout { .. } => Some(
+                "The model may still be loading or generating; raise HTTP_READ_TIMEOUT_SECS or use a smaller model."
+                    .to_string(),
+            ),
+            Self::ModelNotFound { model } => Some(format!(
+                "Run `vibe_cli models pull {}` (or `ollama pull {}`).",
+                model, model
+            )),
+            Self::ContextOverflow { .. } => Some(
+                "Shorten the input or use a model with a larger context window.".to_string(),
+            ),
+            Self::BadResponse { .. } => Some(
+                "Check that the model supports this request (e.g. embeddings) and that OLLAMA_BASE_URL points at Ollama."
+                    .to_string(),
+            ),
+            Self::Api { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for OllamaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unreachable { base_url, reason } => {
+                write!(f, "Cannot reach Ollama at {}: {}", base_url, reason)
+            }
+            Self::Timeout { base_url } => write!(f, "Timed out waiting for Ollama at {}", base_url),
+            Self::ModelNotFound { model } => write!(f, "Model '{}' is not available in Ollama", model),
+            Self::ContextOverflow { model, message } => {
+                write!(f, "Input does not fit the context window of '{}': {}", model, message)
+            }
+            Self::BadResponse { model, message } => {
+                write!(f, "Unexpected response from Ollama for '{}': {}", model, message)
+            }
+            Self::Api { status, message } => write!(f, "Ollama API error ({}): {}", status, message),
+        }
+    }
+}
+
+impl std::error::Error for OllamaError {}
+
+#[cfg(test)]
+mod tests {
+    use super::OllamaError;
+
+    #[test]
+    fn test_missing_model() {
+        let body = r#"{"error":"model \"llama9\" not found, try pulling it first"}"#;
+        let err = OllamaError::from_api(Some(404), body, "llama9");
+        assert!(matches!(err, OllamaError::ModelNotFound { ref model } if model == "llama9"));
+        assert!(err.hint().unwrap().contains("vibe_cli models pull llama9"));
+    }
+
+    #[test]
+    fn test_bare_not_found_is_not_a_missing_model() {
+        let err = OllamaError::from_api(Some(404), "404 page not found\n", "llama3");
+        assert!(matches!(
+            err,
+            OllamaError::Api { status: 404, ref message } if message == "404 page not found"
+        ));
+        assert!(err.hint().is_none());
+    }
+
+    #[test]
+    fn test_context_overflow() {
+        for body in [
+            r#"{"error":"input length exceeds the context length"}"#,
+            r#"{"error":"prompt is larger than the Context Window"}"#,
+            "the input exceeds the context of the model",
+        ] {
+            let err = OllamaError::from_api(Some(400), body, "m");
+            assert!(matches!(err, OllamaError::ContextOverflow { .. }), "{}", body);
+        }
+    }
+
+    #[test]
+    fn test_stream_errors_are_bad_responses() {
+        let err = OllamaError::from_api(None, r#"{"error":"unexpected EOF"}"#, "m");
+        assert!(matches!(
+            err,
+            OllamaError::BadResponse { ref message, .. } if message == "unexpected EOF"
+        ));
+        let err = OllamaError::from_api(Some(500), r#"{"error":"unexpected EOF"}"#, "m");
+        assert_eq!(err.to_string(), "Ollama API error (500): unexpected EOF");
+    }
+}
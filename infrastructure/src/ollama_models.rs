@@ -0,0 +1,245 @@
+use super::config::Config;
+use super::http::{build_client, send_with_retry, RetryPolicy};
+use super::ollama_error::OllamaError;
+use reqwest::{Client, Method, Response};
+use serde::{Deserialize, Serialize};
+use shared::types::Result;
+
+/// Size, family and quantization as reported by Ollama.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ModelDetails {
+    #[serde(default)]
+    pub family: String,
+    #[serde(default)]
+    pub parameter_size: String,
+    #[serde(default)]
+    pub quantization_level: String,
+}
+
+/// A locally available model, as listed by `/api/tags`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelSummary {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default)]
+    pub modified_at: String,
+    #[serde(default)]
+    pub details: ModelDetails,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<ModelSummary>,
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+/// Everything `/api/show` tells about one model.
+#[derive(Debug, Clone)]
+pub struct ModelInfo {
+    pub details: ModelDetails,
+    pub architecture: Option<String>,
+    pub context_length: Option<u64>,
+    /// Vector length, for embedding models.
+    pub embedding_length: Option<u64>,
+    pub capabilities: Vec<String>,
+    /// Default generation parameters, one `name value` pair per line.
+    pub parameters: String,
+}
+
+#[derive(Deserialize)]
+struct ShowResponse {
+    #[serde(default)]
+    details: ModelDetails,
+    #[serde(default)]
+    model_info: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    capabilities: Vec<String>,
+    #[serde(default)]
+    parameters: String,
+}
+
+/// One status line of a running `/api/pull`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    pub digest: Option<String>,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Names a model in management requests. Older servers read `name`, newer ones `model`.
+#[derive(Serialize)]
+struct ModelRequest<'a> {
+    model: &'a str,
+    name: &'a str,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+}
+
+impl<'a> ModelRequest<'a> {
+    fn new(model: &'a str) -> Self {
+        Self {
+            model,
+            name: model,
+            stream: false,
+        }
+    }
+}
+
+/// Lists, pulls, inspects and removes models on an Ollama server.
+pub struct OllamaModels {
+    client: Client,
+    base_url: String,
+    retry: RetryPolicy,
+}
+
+impl OllamaModels {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self {
+            client: build_client(config)?,
+            base_url: config.ollama_base_url.clone(),
+            retry: RetryPolicy::from_config(config),
+        })
+    }
+
+    async fn request<T: Serialize + Sync>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&T>,
+        model: &str,
+    ) -> std::result::Result<Response, OllamaError> {
+        let url = format!("{}{}", self.base_url, path);
+        let response = send_with_retry(&self.retry, || {
+            let builder = self.client.request(method.clone(), &url);
+            match body {
+                Some(body) => builder.json(body),
+                None => builder,
+            }
+        })
+        .await
+        .map_err(|e| OllamaError::from_transport(e, &self.base_url, model))?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            return Err(OllamaError::from_api(Some(status.as_u16()), &text, model));
+        }
+        Ok(response)
+    }
+
+    /// Version string of the server, e.g. "0.5.7".
+    pub async fn version(&self) -> Result<String> {
+        let response = self
+            .request::<()>(Method::GET, "/api/version", None, "")
+            .await?;
+        let version: VersionResponse = response
+            .json()
+            .await
+            .map_err(|e| OllamaError::from_transport(e, &self.base_url, ""))?;
+        Ok(version.version)
+    }
+
+    /// Models available locally, sorted by name.
+    pub async fn list(&self) -> Result<Vec<ModelSummary>> {
+        let response = self
+            .request::<()>(Method::GET, "/api/tags", None, "")
+            .await?;
+        let tags: TagsResponse = response
+            .json()
+            .await
+            .map_err(|e| OllamaError::from_transport(e, &self.base_url, ""))?;
+        let mut models = tags.models;
+        models.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(models)
+    }
+
+    /// Download `model`, calling `on_progress` with every status update.
+    pub async fn pull<F>(&self, model: &str, mut on_progress: F) -> Result<()>
+    where
+        F: FnMut(&PullProgress),
+    {
+        let request = ModelRequest {
+            stream: true,
+            ..ModelRequest::new(model)
+        };
+        let mut response = self
+            .request(Method::POST, "/api/pull", Some(&request), model)
+            .await?;
+
+        // One JSON object per line; a line may span several network chunks.
+        let mut pending: Vec<u8> = Vec::new();
+        let mut handle_line = |line: &[u8]| -> Result<()> {
+            let line = String::from_utf8_lossy(line);
+            let line = line.trim();
+            if line.is_empty() {
+                return Ok(());
+            }
+            let progress: PullProgress = serde_json::from_str(line)
+                .map_err(|e| OllamaError::bad_response(model, format!("invalid pull status: {}", e)))?;
+            // Failures here are about the registry, not a missing local model.
+            if let Some(error) = &progress.error {
+                return Err(anyhow::anyhow!("Cannot pull '{}': {}", model, error));
+            }
+            on_progress(&progress);
+            Ok(())
+        };
+        while let Some(bytes) = response
+            .chunk()
+            .await
+            .map_err(|e| OllamaError::from_transport(e, &self.base_url, model))?
+        {
+            pending.extend_from_slice(&bytes);
+            while let Some(newline) = pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=newline).collect();
+                handle_line(&line)?;
+            }
+        }
+        handle_line(&pending)
+    }
+
+    pub async fn show(&self, model: &str) -> Result<ModelInfo> {
+        let response = self
+            .request(Method::POST, "/api/show", Some(&ModelRequest::new(model)), model)
+            .await?;
+        let show: ShowResponse = response
+            .json()
+            .await
+            .map_err(|e| OllamaError::from_transport(e, &self.base_url, model))?;
+        let architecture = show
+            .model_info
+            .get("general.architecture")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        // Per-architecture keys, e.g. "qwen2.context_length".
+        let arch_value = |key: &str| {
+            architecture
+                .as_ref()
+                .and_then(|arch| show.model_info.get(&format!("{}.{}", arch, key)))
+                .and_then(|v| v.as_u64())
+        };
+        let context_length = arch_value("context_length");
+        let embedding_length = arch_value("embedding_length");
+        Ok(ModelInfo {
+            details: show.details,
+            architecture,
+            context_length,
+            embedding_length,
+            capabilities: show.capabilities,
+            parameters: show.parameters,
+        })
+    }
+
+    pub async fn delete(&self, model: &str) -> Result<()> {
+        self.request(Method::DELETE, "/api/delete", Some(&ModelRequest::new(model)), model)
+            .await?;
+        Ok(())
+    }
+}
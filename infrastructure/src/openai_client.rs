@@ -0,0 +1,485 @@
+use super::config::Config;
+use super::http::{build_client, send_with_retry, RetryPolicy};
+use domain::language_model::{EmbeddingModel, GenerationOptions, LanguageModel};
+use domain::session::Message;
+use domain::usage::{RequestKind, Usage, UsageMeter};
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use shared::types::Result;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::OnceCell;
+
+#[derive(Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: &'a [Message],
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionResponse {
+    choices: Vec<Choice>,
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize, Default)]
+struct TokenUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ChoiceMessage,
+}
+
+#[derive(Deserialize)]
+struct ChoiceMessage {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionChunk {
+    choices: Vec<ChunkChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    delta: ChunkDelta,
+}
+
+#[derive(Deserialize)]
+struct ChunkDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+#[derive(Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+    #[serde(default)]
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Client for servers exposing the OpenAI `/v1` API, such as llama.cpp's
+/// `llama-server`, vLLM and LM Studio.
+#[derive(Clone)]
+pub struct OpenAiClient {
+    client: Arc<Client>,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    retry: RetryPolicy,
+    dimension: Arc<OnceCell<usize>>,
+    meter: UsageMeter,
+}
+
+impl OpenAiClient {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self {
+            client: Arc::new(build_client(config)?),
+            base_url: config.openai_base_url.clone(),
+            api_key: config.openai_api_key.clone(),
+            model: config.openai_model.clone(),
+            retry: RetryPolicy::from_config(config),
+            dimension: Arc::new(OnceCell::new()),
+            meter: UsageMeter::new(),
+        })
+    }
+
+    /// Report token counts and timings of every request to `meter`.
+    pub fn with_meter(mut self, meter: UsageMeter) -> Self {
+        self.meter = meter;
+        self
+    }
+
+    fn record_usage(&self, kind: RequestKind, usage: Option<TokenUsage>, started: Instant) {
+        let usage = usage.unwrap_or_default();
+        self.meter.record(Usage {
+            model: self.model.clone(),
+            kind,
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_ms: 0,
+            load_ms: 0,
+            wall_ms: started.elapsed().as_millis() as u64,
+        });
+    }
+
+    fn post(&self, path: &str) -> RequestBuilder {
+        let request = self.client.post(format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    /// POST `body` to `path`, retrying transient failures.
+    async fn send<T: Serialize + Sync>(&self, path: &str, body: &T) -> Result<reqwest::Response> {
+        let response = send_with_retry(&self.retry, || self.post(path).json(body)).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!(
+                "OpenAI-compatible API error ({}): {}",
+                status,
+                body
+            ));
+        }
+        Ok(response)
+    }
+
+    async fn complete_chat(
+        &self,
+        messages: &[Message],
+        options: &GenerationOptions,
+        response_format: Option<serde_json::Value>,
+    ) -> Result<String> {
+        let started = Instant::now();
+        let request = ChatCompletionRequest {
+            model: &self.model,
+            messages,
+            stream: false,
+            temperature: options.temperature,
+            response_format,
+        };
+        let response = self.send("/chat/completions", &request).await?;
+        let completion: ChatCompletionResponse = response.json().await?;
+        self.record_usage(RequestKind::Chat, completion.usage, started);
+        completion
+            .choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content.unwrap_or_default())
+            .ok_or_else(|| anyhow::anyhow!("{} returned no choices", self.model))
+    }
+}
+
+/// Handle one line of a server-sent event stream. Returns `false` once the
+/// stream signals completion.
+fn handle_sse_line<F: FnMut(&str)>(line: &str, answer: &mut String, on_token: &mut F) -> bool {
+    let Some(data) = line.trim().strip_prefix("data:") else {
+        return true;
+    };
+    let data = data.trim();
+    if data == "[DONE]" {
+        return false;
+    }
+    if let Ok(chunk) = serde_json::from_str::<ChatCompletionChunk>(data) {
+        for choice in chunk.choices {
+            if let Some(token) = choice.delta.content.filter(|t| !t.is_empty()) {
+                on_token(&token);
+                answer.push_str(&token);
+            }
+        }
+    }
+    true
+}
+
+impl LanguageModel for OpenAiClient {
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    fn with_model(&self, model: &str) -> Self {
+        Self {
+            client: Arc::clone(&self.client),
+            base_url: self.base_url.clone(),
+            api_key: self.api_key.clone(),
+            model: model.to_string(),
+            retry: self.retry,
+            dimension: Arc::new(OnceCell::new()),
+            meter: self.meter.clone(),
+        }
+    }
+
+    async fn chat(&self, messages: &[Message], options: &GenerationOptions) -> Result<String> {
+        self.complete_chat(messages, options, None).await
+    }
+
+    async fn stream<F>(
+        &self,
+        messages: &[Message],
+        options: &GenerationOptions,
+        mut on_token: F,
+    ) -> Result<String>
+    where
+        F: FnMut(&str) + Send,
+    {
+        let started = Instant::now();
+        let request = ChatCompletionRequest {
+            model: &self.model,
+            messages,
+            stream: true,
+            temperature: options.temperature,
+            response_format: None,
+        };
+        let mut response = self.send("/chat/completions", &request).await?;
+
+        // Events may be split across network chunks; only complete lines are parsed.
+        let mut pending: Vec<u8> = Vec::new();
+        let mut answer = String::new();
+        let mut open = true;
+        'read: while let Some(bytes) = response.chunk().await? {
+            pending.extend_from_slice(&bytes);
+            while let Some(newline) = pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=newline).collect();
+                open = handle_sse_line(&String::from_utf8_lossy(&line), &mut answer, &mut on_token);
+                if !open {
+                    break 'read;
+                }
+            }
+        }
+        // A last event without a newline, unless the stream was already done.
+        if open && !pending.is_empty() {
+            handle_sse_line(&String::from_utf8_lossy(&pending), &mut answer, &mut on_token);
+        }
+        // Servers only report token counts for streams on request; latency is still useful.
+        self.record_usage(RequestKind::Chat, None, started);
+        Ok(answer)
+    }
+
+    async fn structured(
+        &self,
+        messages: &[Message],
+        schema: &serde_json::Value,
+        options: &GenerationOptions,
+    ) -> Result<serde_json::Value> {
+        let response_format = serde_json::json!({
+            "type": "json_schema",
+            "json_schema": { "name": "response", "schema": schema },
+        });
+        let answer = self
+            .complete_chat(messages, options, Some(response_format))
+            .await?;
+        serde_json::from_str(&answer)
+            .map_err(|e| anyhow::anyhow!("{} returned invalid JSON: {}", self.model, e))
+    }
+}
+
+impl EmbeddingModel for OpenAiClient {
+    fn embedding_model_name(&self) -> &str {
+        &self.model
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        self.embed_batch(&[text.to_string()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("{} returned no embedding", self.model))
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let started = Instant::now();
+        let request = EmbeddingsRequest {
+            model: &self.model,
+            input: texts,
+        };
+        let response = self.send("/embeddings", &request).await?;
+        let mut embeddings: EmbeddingsResponse = response.json().await?;
+        self.record_usage(RequestKind::Embed, embeddings.usage.take(), started);
+        embeddings.data.sort_by_key(|d| d.index);
+        Ok(embeddings.data.into_iter().map(|d| d.embedding).collect())
+    }
+
+    async fn dimension(&self) -> Result<usize> {
+        self.dimension
+            .get_or_try_init(|| async { Ok(self.embed("dimension probe").await?.len()) })
+            .await
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    /// A server answering one connection per response, writing each response
+    /// in the given pieces. Yields the requests it received.
+    fn serve(responses: Vec<Vec<String>>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for pieces in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                requests.push(read_request(&mut stream));
+                for piece in pieces {
+                    stream.write_all(piece.as_bytes()).unwrap();
+                    stream.flush().unwrap();
+                    thread::sleep(Duration::from_millis(20));
+                }
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    fn read_request(stream: &mut impl Read) -> String {
+        let mut raw = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut buf).unwrap();
+            raw.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&raw);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .find_map(|l| {
+                        let l = l.to_lowercase();
+                        l.strip_prefix("content-length:")?.trim().parse::<usize>().ok()
+                    })
+                    .unwrap_or(0);
+                if raw.len() >= end + 4 + length || n == 0 {
+                    return text.to_string();
+                }
+            }
+        }
+    }
+
+    fn json_response(body: &str) -> Vec<String> {
+        vec![format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )]
+    }
+
+    fn client(url: &str, api_key: Option<&str>) -> OpenAiClient {
+        OpenAiClient::from_config(&Config {
+            openai_base_url: url.to_string(),
+            openai_api_key: api_key.map(str::to_string),
+            openai_model: "test-model".to_string(),
+            max_retries: 0,
+            ..Config::load()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_chat_returns_first_choice_and_sends_key() {
+        let (url, server) = serve(vec![json_response(
+            r#"{"choices":[{"message":{"content":"hello there"}}],"usage":{"prompt_tokens":7,"completion_tokens":2}}"#,
+        )]);
+        let meter = UsageMeter::new();
+        let llm = client(&url, Some("secret-key")).with_meter(meter.clone());
+        let answer = llm
+            .chat(&[Message::user("hi")], &GenerationOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(answer, "hello there");
+
+        let request = &server.join().unwrap()[0];
+        assert!(request.starts_with("POST /v1/chat/completions "));
+        assert!(request.to_lowercase().contains("authorization: bearer secret-key"));
+        assert!(request.contains(r#""model":"test-model""#));
+        assert!(request.contains(r#""stream":false"#));
+        let usage = meter.take();
+        assert_eq!((usage[0].prompt_tokens, usage[0].completion_tokens), (7, 2));
+    }
+
+    #[tokio::test]
+    async fn test_no_key_sends_no_authorization() {
+        let (url, server) = serve(vec![json_response(r#"{"choices":[]}"#)]);
+        let err = client(&url, None)
+            .chat(&[Message::user("hi")], &GenerationOptions::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("no choices"));
+        assert!(!server.join().unwrap()[0].to_lowercase().contains("authorization:"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_joins_events_split_across_chunks() {
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n";
+        let (url, server) = serve(vec![vec![
+            head.to_string(),
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\ndata: {\"choices\":[{\"del".to_string(),
+            "ta\":{\"content\":\"lo\"}}]}\n\n: keep-alive\n\n".to_string(),
+            "data: [DONE]\n\ndata: {\"choices\":[{\"delta\":{\"content\":\"!\"}}]}\n\n".to_string(),
+        ]]);
+        let mut tokens = Vec::new();
+        let answer = client(&url, None)
+            .stream(&[Message::user("hi")], &GenerationOptions::default(), |t| {
+                tokens.push(t.to_string())
+            })
+            .await
+            .unwrap();
+        assert_eq!(answer, "Hello");
+        assert_eq!(tokens, vec!["Hel", "lo"]);
+        assert!(server.join().unwrap()[0].contains(r#""stream":true"#));
+    }
+
+    #[test]
+    fn test_sse_lines() {
+        let mut answer = String::new();
+        let mut tokens = Vec::new();
+        let mut on_token = |t: &str| tokens.push(t.to_string());
+        assert!(handle_sse_line("event: message", &mut answer, &mut on_token));
+        assert!(handle_sse_line("data: not json", &mut answer, &mut on_token));
+        assert!(handle_sse_line(
+            "data:{\"choices\":[{\"delta\":{\"content\":\"\"}},{\"delta\":{\"content\":\"a\"}}]}\r\n",
+            &mut answer,
+            &mut on_token
+        ));
+        assert!(!handle_sse_line("data: [DONE]\r\n", &mut answer, &mut on_token));
+        assert_eq!(answer, "a");
+        assert_eq!(tokens, vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_are_ordered_by_index() {
+        let (url, server) = serve(vec![json_response(
+            r#"{"data":[{"index":2,"embedding":[2.0]},{"index":0,"embedding":[0.0]},{"index":1,"embedding":[1.0]}]}"#,
+        )]);
+        let texts: Vec<String> = ["a", "b", "c"].iter().map(|t| t.to_string()).collect();
+        let vectors = client(&url, None).embed_batch(&texts).await.unwrap();
+        assert_eq!(vectors, vec![vec![0.0], vec![1.0], vec![2.0]]);
+        let request = &server.join().unwrap()[0];
+        assert!(request.starts_with("POST /v1/embeddings "));
+        assert!(request.contains(r#""input":["a","b","c"]"#));
+    }
+
+    #[tokio::test]
+    async fn test_error_status_includes_body() {
+        let body = r#"{"error":"model not loaded"}"#;
+        let (url, server) = serve(vec![vec![format!(
+            "HTTP/1.1 404 Not Found\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )]]);
+        let err = client(&url, None).embed("a").await.unwrap_err().to_string();
+        assert!(err.contains("404"));
+        assert!(err.contains("model not loaded"));
+        server.join().unwrap();
+    }
+}
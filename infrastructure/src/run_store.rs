@@ -0,0 +1,136 @@
+use domain::agent_run::AgentRun;
+use shared::types::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Stores agent runs as one JSON file per run (`<dir>/<id>.json`).
+pub struct RunStore {
+    dir: PathBuf,
+}
+
+impl RunStore {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The file of run `id`. Ids that could name a file outside the store
+    /// (path separators or `..`) are rejected.
+    pub fn path_for(&self, id: &str) -> Result<PathBuf> {
+        if id.is_empty() || id.contains(['/', '\\']) || id.contains("..") {
+            return Err(anyhow::anyhow!("Invalid agent run id '{}'", id));
+        }
+        Ok(self.dir.join(format!("{id}.json")))
+    }
+
+    pub fn save(&self, run: &AgentRun) -> Result<()> {
+        let path = self.path_for(&run.id)?;
+        fs::create_dir_all(&self.dir)?;
+        let serialized = serde_json::to_string_pretty(run)?;
+        // Write then rename so an interrupted save never leaves a truncated record.
+        let tmp = self.dir.join(format!("{}.json.tmp", run.id));
+        fs::write(&tmp, serialized)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load(&self, id: &str) -> Result<AgentRun> {
+        let path = self.path_for(id)?;
+        let data = fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("No agent run '{}' ({}): {}", id, path.display(), e))?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// All readable runs, newest first. Unreadable files are skipped.
+    pub fn list(&self) -> Result<Vec<AgentRun>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut runs = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Ok(data) = fs::read_to_string(&path) else {
+                continue;
+            };
+            if let Ok(run) = serde_json::from_str::<AgentRun>(&data) {
+                runs.push(run);
+            }
+        }
+        runs.sort_by_key(|run| std::cmp::Reverse(run.created_at));
+        Ok(runs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RunStore;
+    use domain::agent_run::{AgentRun, StepStatus};
+    use domain::command_plan::{CommandPlan, PlanStep};
+
+    fn temp_store(name: &str) -> (RunStore, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("run_store_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        (RunStore::new(&dir), dir)
+    }
+
+    fn run_with_id(id: &str) -> AgentRun {
+        let plan = CommandPlan::new(id.into(), "task".into(), vec![PlanStep::new("ls")]);
+        AgentRun::new("task", "model", plan)
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let (store, dir) = temp_store("round_trip");
+        let mut run = run_with_id("100-abcd");
+        run.mark_started(0);
+        run.mark_finished(0, Some(0), "file\n".into());
+        store.save(&run).unwrap();
+
+        let loaded = store.load("100-abcd").unwrap();
+        assert_eq!(loaded.id, run.id);
+        assert_eq!(loaded.task, "task");
+        assert_eq!(loaded.plan.steps[0].command, "ls");
+        assert_eq!(loaded.steps[0].status, StepStatus::Completed);
+        assert_eq!(loaded.steps[0].output, "file\n");
+        assert!(!dir.join("100-abcd.json.tmp").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_list_is_newest_first() {
+        let (store, dir) = temp_store("list");
+        assert!(store.list().unwrap().is_empty());
+        let mut old = run_with_id("1-old");
+        old.created_at = 1;
+        let mut new = run_with_id("2-new");
+        new.created_at = 2;
+        store.save(&old).unwrap();
+        store.save(&new).unwrap();
+        std::fs::write(dir.join("broken.json"), "{").unwrap();
+
+        let ids: Vec<String> = store.list().unwrap().into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec!["2-new", "1-old"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_missing_run_is_an_error() {
+        let (store, _) = temp_store("missing");
+        assert!(store.load("404-none").is_err());
+    }
+
+    #[test]
+    fn test_ids_cannot_leave_the_store() {
+        let (store, _) = temp_store("ids");
+        for id in ["../secret", "a/b", "a\\b", "..", ""] {
+            assert!(store.path_for(id).is_err(), "{id} was accepted");
+            assert!(store.load(id).is_err());
+        }
+        assert!(store.path_for("1700000000-0a1b").is_ok());
+        assert!(store.save(&run_with_id("../escape")).is_err());
+    }
+}
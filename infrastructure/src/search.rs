@@ -1,65 +1,232 @@
-use domain::models::Embedding;
+use super::config::Config;
+use super::vector_codec::dot;
+use domain::models::SearchHit;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 
-pub struct SearchEngine;
+/// Damping constant of reciprocal rank fusion. The usual 60 keeps a single
+/// list's top hit from outweighing chunks ranked well by both lists.
+const RRF_K: f32 = 60.0;
 
-impl SearchEngine {
-    pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-        let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-        let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-        let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-        dot_product / (norm_a * norm_b)
-    }
+/// How many retrieved chunks reach the prompt and how they are picked.
+#[derive(Debug, Clone, Copy)]
+pub struct Selection {
+    pub top_k: usize,
+    /// Weight of relevance against novelty in maximal marginal relevance;
+    /// 1.0 ranks by relevance alone.
+    pub mmr_lambda: f32,
+    /// Most chunks taken from one file; 0 for no limit.
+    pub max_per_file: usize,
+    /// Chunks less similar to the question are dropped unless the keyword
+    /// search found them too.
+    pub min_similarity: f32,
+}
 
-    pub fn find_relevant_chunks(
-        query_embedding: &[f32],
-        embeddings: &[Embedding],
-        top_k: usize,
-    ) -> Vec<String> {
-        use std::collections::BinaryHeap;
-
-        #[derive(Debug)]
-        struct Scored<'a> {
-            score: f32,
-            text: &'a str,
+impl Selection {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            top_k: config.rag_top_k,
+            mmr_lambda: config.rag_mmr_lambda,
+            max_per_file: config.rag_max_chunks_per_file,
+            min_similarity: config.rag_min_similarity,
         }
+    }
+
+    /// Whether `candidate` is similar enough to the question to be picked.
+    pub fn admits(&self, candidate: &Candidate) -> bool {
+        candidate.keyword_match || candidate.hit.score >= self.min_similarity
+    }
+}
+
+/// A retrieved chunk competing for a place in the prompt.
+pub struct Candidate<'a> {
+    /// Id of the stored chunk.
+    pub id: &'a str,
+    /// The chunk, scored by its cosine similarity to the question.
+    pub hit: SearchHit,
+    /// Rank-fusion score, scaled so the best candidate has 1.0.
+    pub relevance: f32,
+    /// Unit-length embedding of the chunk.
+    pub vector: &'a [f32],
+    /// Whether the keyword search matched the chunk.
+    pub keyword_match: bool,
+}
 
-        impl<'a> PartialEq for Scored<'a> {
-            fn eq(&self, other: &Self) -> bool {
-                self.score.eq(&other.score)
+pub struct SearchEngine;
+
+impl SearchEngine {
+    /// Merge rankings of chunk ids (best first), such as nearest-neighbour and
+    /// BM25 results, by reciprocal rank fusion. Chunks ranked well by several
+    /// lists come first; at most `top_k` ids are returned with their fused score.
+    pub fn reciprocal_rank_fusion(rankings: &[Vec<String>], top_k: usize) -> Vec<(String, f32)> {
+        let mut fused: HashMap<&str, f32> = HashMap::new();
+        for ranking in rankings {
+            for (rank, id) in ranking.iter().take(top_k).enumerate() {
+                *fused.entry(id.as_str()).or_insert(0.0) += 1.0 / (RRF_K + rank as f32 + 1.0);
             }
         }
-        impl<'a> Eq for Scored<'a> {}
-        impl<'a> PartialOrd for Scored<'a> {
-            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-                self.score.partial_cmp(&other.score)
+        let mut ranked: Vec<(&str, f32)> = fused.into_iter().collect();
+        // Ties fall back to the id so the order does not depend on hashing.
+        ranked.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.0.cmp(b.0))
+        });
+        ranked
+            .into_iter()
+            .take(top_k)
+            .map(|(id, score)| (id.to_string(), score))
+            .collect()
+    }
+
+    /// Pick up to `selection.top_k` candidates by maximal marginal relevance:
+    /// each pick trades relevance against similarity to the chunks already
+    /// picked, so overlapping chunks of one passage do not crowd out the rest
+    /// of the codebase. Files stop contributing once they reach the per-file
+    /// cap.
+    pub fn select(candidates: Vec<Candidate>, selection: &Selection) -> Vec<SearchHit> {
+        let mut remaining: Vec<Candidate> = candidates
+            .into_iter()
+            .filter(|c| selection.admits(c))
+            .collect();
+        let mut picked: Vec<Candidate> = Vec::new();
+        let mut per_file: HashMap<String, usize> = HashMap::new();
+
+        while picked.len() < selection.top_k {
+            if selection.max_per_file > 0 {
+                remaining.retain(|c| {
+                    per_file.get(&c.hit.path).copied().unwrap_or(0) < selection.max_per_file
+                });
             }
+            let best = remaining
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    let redundancy = picked
+                        .iter()
+                        .map(|p| dot(c.vector, p.vector))
+                        .fold(0.0f32, f32::max);
+                    let mmr = selection.mmr_lambda * c.relevance
+                        - (1.0 - selection.mmr_lambda) * redundancy;
+                    (i, mmr)
+                })
+                // On ties the earlier, more relevant candidate wins.
+                .reduce(|best, next| if next.1 > best.1 { next } else { best });
+            let Some((i, _)) = best else {
+                break;
+            };
+            let candidate = remaining.remove(i);
+            *per_file.entry(candidate.hit.path.clone()).or_insert(0) += 1;
+            picked.push(candidate);
         }
-        impl<'a> Ord for Scored<'a> {
-            fn cmp(&self, other: &Self) -> Ordering {
-                self.partial_cmp(other).unwrap_or(Ordering::Equal)
-            }
+        picked.into_iter().map(|c| c.hit).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn candidate<'a>(id: &'a str, path: &str, relevance: f32, vector: &'a [f32]) -> Candidate<'a> {
+        Candidate {
+            id,
+            hit: SearchHit {
+                path: path.to_string(),
+                start_line: 1,
+                end_line: 10,
+                symbol: None,
+                text: id.to_string(),
+                score: 0.8,
+            },
+            relevance,
+            vector,
+            keyword_match: false,
         }
+    }
 
-        let mut heap: BinaryHeap<Scored> =
-            BinaryHeap::with_capacity(top_k.saturating_mul(2).max(8));
-        for emb in embeddings {
-            let score = Self::cosine_similarity(query_embedding, &emb.vector);
-            heap.push(Scored {
-                score,
-                text: emb.text.as_str(),
-            });
-            if heap.len() > top_k * 3 {
-                heap.pop();
-            }
+    fn selection(top_k: usize) -> Selection {
+        Selection {
+            top_k,
+            mmr_lambda: 0.7,
+            max_per_file: 0,
+            min_similarity: 0.25,
         }
+    }
 
-        let mut results: Vec<Scored> = heap.into_iter().collect();
-        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
-        results
-            .into_iter()
-            .take(top_k)
-            .map(|s| s.text.to_string())
-            .collect()
+    fn picked(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|h| h.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fusion_prefers_chunks_in_both_lists() {
+        let fused = SearchEngine::reciprocal_rank_fusion(
+            &[ids(&["a", "b", "c"]), ids(&["d", "b"])],
+            10,
+        );
+        let order: Vec<&str> = fused.iter().map(|(id, _)| id.as_str()).collect();
+        // `b` is second in both lists; `a` and `d` tie as single-list leaders.
+        assert_eq!(order, ["b", "a", "d", "c"]);
+        assert!((fused[1].1 - 1.0 / (RRF_K + 1.0)).abs() < 1e-6);
+
+        let fused = SearchEngine::reciprocal_rank_fusion(&[ids(&["a", "b", "c"])], 2);
+        assert_eq!(fused.len(), 2);
+    }
+
+    #[test]
+    fn test_near_duplicates_are_skipped() {
+        let candidates = vec![
+            candidate("first", "src/a.rs", 1.0, &[1.0, 0.0]),
+            candidate("copy", "src/a.rs", 0.95, &[0.999, 0.045]),
+            candidate("other", "src/b.rs", 0.6, &[0.0, 1.0]),
+        ];
+        let hits = SearchEngine::select(candidates, &selection(2));
+        assert_eq!(picked(&hits), ["first", "other"]);
+
+        // Relevance alone keeps the duplicate.
+        let candidates = vec![
+            candidate("first", "src/a.rs", 1.0, &[1.0, 0.0]),
+            candidate("copy", "src/a.rs", 0.95, &[0.999, 0.045]),
+            candidate("other", "src/b.rs", 0.6, &[0.0, 1.0]),
+        ];
+        let relevance_only = Selection {
+            mmr_lambda: 1.0,
+            ..selection(2)
+        };
+        let hits = SearchEngine::select(candidates, &relevance_only);
+        assert_eq!(picked(&hits), ["first", "copy"]);
+    }
+
+    #[test]
+    fn test_per_file_cap() {
+        let candidates = vec![
+            candidate("a1", "src/a.rs", 1.0, &[1.0, 0.0, 0.0]),
+            candidate("a2", "src/a.rs", 0.9, &[0.0, 1.0, 0.0]),
+            candidate("a3", "src/a.rs", 0.8, &[0.0, 0.0, 1.0]),
+            candidate("b1", "src/b.rs", 0.3, &[0.0, 0.0, -1.0]),
+        ];
+        let capped = Selection {
+            max_per_file: 2,
+            mmr_lambda: 1.0,
+            ..selection(10)
+        };
+        let hits = SearchEngine::select(candidates, &capped);
+        assert_eq!(picked(&hits), ["a1", "a2", "b1"]);
+    }
+
+    #[test]
+    fn test_similarity_floor_drops_weak_vector_hits() {
+        let mut weak = candidate("weak", "src/a.rs", 0.9, &[0.0, 1.0]);
+        weak.hit.score = 0.1;
+        let mut keyword = candidate("keyword", "src/b.rs", 0.5, &[1.0, 0.0]);
+        keyword.hit.score = 0.1;
+        keyword.keyword_match = true;
+        let strong = candidate("strong", "src/c.rs", 0.4, &[0.6, 0.8]);
+
+        let hits = SearchEngine::select(vec![weak, keyword, strong], &selection(10));
+        assert_eq!(picked(&hits), ["keyword", "strong"]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,53 @@
+use domain::usage::StatsEvent;
+use shared::types::Result;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Append-only log of usage and cache events, one JSON object per line.
+pub struct StatsStore {
+    path: PathBuf,
+}
+
+impl StatsStore {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, events: &[StatsEvent]) -> Result<()> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut lines = String::new();
+        for event in events {
+            lines.push_str(&serde_json::to_string(event)?);
+            lines.push('\n');
+        }
+        // A single write keeps concurrent invocations from interleaving lines.
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(lines.as_bytes())?;
+        Ok(())
+    }
+
+    /// Every event recorded at or after `since` (unix seconds). Unreadable lines are skipped.
+    pub fn load_since(&self, since: u64) -> Result<Vec<StatsEvent>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let data = fs::read_to_string(&self.path)?;
+        Ok(data
+            .lines()
+            .filter_map(|line| serde_json::from_str::<StatsEvent>(line).ok())
+            .filter(|event| event.timestamp() >= since)
+            .collect())
+    }
+}
@@ -0,0 +1,451 @@
+use super::vector_codec::{dot, normalized};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use shared::types::Result;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Layout of the saved index, written ahead of it; other layouts are rebuilt.
+const INDEX_VERSION: u32 = 2;
+/// Below this many vectors every query scans them all, which is exact and
+/// still fast; clustering only pays off for larger indexes.
+const FLAT_LIMIT: usize = 2048;
+/// Lloyd iterations per training run.
+const TRAIN_ITERATIONS: usize = 10;
+/// Fraction of the lists scanned per query, as a divisor of the list count.
+const PROBE_DIVISOR: usize = 8;
+/// Fewest lists scanned per query, so small clusterings keep good recall.
+const MIN_PROBES: usize = 4;
+
+/// One inverted list: its vectors form a row-major matrix, row `i` being
+/// the chunk `ids[i]`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct IndexList {
+    ids: Vec<String>,
+    paths: Vec<String>,
+    /// Unit-length rows, so a dot product is the cosine similarity.
+    vectors: Vec<f32>,
+}
+
+impl IndexList {
+    fn row(&self, i: usize, dimension: usize) -> &[f32] {
+        &self.vectors[i * dimension..(i + 1) * dimension]
+    }
+
+    fn push(&mut self, id: String, path: String, vector: &[f32]) {
+        self.ids.push(id);
+        self.paths.push(path);
+        self.vectors.extend_from_slice(vector);
+    }
+
+    fn swap_remove(&mut self, i: usize, dimension: usize) {
+        let last = self.ids.len() - 1;
+        self.ids.swap_remove(i);
+        self.paths.swap_remove(i);
+        if i != last {
+            self.vectors
+                .copy_within(last * dimension..(last + 1) * dimension, i * dimension);
+        }
+        self.vectors.truncate(last * dimension);
+    }
+
+    /// Keep only the rows whose path satisfies `keep`.
+    fn retain_paths(&mut self, dimension: usize, keep: impl Fn(&str) -> bool) {
+        let mut kept = IndexList::default();
+        for i in 0..self.ids.len() {
+            if keep(&self.paths[i]) {
+                kept.push(
+                    std::mem::take(&mut self.ids[i]),
+                    std::mem::take(&mut self.paths[i]),
+                    self.row(i, dimension),
+                );
+            }
+        }
+        *self = kept;
+    }
+}
+
+/// An inverted-file (IVF) index over chunk vectors. Vectors are clustered
+/// around k-means centroids and a query only scans the lists whose centroids
+/// are closest to it, in parallel. Small indexes keep a single list and are
+/// searched exhaustively.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VectorIndex {
+    dimension: usize,
+    /// Empty while the index is flat.
+    centroids: Vec<Vec<f32>>,
+    lists: Vec<IndexList>,
+    /// Size of the index when the centroids were last trained.
+    trained_on: usize,
+    /// Chunk id -> list holding it; rebuilt on load.
+    #[serde(skip)]
+    locations: HashMap<String, usize>,
+}
+
+fn nearest_centroid(centroids: &[Vec<f32>], vector: &[f32]) -> usize {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i, dot(c, vector)))
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+impl VectorIndex {
+    /// The index saved at `path`, or `None` when it is missing or unreadable.
+    pub fn load(path: impl AsRef<Path>) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        let (version, mut index): (u32, Self) = bincode::deserialize(&bytes).ok()?;
+        if version != INDEX_VERSION {
+            return None;
+        }
+        index.rebuild_locations();
+        Some(index)
+    }
+
+    /// Write the index to `path` through a temporary file, so a crash never
+    /// leaves a truncated index behind.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bincode::serialize(&(INDEX_VERSION, self))?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    /// Number of inverted lists; 1 while the index is flat.
+    pub fn list_count(&self) -> usize {
+        self.lists.len()
+    }
+
+    /// Length of the stored vectors, or `None` while the index is empty.
+    pub fn dimension(&self) -> Option<usize> {
+        (!self.is_empty()).then_some(self.dimension)
+    }
+
+    /// Add or replace the vector for chunk `id`. Fails, leaving the index
+    /// unchanged, when the vector is empty or its length differs from the
+    /// stored vectors'.
+    pub fn insert(&mut self, id: &str, path: &str, vector: &[f32]) -> Result<()> {
+        if vector.is_empty() || self.dimension().is_some_and(|d| d != vector.len()) {
+            return Err(anyhow::anyhow!(
+                "Vector for {} has {} dimensions; the index holds {}",
+                id,
+                vector.len(),
+                self.dimension
+            ));
+        }
+        self.remove(id);
+        if self.is_empty() {
+            self.dimension = vector.len();
+        }
+        if self.lists.is_empty() {
+            self.lists.push(IndexList::default());
+        }
+        let vector = normalized(vector);
+        let list = if self.centroids.is_empty() {
+            0
+        } else {
+            nearest_centroid(&self.centroids, &vector)
+        };
+        self.lists[list].push(id.to_string(), path.to_string(), &vector);
+        self.locations.insert(id.to_string(), list);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        if let Some(list) = self.locations.remove(id) {
+            let list = &mut self.lists[list];
+            if let Some(i) = list.ids.iter().position(|e| e == id) {
+                list.swap_remove(i, self.dimension);
+            }
+        }
+        self.reset_if_empty();
+    }
+
+    /// Drop every chunk of the file at `path`.
+    pub fn remove_path(&mut self, path: &str) {
+        let dimension = self.dimension;
+        for list in &mut self.lists {
+            list.retain_paths(dimension, |p| p != path);
+        }
+        self.rebuild_locations();
+        self.reset_if_empty();
+    }
+
+    /// Forget the dimension and clustering of an emptied index, so vectors
+    /// from another model can be added and small indexes are flat again.
+    fn reset_if_empty(&mut self) {
+        if self.is_empty() {
+            *self = Self::default();
+        }
+    }
+
+    /// Re-cluster once the index has outgrown its centroids, keeping list
+    /// sizes near the square root of the index size.
+    pub fn maybe_retrain(&mut self) {
+        let len = self.len();
+        if len <= FLAT_LIMIT || len < self.trained_on * 2 {
+            return;
+        }
+        let dimension = self.dimension;
+        let lists = ((len as f64).sqrt() as usize).clamp(8, 1024);
+        let mut all = IndexList::default();
+        for mut list in self.lists.drain(..) {
+            all.ids.append(&mut list.ids);
+            all.paths.append(&mut list.paths);
+            all.vectors.append(&mut list.vectors);
+        }
+
+        // Seed with evenly spaced rows, which is deterministic and spreads
+        // the seeds across files since rows arrive grouped by file.
+        let step = len / lists;
+        let mut centroids: Vec<Vec<f32>> =
+            (0..lists).map(|i| all.row(i * step, dimension).to_vec()).collect();
+        let mut assignment = vec![0; len];
+        for _ in 0..TRAIN_ITERATIONS {
+            assignment = all
+                .vectors
+                .par_chunks_exact(dimension)
+                .map(|row| nearest_centroid(&centroids, row))
+                .collect();
+            let mut sums = vec![vec![0.0f32; dimension]; lists];
+            for (row, &list) in all.vectors.chunks_exact(dimension).zip(&assignment) {
+                for (sum, x) in sums[list].iter_mut().zip(row) {
+                    *sum += x;
+                }
+            }
+            for (centroid, sum) in centroids.iter_mut().zip(sums) {
+                // An empty cluster keeps its previous centroid.
+                if sum.iter().any(|x| *x != 0.0) {
+                    *centroid = normalized(&sum);
+                }
+            }
+        }
+
+        let mut grouped: Vec<IndexList> = (0..lists).map(|_| IndexList::default()).collect();
+        for (i, list) in assignment.into_iter().enumerate() {
+            grouped[list].push(
+                std::mem::take(&mut all.ids[i]),
+                std::mem::take(&mut all.paths[i]),
+                all.row(i, dimension),
+            );
+        }
+        self.centroids = centroids;
+        self.lists = grouped;
+        self.trained_on = len;
+        self.rebuild_locations();
+    }
+
+    /// Ids of the `top_k` chunks most similar to `query`, best first, with
+    /// their cosine similarity. Empty when `query` has another dimension.
+    pub fn search(&self, query: &[f32], top_k: usize) -> Vec<(String, f32)> {
+        if top_k == 0 || query.len() != self.dimension {
+            return Vec::new();
+        }
+        let query = normalized(query);
+        let probed: Vec<&IndexList> = if self.centroids.is_empty() {
+            self.lists.iter().collect()
+        } else {
+            let probes = (self.centroids.len() / PROBE_DIVISOR).max(MIN_PROBES);
+            let mut order: Vec<(usize, f32)> = self
+                .centroids
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (i, dot(c, &query)))
+                .collect();
+            order.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+            order.iter().take(probes).map(|(i, _)| &self.lists[*i]).collect()
+        };
+
+        let mut scored: Vec<(f32, &String)> = probed
+            .into_par_iter()
+            .flat_map(|list| {
+                list.vectors
+                    .par_chunks_exact(self.dimension)
+                    .zip(list.ids.par_iter())
+                    .map(|(row, id)| (dot(&query, row), id))
+            })
+            .collect();
+        let best_first = |a: &(f32, &String), b: &(f32, &String)| {
+            b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal)
+        };
+        if scored.len() > top_k {
+            scored.select_nth_unstable_by(top_k - 1, best_first);
+            scored.truncate(top_k);
+        }
+        scored.sort_by(best_first);
+        scored.into_iter().map(|(score, id)| (id.clone(), score)).collect()
+    }
+
+    /// The unit-length vector of chunk `id`.
+    pub fn vector(&self, id: &str) -> Option<&[f32]> {
+        let list = &self.lists[*self.locations.get(id)?];
+        let i = list.ids.iter().position(|e| e == id)?;
+        Some(list.row(i, self.dimension))
+    }
+
+    /// Cosine similarity of chunk `id` to `query`, which must be unit length.
+    pub fn similarity(&self, id: &str, query: &[f32]) -> Option<f32> {
+        if query.len() != self.dimension {
+            return None;
+        }
+        Some(dot(query, self.vector(id)?))
+    }
+
+    fn rebuild_locations(&mut self) {
+        self.locations = self
+            .lists
+            .iter()
+            .enumerate()
+            .flat_map(|(list, rows)| rows.ids.iter().map(move |id| (id.clone(), list)))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pseudo-random vector of `dimension` values, the same for every `i`.
+    fn vector(i: usize, dimension: usize) -> Vec<f32> {
+        (0..dimension)
+            .map(|d| {
+                let x = ((i * dimension + d) as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15);
+                (x >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_search_and_remove() {
+        let mut index = VectorIndex::default();
+        index.insert("a", "a.rs", &[1.0, 0.0, 0.0]).unwrap();
+        index.insert("b", "b.rs", &[0.0, 2.0, 0.0]).unwrap();
+        index.insert("c", "b.rs", &[0.7, 0.7, 0.0]).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.dimension(), Some(3));
+
+        let hits = index.search(&[1.0, 0.1, 0.0], 2);
+        let ids: Vec<&str> = hits.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "c"]);
+        assert!(hits[0].1 > hits[1].1);
+        // Stored vectors are unit length.
+        assert!((index.similarity("b", &[0.0, 1.0, 0.0]).unwrap() - 1.0).abs() < 1e-6);
+        assert!(index.search(&[1.0, 0.0], 2).is_empty());
+        assert!(index.search(&[1.0, 0.0, 0.0], 0).is_empty());
+
+        // Replacing keeps one row per id.
+        index.insert("a", "a.rs", &[0.0, 0.0, 1.0]).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.search(&[0.0, 0.0, 1.0], 1)[0].0, "a");
+
+        index.remove("c");
+        assert_eq!(index.len(), 2);
+        assert!(index.vector("c").is_none());
+        assert!(index.vector("b").is_some());
+        index.remove_path("b.rs");
+        let ids: Vec<String> = index.search(&[1.0, 1.0, 1.0], 10).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_vectors_of_another_dimension_are_refused() {
+        let mut index = VectorIndex::default();
+        index.insert("a", "a.rs", &[1.0, 0.0]).unwrap();
+        assert!(index.insert("b", "b.rs", &[1.0, 0.0, 0.0]).is_err());
+        assert!(index.insert("a", "a.rs", &[1.0]).is_err());
+        assert!(index.insert("c", "c.rs", &[]).is_err());
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.vector("a").unwrap(), &[1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_emptied_index_starts_over() {
+        let mut index = VectorIndex::default();
+        for i in 0..FLAT_LIMIT + 1 {
+            index.insert(&i.to_string(), &format!("{}.rs", i % 50), &vector(i, 8)).unwrap();
+        }
+        index.maybe_retrain();
+        assert!(index.list_count() > 1);
+
+        for i in 0..50 {
+            index.remove_path(&format!("{}.rs", i));
+        }
+        assert!(index.is_empty());
+        assert_eq!(index.dimension(), None);
+        assert_eq!(index.list_count(), 0);
+
+        // A new model's vectors fit, and the index is flat again.
+        index.insert("x", "x.rs", &[1.0, 0.0, 0.0, 0.0]).unwrap();
+        index.remove("x");
+        index.insert("y", "y.rs", &[0.0, 1.0]).unwrap();
+        assert_eq!(index.dimension(), Some(2));
+        assert_eq!(index.list_count(), 1);
+        assert_eq!(index.search(&[0.0, 1.0], 1)[0].0, "y");
+    }
+
+    #[test]
+    fn test_retrain_past_flat_limit_keeps_every_vector() {
+        let mut index = VectorIndex::default();
+        let count = FLAT_LIMIT + 100;
+        for i in 0..count {
+            index.insert(&i.to_string(), "a.rs", &vector(i, 16)).unwrap();
+        }
+        index.maybe_retrain();
+        assert_eq!(index.list_count(), ((count as f64).sqrt() as usize).clamp(8, 1024));
+        assert_eq!(index.len(), count);
+
+        // Probing finds an exact match, and inserts go to the nearest list.
+        for i in [0, 777, count - 1] {
+            let hits = index.search(&vector(i, 16), 1);
+            assert!(hits[0].1 > 0.999, "{}: {:?}", i, hits);
+        }
+        index.insert("new", "b.rs", &vector(5, 16)).unwrap();
+        assert!(index.search(&vector(5, 16), 2).iter().any(|(id, _)| id == "new"));
+
+        // Not retrained again until it has doubled.
+        let lists = index.list_count();
+        index.insert("more", "b.rs", &vector(6, 16)).unwrap();
+        index.maybe_retrain();
+        assert_eq!(index.list_count(), lists);
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let dir = std::env::temp_dir().join(format!("vector_index_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.ivf");
+        assert!(VectorIndex::load(&path).is_none());
+
+        let mut index = VectorIndex::default();
+        for i in 0..FLAT_LIMIT + 1 {
+            index.insert(&i.to_string(), "a.rs", &vector(i, 8)).unwrap();
+        }
+        index.maybe_retrain();
+        index.save(&path).unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        let loaded = VectorIndex::load(&path).unwrap();
+        assert_eq!(loaded.len(), index.len());
+        assert_eq!(loaded.list_count(), index.list_count());
+        assert_eq!(loaded.vector("42"), index.vector("42"));
+        assert_eq!(loaded.search(&vector(42, 8), 3), index.search(&vector(42, 8), 3));
+
+        std::fs::write(&path, b"not an index").unwrap();
+        assert!(VectorIndex::load(&path).is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
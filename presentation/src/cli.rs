@@ -1,15 +1,36 @@
+use application::citations::RagAnswer;
+use application::command_vote::tally_votes;
 use application::rag_service::RagService;
-use clap::Parser;
+use application::script_export::render_plan_script;
+use application::usage_stats::{summarize, Percentiles};
+use crate::doctor::{self, CacheFormat};
+use crate::plan_editor::{edit_plan, PlanDecision};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
 use colored::Colorize;
 use docx_rs::*;
-use infrastructure::{config::Config, ollama_client::OllamaClient};
+use domain::agent_run::{new_run_id, AgentRun, StepStatus};
+use domain::command_plan::{CommandPlan, PlanStep};
+use domain::language_model::{EmbeddingModel, GenerationOptions, LanguageModel};
+use domain::session::Message;
+use domain::usage::{StatsEvent, UsageMeter};
+use infrastructure::{
+    config::{Config, LlmBackend},
+    model_backend::ModelBackend,
+    ollama_error::OllamaError,
+    ollama_models::{OllamaModels, PullProgress},
+    run_store::RunStore,
+    stats_store::StatsStore,
+};
 use serde::{Deserialize, Serialize};
 use shared::confirmation::ask_confirmation;
+use shared::shell::check_syntax;
 use shared::types::Result;
+use shared::utils::{format_utc, unix_timestamp};
 use std::collections::HashSet;
 use std::hash::{Hash, Hasher};
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 fn find_project_root() -> Option<String> {
     let mut current = std::env::current_dir().ok()?;
@@ -154,6 +175,25 @@ struct ExplainCacheEntry {
     timestamp: u64,
 }
 
+impl ExplainCacheFile {
+    /// The cache stored at `path`; empty when it is missing or unreadable,
+    /// including caches from versions that wrote it as JSON.
+    fn read(path: &Path) -> Self {
+        std::fs::read(path)
+            .ok()
+            .and_then(|data| bincode::deserialize(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, bincode::serialize(self)?)?;
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize, Default)]
 struct RagCacheFile {
     entries: Vec<RagCacheEntry>,
@@ -257,27 +297,60 @@ fn extract_json_array(text: &str) -> Option<&str> {
     None
 }
 
-/// Parse agent response into a list of commands
-fn parse_agent_plan(raw: &str) -> Vec<String> {
+/// A plan step as returned by the model: either a bare command or a full object.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawPlanStep {
+    Command(String),
+    Step(PlanStep),
+}
+
+fn parse_plan_json(json: &str) -> Option<Vec<PlanStep>> {
+    let raw: Vec<RawPlanStep> = serde_json::from_str(json).ok()?;
+    Some(
+        raw.into_iter()
+            .map(|step| match step {
+                RawPlanStep::Command(command) => PlanStep::new(command),
+                RawPlanStep::Step(step) => step,
+            })
+            .filter(|step| !step.command.trim().is_empty())
+            .collect(),
+    )
+}
+
+/// Parse an agent response that contains a JSON plan, tolerating noise around it
+fn parse_structured_plan(raw: &str) -> Option<Vec<PlanStep>> {
     // Try plain parse
-    if let Ok(cmds) = serde_json::from_str::<Vec<String>>(raw) {
-        return cmds;
+    if let Some(steps) = parse_plan_json(raw) {
+        return Some(steps);
     }
     // Clean and try again
     let cleaned = clean_command_output(raw);
-    if let Ok(cmds) = serde_json::from_str::<Vec<String>>(&cleaned) {
-        return cmds;
+    if let Some(steps) = parse_plan_json(&cleaned) {
+        return Some(steps);
     }
     // Try to pull array from noisy text
-    if let Some(arr) = extract_json_array(raw) {
-        if let Ok(cmds) = serde_json::from_str::<Vec<String>>(arr) {
-            return cmds;
-        }
+    if let Some(steps) = extract_json_array(raw).and_then(parse_plan_json) {
+        return Some(steps);
     }
-    if let Some(json) = extract_last_json(raw) {
-        if let Ok(cmds) = serde_json::from_str::<Vec<String>>(json) {
-            return cmds;
-        }
+    extract_last_json(raw).and_then(parse_plan_json)
+}
+
+/// Check that a plan has steps and that every command parses as shell.
+fn validate_plan(steps: &[PlanStep]) -> std::result::Result<(), String> {
+    if steps.is_empty() {
+        return Err("the plan is empty".to_string());
+    }
+    for (i, step) in steps.iter().enumerate() {
+        check_syntax(&step.command).map_err(|e| format!("step {}: {}", i + 1, e))?;
+    }
+    Ok(())
+}
+
+/// Parse agent response into a list of plan steps
+fn parse_agent_plan(raw: &str) -> Vec<PlanStep> {
+    if let Some(steps) = parse_structured_plan(raw) {
+        return steps;
     }
     // Fallback: split non-empty lines, stripping common list markers and code fences
     raw.lines()
@@ -298,6 +371,7 @@ fn parse_agent_plan(raw: &str) -> Vec<String> {
             line.trim_matches(',').trim().trim_matches('"').to_string()
         })
         .filter(|l| !l.is_empty())
+        .map(PlanStep::new)
         .collect()
 }
 
@@ -323,10 +397,217 @@ fn extract_command_from_response(response: &str) -> String {
         .to_string()
 }
 
+/// Print a fatal error, with a suggested fix when the cause is recognised.
+pub fn report_error(err: &anyhow::Error) {
+    eprintln!("{} {:#}", "Error:".red().bold(), err);
+    if let Some(hint) = err.downcast_ref::<OllamaError>().and_then(OllamaError::hint) {
+        eprintln!("{} {}", "Hint:".yellow().bold(), hint);
+    }
+}
+
+/// Write a streamed piece of a model answer to the terminal as soon as it arrives.
+fn print_token(token: &str) {
+    print!("{}", token);
+    let _ = io::stdout().flush();
+}
+
+/// End a streamed answer on its own line.
+fn finish_streamed(answer: &str) {
+    if !answer.ends_with('\n') {
+        println!();
+    }
+}
+
+/// The chunks a RAG answer cites, one numbered line each; empty when it cites none.
+fn format_sources(answer: &RagAnswer) -> String {
+    if answer.sources.is_empty() {
+        return String::new();
+    }
+    let mut lines = vec!["Sources:".to_string()];
+    for (i, hit) in answer.sources.iter().enumerate() {
+        let mut line = format!("  [{}] {}", i + 1, hit.location());
+        if let Some(symbol) = &hit.symbol {
+            line.push_str(&format!("  {}", symbol));
+        }
+        if hit.score > 0.0 {
+            line.push_str(&format!("  (score {:.2})", hit.score));
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// Exit code and captured output of one shell command.
+type CommandOutcome = (Option<i32>, String);
+
+/// Maximum bytes of step output kept in a persisted agent run.
+const MAX_CAPTURED_OUTPUT: usize = 64 * 1024;
+
+/// Run a shell command, streaming its output to the terminal while keeping a copy.
+/// Output is forwarded as it arrives, so prompts without a trailing newline show
+/// up. With a `label`, every printed line is prefixed so parallel steps stay readable.
+fn run_and_capture(cmd: &str, label: Option<&str>) -> Result<CommandOutcome> {
+    use std::io::IsTerminal;
+    use std::process::Stdio;
+    use std::sync::Mutex;
+
+    let mut command = std::process::Command::new("bash");
+    command
+        .arg("-c")
+        .arg(cmd)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // The pipes hide the terminal from the child; keep colors for tools that
+    // honour these variables.
+    if std::io::stdout().is_terminal() {
+        command.env("CLICOLOR_FORCE", "1").env("FORCE_COLOR", "1");
+    }
+    let mut child = command.spawn()?;
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let captured = Mutex::new(Vec::new());
+    let label = label.map(|label| format!("{} ", label.blue()));
+
+    std::thread::scope(|scope| {
+        let captured = &captured;
+        let label = label.as_deref();
+        if let Some(out) = stdout {
+            scope.spawn(move || forward_output(out, std::io::stdout(), label, captured));
+        }
+        if let Some(err) = stderr {
+            scope.spawn(move || forward_output(err, std::io::stderr(), label, captured));
+        }
+    });
+
+    let status = child.wait()?;
+    let output = captured.into_inner().unwrap_or_else(|e| e.into_inner());
+    let output = String::from_utf8_lossy(&output);
+    Ok((status.code(), tail_of(&output, MAX_CAPTURED_OUTPUT)))
+}
+
+/// Copy `source` to `sink` chunk by chunk as it arrives, appending it to
+/// `captured`. A `label` is written at the start of every line.
+fn forward_output(
+    mut source: impl std::io::Read,
+    mut sink: impl std::io::Write,
+    label: Option<&str>,
+    captured: &std::sync::Mutex<Vec<u8>>,
+) {
+    let mut buf = [0u8; 4096];
+    let mut at_line_start = true;
+    loop {
+        let n = match source.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        let chunk = &buf[..n];
+        match label {
+            Some(label) => {
+                let mut labelled = Vec::with_capacity(n + label.len());
+                for line in chunk.split_inclusive(|b| *b == b'\n') {
+                    if at_line_start {
+                        labelled.extend_from_slice(label.as_bytes());
+                    }
+                    labelled.extend_from_slice(line);
+                    at_line_start = line.ends_with(b"\n");
+                }
+                let _ = sink.write_all(&labelled);
+            }
+            None => {
+                let _ = sink.write_all(chunk);
+            }
+        }
+        let _ = sink.flush();
+        if let Ok(mut out) = captured.lock() {
+            out.extend_from_slice(chunk);
+        }
+    }
+}
+
+/// Keep at most `max` trailing bytes of `text`, cut on a char boundary.
+fn tail_of(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let mut start = text.len() - max;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    text[start..].to_string()
+}
+
+/// Render 0-based step indices as "1, 2, 5".
+fn format_step_numbers(indices: &[usize]) -> String {
+    indices
+        .iter()
+        .map(|i| (i + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_age(now: u64, timestamp: u64) -> String {
+    let secs = now.saturating_sub(timestamp);
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Ollama treats a name without a tag as `:latest`.
+fn full_model_name(name: &str) -> String {
+    if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("{}:latest", name)
+    }
+}
+
+fn print_run_steps(run: &AgentRun) {
+    for (i, (step, record)) in run.plan.steps.iter().zip(&run.steps).enumerate() {
+        let marker = match record.status {
+            StepStatus::Completed => "done".green(),
+            StepStatus::Failed => "failed".red(),
+            StepStatus::Skipped => "skipped".yellow(),
+            StepStatus::Running => "interrupted".red(),
+            StepStatus::Pending => "pending".normal(),
+        };
+        println!(
+            "  {} {:<11} {}",
+            format!("[{}]", i + 1).blue(),
+            marker,
+            step.command
+        );
+    }
+}
+
 #[derive(Parser)]
 #[command(name = "vibe_cli")]
 #[command(about = "Vibe CLI assistant with RAG capabilities")]
+#[command(args_conflicts_with_subcommands = true)]
+#[command(disable_help_subcommand = true)]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Enter interactive chat mode
     #[arg(long)]
     pub chat: bool,
@@ -347,29 +628,135 @@ pub struct Cli {
     #[arg(long)]
     pub context: bool,
 
+    /// Resume a saved agent run from its first incomplete step (with --agent)
+    #[arg(long, value_name = "ID", requires = "agent")]
+    pub resume: Option<String>,
+
+    /// List saved agent runs (with --agent)
+    #[arg(long, requires = "agent")]
+    pub list: bool,
+
+    /// Write the agent plan to a reviewable bash script instead of running it (with --agent)
+    #[arg(long, value_name = "FILE", requires = "agent")]
+    pub export: Option<String>,
+
+    /// Sample the command K times and pick the majority (one-shot and chat modes)
+    #[arg(long, value_name = "K", default_value_t = 1)]
+    pub vote: usize,
+
     /// The query or file path to process
     #[arg(trailing_var_arg = true)]
     pub args: Vec<String>,
 }
 
-pub struct CliApp {
-    rag_service: Option<RagService>,
+impl Cli {
+    /// Parse the command line. A subcommand name followed by words that are
+    /// not its arguments starts a query instead, so `vibe_cli stats for nginx
+    /// logs` asks about nginx logs; after `--` every word is part of the query.
+    pub fn parse_args<I, T>(args: I) -> std::result::Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString>,
+    {
+        let args: Vec<std::ffi::OsString> = args.into_iter().map(Into::into).collect();
+        let err = match Self::try_parse_from(&args) {
+            Ok(cli) => return Ok(cli),
+            Err(err) => err,
+        };
+        if !matches!(err.kind(), ErrorKind::UnknownArgument | ErrorKind::InvalidSubcommand) {
+            return Err(err);
+        }
+        let command = Self::command();
+        let Some(at) = args.iter().skip(1).position(|arg| {
+            command
+                .get_subcommands()
+                .any(|sub| arg.to_str() == Some(sub.get_name()))
+        }) else {
+            return Err(err);
+        };
+        let mut query = args.clone();
+        query.insert(at + 1, "--".into());
+        Self::try_parse_from(query).map_err(|_| err)
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Show token usage, latency percentiles and cache hit rates
+    Stats {
+        /// Only include the last N days
+        #[arg(long, value_name = "N", default_value_t = 30)]
+        days: u64,
+    },
+    /// Check the model server, index database, caches and terminal
+    Doctor,
+    /// List, download, inspect or delete Ollama models
+    Models {
+        #[command(subcommand)]
+        action: ModelsCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ModelsCommand {
+    /// List local models and flag configured models that are missing
+    List,
+    /// Download a model
+    Pull { name: String },
+    /// Show a model's details
+    Show { name: String },
+    /// Delete a local model
+    Rm {
+        name: String,
+        /// Do not ask for confirmation
+        #[arg(long, short)]
+        yes: bool,
+    },
+}
+
+pub struct CliApp<L: LanguageModel = ModelBackend, E: EmbeddingModel + Clone = ModelBackend> {
+    llm: L,
+    embeddings: E,
+    rag_service: Option<RagService<L, E>>,
     cache_path: PathBuf,
     system_info: String,
     config: Config,
+    vote_samples: usize,
+    meter: UsageMeter,
+    stats: StatsStore,
+    /// Mode of the current invocation, used to label recorded statistics.
+    mode: &'static str,
 }
 
 impl CliApp {
-    pub fn new() -> Self {
+    /// An app talking to the backend selected in `Config`.
+    pub fn new() -> Result<Self> {
+        let config = Config::load();
+        let meter = UsageMeter::new();
+        let llm = ModelBackend::from_config(&config)?.with_meter(meter.clone());
+        let embeddings = ModelBackend::embeddings_from_config(&config)?.with_meter(meter.clone());
+        Ok(CliApp::with_models(config, llm, embeddings, meter))
+    }
+}
+
+impl<L: LanguageModel, E: EmbeddingModel + Clone> CliApp<L, E> {
+    /// `meter` should be the one the models report their usage to.
+    pub fn with_models(config: Config, llm: L, embeddings: E, meter: UsageMeter) -> Self {
         let cache_path = Self::default_cache_path();
+        let stats = StatsStore::new(&config.stats_path);
         let system_info_path = Self::default_system_info_path();
         let system_info = Self::load_or_collect_system_info(&system_info_path);
-        let config = Config::load();
         Self {
+            llm,
+            embeddings,
             rag_service: None,
             cache_path,
             system_info,
             config,
+            vote_samples: 1,
+            meter,
+            stats,
+            mode: "query",
         }
     }
 
@@ -535,8 +922,344 @@ impl CliApp {
         Ok(())
     }
 
+    /// Append the usage recorded since the last flush to the stats log.
+    /// Statistics are best effort and never fail the command.
+    fn flush_usage(&self) {
+        let timestamp = unix_timestamp();
+        let events: Vec<StatsEvent> = self
+            .meter
+            .take()
+            .into_iter()
+            .map(|usage| StatsEvent::Request {
+                timestamp,
+                mode: self.mode.to_string(),
+                usage,
+            })
+            .collect();
+        let _ = self.stats.append(&events);
+    }
+
+    /// Record whether a cache lookup answered the request.
+    fn record_cache(&self, hit: bool) {
+        let _ = self.stats.append(&[StatsEvent::Cache {
+            timestamp: unix_timestamp(),
+            mode: self.mode.to_string(),
+            hit,
+        }]);
+    }
+
+    fn show_stats(&self, days: u64) -> Result<()> {
+        let since = unix_timestamp().saturating_sub(days.saturating_mul(86_400));
+        let events = self.stats.load_since(since)?;
+        if events.is_empty() {
+            println!("No statistics recorded in the last {} days.", days);
+            return Ok(());
+        }
+        let report = summarize(&events);
+        let latency = |p: Option<Percentiles>| match p {
+            Some(p) => format!("{:>7} {:>7} {:>7}", p.p50, p.p90, p.p99),
+            None => format!("{:>7} {:>7} {:>7}", "-", "-", "-"),
+        };
+
+        println!("{}", format!("Usage over the last {} days", days).bold());
+        println!();
+        println!("{}", "By mode".bold());
+        println!(
+            "{:<9} {:>6} {:>6} {:>10} {:>10} {:>7} {:>7} {:>7} {:>7}",
+            "mode", "chat", "embed", "prompt", "output", "p50 ms", "p90 ms", "p99 ms", "cache"
+        );
+        for mode in &report.modes {
+            let cache = mode
+                .cache_hit_rate()
+                .map(|rate| format!("{:.0}%", rate * 100.0))
+                .unwrap_or_else(|| "-".to_string());
+            println!(
+                "{:<9} {:>6} {:>6} {:>10} {:>10} {} {:>7}",
+                mode.mode,
+                mode.chat_requests,
+                mode.embed_requests,
+                mode.prompt_tokens,
+                mode.completion_tokens,
+                latency(mode.latency),
+                cache
+            );
+        }
+
+        println!();
+        println!("{}", "By model".bold());
+        println!(
+            "{:<28} {:>6} {:>10} {:>7} {:>7} {:>7} {:>9} {:>9} {:>7}",
+            "model", "reqs", "tokens", "p50 ms", "p90 ms", "p99 ms", "avg load", "max load", "tok/s"
+        );
+        for model in &report.models {
+            let rate = model
+                .tokens_per_sec
+                .map(|rate| format!("{:.1}", rate))
+                .unwrap_or_else(|| "-".to_string());
+            println!(
+                "{:<28} {:>6} {:>10} {} {:>9} {:>9} {:>7}",
+                model.model,
+                model.requests,
+                model.prompt_tokens + model.completion_tokens,
+                latency(model.latency),
+                model.avg_load_ms,
+                model.max_load_ms,
+                rate
+            );
+        }
+
+        println!();
+        println!("{}", "By day".bold());
+        println!(
+            "{:<10} {:>6} {:>10} {:>7} {:>7} {:>7} {:>9}",
+            "day", "chat", "tokens", "p50 ms", "p90 ms", "p99 ms", "avg load"
+        );
+        for day in &report.days {
+            let date = format_utc(day.day);
+            println!(
+                "{:<10} {:>6} {:>10} {} {:>9}",
+                &date[..10],
+                day.chat_requests,
+                day.tokens,
+                latency(day.latency),
+                day.avg_load_ms
+            );
+        }
+        println!();
+        println!("{}", format!("Log: {}", self.stats.path().display()).dimmed());
+        Ok(())
+    }
+
+    /// Configured models with the roles they are used in.
+    fn configured_models(&self) -> Vec<(String, Vec<String>)> {
+        let mut roles: Vec<(String, Vec<String>)> = Vec::new();
+        let mut add = |model: &str, role: &str| {
+            let name = full_model_name(model);
+            match roles.iter_mut().find(|(m, _)| *m == name) {
+                Some((_, used)) => used.push(role.to_string()),
+                None => roles.push((name, vec![role.to_string()])),
+            }
+        };
+        add(&self.config.ollama_model, "base");
+        for model in &self.config.fallback_models {
+            add(model, "fallback");
+        }
+        add(&self.config.embed_model, "embed");
+        let mut overrides: Vec<_> = self.config.mode_models.iter().collect();
+        overrides.sort();
+        for (mode, model) in overrides {
+            add(model, mode);
+        }
+        roles
+    }
+
+    async fn handle_doctor(&self) -> Result<()> {
+        let mut checks = match self.config.llm_backend {
+            LlmBackend::Ollama => doctor::check_ollama(&self.config, &self.configured_models()).await,
+            LlmBackend::OpenAi => vec![doctor::Check::warn(
+                "Ollama server",
+                format!("skipped, LLM_BACKEND is openai ({})", self.config.openai_base_url),
+                "Unset LLM_BACKEND to check an Ollama server.",
+            )],
+        };
+        checks.extend(doctor::check_database(&self.config));
+        checks.push(doctor::check_cache(
+            "Command cache",
+            &self.cache_path,
+            CacheFormat::Json,
+            |bytes| serde_json::from_slice::<CacheFile>(bytes).ok().map(|c| c.entries.len()),
+        ));
+        checks.push(doctor::check_cache(
+            "Explain cache",
+            &Self::explain_cache_path(),
+            CacheFormat::Bincode,
+            |bytes| bincode::deserialize::<ExplainCacheFile>(bytes).ok().map(|c| c.entries.len()),
+        ));
+        checks.push(doctor::check_cache(
+            "RAG cache",
+            &Self::rag_cache_path(),
+            CacheFormat::Bincode,
+            |bytes| bincode::deserialize::<RagCacheFile>(bytes).ok().map(|c| c.entries.len()),
+        ));
+        checks.push(doctor::check_clipboard());
+        checks.push(doctor::check_raw_mode());
+        checks.push(doctor::check_system_profile(
+            &Self::default_system_info_path(),
+            &self.system_info,
+        ));
+
+        let failed = doctor::print_report(&checks);
+        if failed > 0 {
+            return Err(anyhow::anyhow!("{} of {} checks failed", failed, checks.len()));
+        }
+        Ok(())
+    }
+
+    async fn handle_models(&self, action: ModelsCommand) -> Result<()> {
+        if self.config.llm_backend != LlmBackend::Ollama {
+            return Err(anyhow::anyhow!(
+                "Model management needs the Ollama backend; unset LLM_BACKEND to use it"
+            ));
+        }
+        let models = OllamaModels::from_config(&self.config)?;
+        let configured = self.configured_models();
+        let roles_of = |name: &str| {
+            configured
+                .iter()
+                .find(|(m, _)| *m == full_model_name(name))
+                .map(|(_, roles)| roles.join(", "))
+        };
+
+        match action {
+            ModelsCommand::List => {
+                let local = models.list().await?;
+                println!(
+                    "{:<32} {:>9} {:>8} {:<8} {:<10} USED AS",
+                    "NAME", "SIZE", "PARAMS", "QUANT", "MODIFIED"
+                );
+                for model in &local {
+                    let line = format!(
+                        "{:<32} {:>9} {:>8} {:<8} {:<10} {}",
+                        model.name,
+                        format_size(model.size),
+                        model.details.parameter_size,
+                        model.details.quantization_level,
+                        model.modified_at.get(..10).unwrap_or(""),
+                        roles_of(&model.name).unwrap_or_default()
+                    );
+                    if roles_of(&model.name).is_some() {
+                        println!("{}", line.green());
+                    } else {
+                        println!("{}", line);
+                    }
+                }
+                let missing: Vec<_> = configured
+                    .iter()
+                    .filter(|(name, _)| !local.iter().any(|m| full_model_name(&m.name) == *name))
+                    .collect();
+                for (name, roles) in missing {
+                    println!(
+                        "{}",
+                        format!(
+                            "Missing: {} (used as {}); run `vibe_cli models pull {}`",
+                            name,
+                            roles.join(", "),
+                            name
+                        )
+                        .yellow()
+                    );
+                }
+            }
+            ModelsCommand::Pull { name } => {
+                let mut last_status = String::new();
+                let pulled = models
+                    .pull(&name, |progress: &PullProgress| {
+                        if progress.status != last_status && !last_status.is_empty() {
+                            eprintln!();
+                        }
+                        match (progress.completed, progress.total) {
+                            (Some(completed), Some(total)) if total > 0 => eprint!(
+                                "\r{} {:>3}% of {}",
+                                progress.status,
+                                completed * 100 / total,
+                                format_size(total)
+                            ),
+                            _ => eprint!("\r{}", progress.status),
+                        }
+                        let _ = io::stderr().flush();
+                        last_status = progress.status.clone();
+                    })
+                    .await;
+                eprintln!();
+                pulled?;
+                println!("{}", format!("Pulled {}", name).green());
+            }
+            ModelsCommand::Show { name } => {
+                let info = models.show(&name).await?;
+                let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+                println!("{}", name.bold());
+                println!("  Architecture:   {}", or_dash(info.architecture));
+                println!("  Family:         {}", info.details.family);
+                println!("  Parameters:     {}", info.details.parameter_size);
+                println!("  Quantization:   {}", info.details.quantization_level);
+                println!(
+                    "  Context length: {}",
+                    or_dash(info.context_length.map(|n| n.to_string()))
+                );
+                if let Some(length) = info.embedding_length {
+                    println!("  Embedding size: {}", length);
+                }
+                if !info.capabilities.is_empty() {
+                    println!("  Capabilities:   {}", info.capabilities.join(", "));
+                }
+                if let Some(roles) = roles_of(&name) {
+                    println!("  Used as:        {}", roles);
+                }
+                if !info.parameters.trim().is_empty() {
+                    println!("  Defaults:");
+                    for line in info.parameters.lines() {
+                        println!("    {}", line.split_whitespace().collect::<Vec<_>>().join(" "));
+                    }
+                }
+            }
+            ModelsCommand::Rm { name, yes } => {
+                if let Some(roles) = roles_of(&name) {
+                    println!(
+                        "{}",
+                        format!("{} is configured as {}.", name, roles).yellow()
+                    );
+                }
+                if !yes && !ask_confirmation(&format!("Delete {}?", name), false)? {
+                    println!("{}", "Deletion cancelled.".yellow());
+                    return Ok(());
+                }
+                models.delete(&name).await.map_err(|err| {
+                    match err.downcast_ref::<OllamaError>() {
+                        Some(OllamaError::ModelNotFound { .. }) => anyhow::anyhow!(
+                            "{} is not installed; see `vibe_cli models list`",
+                            name
+                        ),
+                        _ => err,
+                    }
+                })?;
+                println!("{}", format!("Deleted {}", name).green());
+            }
+        }
+        Ok(())
+    }
+
     pub async fn run(&mut self, cli: Cli) -> Result<()> {
+        match cli.command {
+            Some(Command::Stats { days }) => return self.show_stats(days),
+            Some(Command::Models { action }) => return self.handle_models(action).await,
+            Some(Command::Doctor) => return self.handle_doctor().await,
+            None => {}
+        }
+        self.mode = if cli.chat {
+            "chat"
+        } else if cli.agent {
+            "agent"
+        } else if cli.explain {
+            "explain"
+        } else if cli.rag {
+            "rag"
+        } else if cli.context {
+            "context"
+        } else {
+            "query"
+        };
+        let model = self.config.model_for(self.mode);
+        if model != self.llm.model_name() {
+            self.llm = self.llm.with_model(model);
+        }
+        let result = self.dispatch(cli).await;
+        self.flush_usage();
+        result
+    }
+
+    async fn dispatch(&mut self, cli: Cli) -> Result<()> {
         let args_str = cli.args.join(" ");
+        self.vote_samples = cli.vote.max(1);
         if cli.chat {
             if args_str.trim().is_empty() {
                 self.handle_chat().await
@@ -545,7 +1268,13 @@ impl CliApp {
                 self.handle_chat().await
             }
         } else if cli.agent {
-            self.handle_agent(&args_str).await
+            if cli.list {
+                self.list_agent_runs()
+            } else if let Some(id) = cli.resume.as_deref() {
+                self.resume_agent(id, cli.export.as_deref()).await
+            } else {
+                self.handle_agent(&args_str, cli.export.as_deref()).await
+            }
         } else if cli.explain {
             self.handle_explain(&args_str).await
         } else if cli.rag {
@@ -569,10 +1298,10 @@ impl CliApp {
                 break;
             }
             // Use the same logic as handle_query
-            let client = infrastructure::ollama_client::OllamaClient::new()?;
             let prompt = format!("You are on a system with: {}. Generate a bash command to: {}. Respond with only the exact command to run, without any formatting, backticks, quotes, or explanation. Ensure the command is complete, syntactically correct, and uses standard Unix tools. For size comparisons, use appropriate units like -BG for gigabytes in df.", self.system_info, input);
-            let response = client.generate_response(&prompt).await?;
-            let command = extract_command_from_response(&response);
+            let command = self.generate_command(&prompt).await?;
+            // Long sessions would otherwise only be written out on exit.
+            self.flush_usage();
             println!("{}", format!("Command: {}", command).green());
             if ask_confirmation("Run this command?", false)? {
                 let output = std::process::Command::new("bash")
@@ -597,61 +1326,363 @@ impl CliApp {
         Ok(())
     }
 
-    async fn handle_agent(&self, task: &str) -> Result<()> {
-        let client = infrastructure::ollama_client::OllamaClient::new()?;
-        let prompt = format!(
+    fn agent_plan_prompt(&self, task: &str) -> String {
+        format!(
             "You are an assistant that turns a user's goal into a sequence of POSIX shell commands that can be run one-by-one with confirmation in between.\n\
 Environment: {}.\n\
 Constraints:\n\
-- Respond ONLY with a JSON array of strings. Each element must be a complete shell command ready to run.\n\
+- Respond ONLY with a JSON array. Each element is an object {{\"command\": ..., \"verify\": ..., \"rollback\": ..., \"read_only\": ..., \"depends_on\": [...]}}.\n\
+- \"command\" is a complete shell command ready to run.\n\
+- \"verify\" is a read-only command that exits 0 when the step worked, or null.\n\
+- \"rollback\" is a command that undoes the step, or null for read-only steps.\n\
+- \"read_only\" is true only when the command inspects state and changes nothing.\n\
+- \"depends_on\" lists the 1-based numbers of earlier steps that must finish first; use [] for independent steps.\n\
 - No prose, no markdown, no comments. If you cannot produce a valid JSON array, respond with [].\n\
 - Prefer Debian/Ubuntu defaults (apt/apt-get, systemctl) unless otherwise implied.\n\
 - Use real paths; avoid placeholders like /path/to.\n\
 - Keep commands minimal and idempotent (check state before changing it).\n\n\
 User request: {}",
             self.system_info, task
-        );
-        let response = client.generate_response(&prompt).await?;
-        let commands = parse_agent_plan(&response);
+        )
+    }
+
+    /// The configured model chain starting at `model`.
+    fn chain_from(&self, model: &str) -> Vec<String> {
+        let chain = self.config.model_chain();
+        match chain.iter().position(|m| m == model) {
+            Some(start) => chain[start..].to_vec(),
+            // A per-mode model escalates straight to the fallbacks.
+            None => std::iter::once(model.to_string())
+                .chain(self.config.fallback_models.iter().filter(|m| *m != model).cloned())
+                .collect(),
+        }
+    }
+
+    /// Request a plan, escalating through the model chain while the answer is
+    /// not a valid structured plan. Returns the steps and a client bound to the
+    /// model that produced them; if no model gets it right, the first plan that
+    /// could be salvaged from free text is returned instead.
+    async fn request_plan(
+        &self,
+        client: &L,
+        prompt: &str,
+    ) -> Result<(Vec<PlanStep>, L)> {
+        let chain = self.chain_from(client.model_name());
+        let mut salvaged: Option<(Vec<PlanStep>, L)> = None;
+        for (i, model) in chain.iter().enumerate() {
+            let candidate = client.with_model(model);
+            let problem = match candidate.complete(prompt).await {
+                Ok(response) => {
+                    let problem = match parse_structured_plan(&response) {
+                        Some(steps) => match validate_plan(&steps) {
+                            Ok(()) => return Ok((steps, candidate)),
+                            Err(reason) => reason,
+                        },
+                        None => "the response was not a JSON plan".to_string(),
+                    };
+                    if salvaged.is_none() {
+                        let lenient = parse_agent_plan(&response);
+                        if !lenient.is_empty() {
+                            salvaged = Some((lenient, candidate.clone()));
+                        }
+                    }
+                    problem
+                }
+                Err(e) if i + 1 < chain.len() => e.to_string(),
+                Err(e) => return Err(e),
+            };
+            if let Some(next) = chain.get(i + 1) {
+                eprintln!(
+                    "{}",
+                    format!("{} gave an unusable plan ({}); escalating to {}", model, problem, next)
+                        .yellow()
+                );
+            }
+        }
+        Ok(salvaged.unwrap_or_else(|| (Vec::new(), client.clone())))
+    }
 
-        if commands.is_empty() {
+    async fn handle_agent(&self, task: &str, export: Option<&str>) -> Result<()> {
+        let prompt = self.agent_plan_prompt(task);
+        let (mut steps, mut client) = self.request_plan(&self.llm, &prompt).await?;
+
+        if steps.is_empty() {
             println!(
                 "{}",
                 "Model did not return a runnable command list (expected JSON array).".red()
             );
             return Ok(());
         }
+        println!("{}", format!("Plan generated by {}", client.model_name()).dimmed());
+
+        // Let the user shape the plan (or have the model revise it) before anything runs.
+        let steps = loop {
+            match edit_plan(steps.clone())? {
+                PlanDecision::Execute(edited) => break edited,
+                PlanDecision::Revise(edited, feedback) => {
+                    // Revise what the user sees, including their own edits.
+                    steps = edited;
+                    eprintln!("Revising plan...");
+                    let revision_prompt = format!(
+                        "{}\n\nThe current plan is:\n{}\n\nRevise the plan according to this feedback: {}\n\
+Respond ONLY with the complete revised JSON array.",
+                        prompt,
+                        serde_json::to_string_pretty(&steps)?,
+                        feedback
+                    );
+                    let (revised, reviser) = self.request_plan(&client, &revision_prompt).await?;
+                    if revised.is_empty() {
+                        println!(
+                            "{}",
+                            "Model did not return a revised plan; keeping the current one.".red()
+                        );
+                    } else {
+                        steps = revised;
+                        client = reviser;
+                        println!("{}", format!("Plan revised by {}", client.model_name()).dimmed());
+                    }
+                }
+                PlanDecision::Cancel => {
+                    println!("{}", "Agent run cancelled.".yellow());
+                    return Ok(());
+                }
+            }
+        };
 
-        println!("\n{}", "Proposed plan:".green());
-        for (i, cmd) in commands.iter().enumerate() {
-            println!("  {} {}", format!("[{}]", i + 1).blue(), cmd);
+        let plan = CommandPlan::new(new_run_id(), task.to_string(), steps);
+        if let Some(path) = export {
+            return Self::export_plan(&plan, client.model_name(), unix_timestamp(), path);
         }
 
-        for (i, cmd) in commands.iter().enumerate() {
+        let store = RunStore::new(&self.config.runs_dir);
+        let mut run = AgentRun::new(task, client.model_name(), plan);
+        store.save(&run)?;
+        eprintln!("Agent run {} saved to {}", run.id, store.path_for(&run.id)?.display());
+
+        self.execute_agent_run(&mut run, &store)
+    }
+
+    /// Execute every step of `run` that has not completed yet, saving progress after each change.
+    /// Independent read-only steps run concurrently; mutating steps run one at a time in plan order.
+    fn execute_agent_run(&self, run: &mut AgentRun, store: &RunStore) -> Result<()> {
+        let total = run.plan.steps.len();
+        let mut attempted: HashSet<usize> = HashSet::new();
+        let mut waived: HashSet<usize> = HashSet::new();
+
+        loop {
+            let wave = run.next_wave(&attempted, &waived);
+            if wave.is_empty() {
+                break;
+            }
+            let accepted = Self::confirm_wave(run, &wave)?;
+            for &i in wave.iter().filter(|i| !accepted.contains(i)) {
+                run.mark_skipped(i);
+                println!("{}", format!("Skipping step {}.", i + 1).yellow());
+            }
+            for &i in &accepted {
+                run.mark_started(i);
+            }
+            store.save(run)?;
+
+            let results: Vec<(usize, Result<CommandOutcome>)> = if accepted.len() == 1 {
+                let i = accepted[0];
+                vec![(i, run_and_capture(&run.plan.steps[i].command, None))]
+            } else {
+                std::thread::scope(|scope| {
+                    let handles: Vec<_> = accepted
+                        .iter()
+                        .map(|&i| {
+                            let cmd = run.plan.steps[i].command.as_str();
+                            let label = format!("[{}]", i + 1);
+                            (i, scope.spawn(move || run_and_capture(cmd, Some(&label))))
+                        })
+                        .collect();
+                    handles
+                        .into_iter()
+                        .map(|(i, handle)| {
+                            let result = handle
+                                .join()
+                                .unwrap_or_else(|_| Err(anyhow::anyhow!("step thread panicked")));
+                            (i, result)
+                        })
+                        .collect()
+                })
+            };
+
+            for (i, result) in results {
+                let (exit_code, output) = match result {
+                    Ok(outcome) => outcome,
+                    Err(e) => (None, format!("Failed to start command: {}", e)),
+                };
+                run.mark_finished(i, exit_code, output);
+                if exit_code == Some(0) {
+                    println!("{}", format!("Step {} completed successfully.", i + 1).green());
+                } else {
+                    println!(
+                        "{} (exit status: {:?})",
+                        format!("Step {} failed.", i + 1).red(),
+                        exit_code
+                    );
+                }
+            }
+            store.save(run)?;
+            attempted.extend(wave.iter().copied());
+
+            // Later steps were planned assuming these ran; don't silently carry on.
+            for &i in &wave {
+                if run.steps[i].status == StepStatus::Completed {
+                    continue;
+                }
+                let dependents: Vec<usize> = run
+                    .plan
+                    .dependents_of(i)
+                    .into_iter()
+                    .filter(|d| !attempted.contains(d))
+                    .collect();
+                if dependents.is_empty() {
+                    continue;
+                }
+                let question = format!(
+                    "Steps {} depend on step {}. Run them anyway?",
+                    format_step_numbers(&dependents),
+                    i + 1
+                );
+                if ask_confirmation(&question, false)? {
+                    waived.insert(i);
+                }
+            }
+        }
+
+        let blocked: Vec<usize> = (0..total)
+            .filter(|i| !attempted.contains(i) && run.steps[*i].status != StepStatus::Completed)
+            .collect();
+        if !blocked.is_empty() {
+            println!(
+                "{}",
+                format!(
+                    "Not run because a dependency did not complete: steps {}",
+                    format_step_numbers(&blocked)
+                )
+                .yellow()
+            );
+        }
+
+        if run.is_finished() {
+            println!("\n{}", "All steps completed.".green());
+        } else {
+            println!(
+                "\n{} {}/{} steps completed. Resume with: vibe_cli --agent --resume {}",
+                format!("Run {} saved.", run.id).yellow(),
+                run.completed_steps(),
+                total,
+                run.id
+            );
+        }
+        Ok(())
+    }
+
+    /// Ask which steps of a wave to run. A multi-step wave is offered as one
+    /// parallel batch first, falling back to per-step confirmation.
+    fn confirm_wave(run: &AgentRun, wave: &[usize]) -> Result<Vec<usize>> {
+        let total = run.plan.steps.len();
+        if let [i] = wave {
             println!(
                 "\n{} {}",
                 "Step".green().bold(),
-                format!("{}:", i + 1).green().bold()
+                format!("{}/{}:", i + 1, total).green().bold()
             );
-            println!("{} {}", "Suggested command:".green(), cmd.yellow());
-            let accept = ask_confirmation("Run this command?", false)?;
-            if !accept {
-                println!("{}", "Skipping this step.".yellow());
-                continue;
+            println!(
+                "{} {}",
+                "Suggested command:".green(),
+                run.plan.steps[*i].command.yellow()
+            );
+            let accepted = ask_confirmation("Run this command?", false)?;
+            return Ok(if accepted { vec![*i] } else { Vec::new() });
+        }
+
+        println!(
+            "\n{} {}",
+            "Independent read-only steps".green().bold(),
+            format!("{}:", format_step_numbers(wave)).green().bold()
+        );
+        for &i in wave {
+            println!(
+                "  {} {}",
+                format!("[{}]", i + 1).blue(),
+                run.plan.steps[i].command.yellow()
+            );
+        }
+        if ask_confirmation("Run these steps in parallel?", false)? {
+            return Ok(wave.to_vec());
+        }
+        let mut accepted = Vec::new();
+        for &i in wave {
+            let question = format!("Run step {} ({})?", i + 1, run.plan.steps[i].command);
+            if ask_confirmation(&question, false)? {
+                accepted.push(i);
             }
-            let status = std::process::Command::new("bash")
-                .arg("-c")
-                .arg(cmd)
-                .status()?;
-            if status.success() {
-                println!("{}", "Command completed successfully.".green());
+        }
+        Ok(accepted)
+    }
+
+    /// Write `plan` as a hardened bash script for review instead of running it.
+    fn export_plan(plan: &CommandPlan, model: &str, created_at: u64, path: &str) -> Result<()> {
+        let target = std::path::Path::new(path);
+        if target.exists()
+            && !ask_confirmation(&format!("{} already exists. Overwrite?", path), false)?
+        {
+            println!("{}", "Export cancelled.".yellow());
+            return Ok(());
+        }
+        let script = render_plan_script(plan, model, created_at);
+        std::fs::write(target, script)?;
+        // Deliberately left non-executable: the script is meant to be read first.
+        println!("{} {}", "Plan exported to".green().bold(), path);
+        println!("{}", "Review it carefully, then run:".yellow());
+        println!("  {}", format!("bash {}", path).yellow());
+        Ok(())
+    }
+
+    async fn resume_agent(&self, id: &str, export: Option<&str>) -> Result<()> {
+        let store = RunStore::new(&self.config.runs_dir);
+        let mut run = store.load(id)?;
+        if let Some(path) = export {
+            return Self::export_plan(&run.plan, &run.model, run.created_at, path);
+        }
+        println!("{} {}", "Resuming agent run:".green(), run.task);
+        print_run_steps(&run);
+        if run.is_finished() {
+            println!("{}", "Every step of this run already completed.".green());
+            return Ok(());
+        }
+        self.execute_agent_run(&mut run, &store)
+    }
+
+    fn list_agent_runs(&self) -> Result<()> {
+        let store = RunStore::new(&self.config.runs_dir);
+        let runs = store.list()?;
+        if runs.is_empty() {
+            println!("No saved agent runs.");
+            return Ok(());
+        }
+        let now = unix_timestamp();
+        for run in &runs {
+            let state = if run.is_finished() {
+                "done".green()
+            } else if run.steps.iter().any(|s| s.status == StepStatus::Failed) {
+                "failed".red()
             } else {
-                println!(
-                    "{} (exit status: {:?})",
-                    "Command failed.".red(),
-                    status.code()
-                );
-            }
+                "incomplete".yellow()
+            };
+            println!(
+                "{}  {:>8}  {:<10}  {}/{}  {}",
+                run.id.blue(),
+                format_age(now, run.updated_at),
+                state,
+                run.completed_steps(),
+                run.steps.len(),
+                run.task
+            );
         }
         Ok(())
     }
@@ -731,33 +1762,46 @@ User request: {}",
 
         // Check cache first
         if let Some(cached_response) = self.load_cached_explain(&prompt)? {
+            self.record_cache(true);
             println!("{}", cached_response);
             return Ok(());
         }
+        self.record_cache(false);
 
         eprintln!("Analyzing file content...");
-        let client = infrastructure::ollama_client::OllamaClient::new()?;
-        let response = client.generate_response(&prompt).await?;
+        let response = self
+            .llm
+            .stream(&[Message::user(&prompt)], &GenerationOptions::default(), print_token)
+            .await?;
+        finish_streamed(&response);
 
         // Cache the response
         self.save_cached_explain(&prompt, &response)?;
-
-        println!("{}", response);
         Ok(())
     }
 
     async fn handle_rag(&mut self, question: &str) -> Result<()> {
         if let Some(cached_response) = self.load_cached_rag(question)? {
             if ask_confirmation("Cached answer found. Use it?", true)? {
+                self.record_cache(true);
                 println!("{}", cached_response);
                 return Ok(());
             }
         }
+        self.record_cache(false);
 
         if self.rag_service.is_none() {
             eprintln!("Analyzing query and scanning codebase...");
-            let client = OllamaClient::new()?;
-            self.rag_service = Some(RagService::new(".", &self.config.db_path, client, self.config.clone()).await?);
+            self.rag_service = Some(
+                RagService::new(
+                    ".",
+                    &self.config.db_path,
+                    self.llm.clone(),
+                    self.embeddings.clone(),
+                    self.config.clone(),
+                )
+                .await?,
+            );
             let keywords = Self::keywords_from_text(question);
             self.rag_service
                 .as_ref()
@@ -766,6 +1810,17 @@ User request: {}",
                 .await?;
         }
 
+        let (kind, custom) = self.rag_service.as_ref().unwrap().question_kind(question);
+        eprintln!(
+            "{}",
+            format!(
+                "Answering as: {}{}",
+                kind.label(),
+                if custom { " (project template)" } else { "" }
+            )
+            .dimmed()
+        );
+
         let mut feedback = String::new();
         loop {
             eprintln!("Thinking...");
@@ -773,13 +1828,32 @@ User request: {}",
                 .rag_service
                 .as_ref()
                 .unwrap()
-                .query_with_feedback(question, &feedback)
+                .stream_query_with_feedback(question, &feedback, print_token)
                 .await?;
-
-            println!("{}", response);
+            finish_streamed(&response.text);
+            let sources = format_sources(&response);
+            if !sources.is_empty() {
+                println!("\n{}", sources.cyan());
+            }
+            if !response.dropped.is_empty() {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Dropped citations to code that was not retrieved: {}",
+                        response.dropped.join(", ")
+                    )
+                    .yellow()
+                );
+            }
 
             if ask_confirmation("Satisfied with this response?", true)? {
-                self.save_cached_rag(question, &response)?;
+                // Cache the answer without dropped citations, with its sources.
+                let cached = if sources.is_empty() {
+                    response.text.clone()
+                } else {
+                    format!("{}\n\n{}", response.text.trim_end(), sources)
+                };
+                self.save_cached_rag(question, &cached)?;
                 break;
             } else {
                 feedback.clear();
@@ -796,8 +1870,16 @@ User request: {}",
 
     async fn handle_context(&mut self, path: &str) -> Result<()> {
         eprintln!("Loading context from {}...", path);
-        let client = OllamaClient::new()?;
-        self.rag_service = Some(RagService::new(path, &self.config.db_path, client, self.config.clone()).await?);
+        self.rag_service = Some(
+            RagService::new(
+                path,
+                &self.config.db_path,
+                self.llm.clone(),
+                self.embeddings.clone(),
+                self.config.clone(),
+            )
+            .await?,
+        );
         self.rag_service.as_ref().unwrap().build_index().await?;
         eprintln!("Context loaded from {}", path);
         self.handle_chat().await
@@ -810,6 +1892,7 @@ User request: {}",
                 format!("Found cached command: {}", cached_command).green()
             );
             if ask_confirmation("Use cached command?", true)? {
+                self.record_cache(true);
                 let output = std::process::Command::new("bash")
                     .arg("-c")
                     .arg(&cached_command)
@@ -829,11 +1912,11 @@ User request: {}",
             }
         }
 
-        let client = infrastructure::ollama_client::OllamaClient::new()?;
+        self.record_cache(false);
+
         let system_info = detect_system_info();
         let prompt = format!("You are on a system with: {}. Generate a bash command to: {}. Respond with only the exact command to run, without any formatting, backticks, quotes, or explanation. Ensure the command is complete, syntactically correct, and uses standard Unix tools. For size comparisons, use appropriate units like -BG for gigabytes in df.", system_info, query);
-        let response = client.generate_response(&prompt).await?;
-        let command = extract_command_from_response(&response);
+        let command = self.generate_command(&prompt).await?;
         println!("{}", format!("Command: {}", command).green());
         if ask_confirmation("Run this command?", false)? {
             let output = std::process::Command::new("bash")
@@ -859,6 +1942,118 @@ User request: {}",
         Ok(())
     }
 
+    /// Ask for a command, escalating through the model chain while the answer
+    /// fails the syntax check. The model that produced the answer is reported.
+    async fn generate_command(&self, prompt: &str) -> Result<String> {
+        let chain = self.chain_from(self.llm.model_name());
+        let mut rejected: Option<(String, String, String)> = None;
+        for (i, model) in chain.iter().enumerate() {
+            let candidate = self.llm.with_model(model);
+            let problem = match self.sample_command(&candidate, prompt).await {
+                Ok(command) => match check_syntax(&command) {
+                    Ok(()) => {
+                        println!("{}", format!("Model: {}", model).dimmed());
+                        return Ok(command);
+                    }
+                    Err(reason) => {
+                        rejected = Some((command, model.clone(), reason.clone()));
+                        reason
+                    }
+                },
+                Err(e) if i + 1 < chain.len() => e.to_string(),
+                Err(e) => return Err(e),
+            };
+            if let Some(next) = chain.get(i + 1) {
+                eprintln!(
+                    "{}",
+                    format!("{} gave an unusable command ({}); escalating to {}", model, problem, next)
+                        .yellow()
+                );
+            }
+        }
+        let Some((command, model, reason)) = rejected else {
+            return Err(anyhow::anyhow!("No model produced a command"));
+        };
+        println!("{}", format!("Model: {}", model).dimmed());
+        println!(
+            "{} {}",
+            "Warning: command failed syntax validation:".red().bold(),
+            reason.red()
+        );
+        Ok(command)
+    }
+
+    /// Ask one model for a command. With `--vote K` the request is sampled K
+    /// times at `VOTE_TEMPERATURE` and the majority answer wins.
+    async fn sample_command(&self, client: &L, prompt: &str) -> Result<String> {
+        if self.vote_samples <= 1 {
+            let response = client.complete(prompt).await?;
+            return Ok(extract_command_from_response(&response));
+        }
+
+        eprintln!("Sampling {} candidate commands...", self.vote_samples);
+        let messages = [Message::user(prompt)];
+        let options = GenerationOptions::with_temperature(self.config.vote_temperature);
+        let samples = futures::future::join_all(
+            (0..self.vote_samples).map(|_| client.chat(&messages, &options)),
+        )
+        .await;
+        // A failed request only costs its vote; the rest still decide.
+        let mut candidates = Vec::with_capacity(samples.len());
+        let mut failures = Vec::new();
+        for sample in samples {
+            match sample {
+                Ok(response) => {
+                    let command = extract_command_from_response(&response);
+                    if !command.is_empty() {
+                        candidates.push(command);
+                    }
+                }
+                Err(e) => failures.push(e),
+            }
+        }
+        if !failures.is_empty() {
+            if failures.len() == self.vote_samples {
+                return Err(failures.swap_remove(0));
+            }
+            eprintln!(
+                "{}",
+                format!(
+                    "{} of {} samples failed ({}); voting among the rest",
+                    failures.len(),
+                    self.vote_samples,
+                    failures[0]
+                )
+                .yellow()
+            );
+        }
+        let Some(vote) = tally_votes(&candidates) else {
+            return Err(anyhow::anyhow!(
+                "None of the {} samples produced a parseable command",
+                self.vote_samples
+            ));
+        };
+
+        let summary = format!(
+            "Confidence: {}/{} samples agree ({:.0}%)",
+            vote.votes,
+            vote.samples,
+            vote.confidence() * 100.0
+        );
+        let summary = if vote.confidence() >= 0.8 {
+            summary.green()
+        } else if vote.confidence() >= 0.5 {
+            summary.yellow()
+        } else {
+            summary.red()
+        };
+        println!("{}", summary);
+        for (alternative, votes) in &vote.alternatives {
+            println!("  {} {}", format!("{}x", votes).dimmed(), alternative.dimmed());
+        }
+        Ok(vote.command)
+    }
+
     fn keywords_from_text(text: &str) -> Vec<String> {
         text.split_whitespace()
             .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
@@ -884,8 +2079,7 @@ User request: {}",
             return Ok(None);
         }
 
-        let data = std::fs::read(&cache_path)?;
-        let mut cache: ExplainCacheFile = bincode::deserialize(&data).unwrap_or_default();
+        let mut cache = ExplainCacheFile::read(&cache_path);
 
         // Remove expired entries (7 days)
         let now = std::time::SystemTime::now()
@@ -895,11 +2089,7 @@ User request: {}",
         cache.entries.retain(|entry| now - entry.timestamp < 604800);
 
         // Save cleaned cache
-        if let Some(parent) = cache_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-        let serialized = bincode::serialize(&cache)?;
-        std::fs::write(&cache_path, serialized)?;
+        cache.write(&cache_path)?;
 
         // Find exact match
         for entry in &cache.entries {
@@ -912,12 +2102,7 @@ User request: {}",
 
     fn save_cached_explain(&self, prompt: &str, response: &str) -> Result<()> {
         let cache_path = Self::explain_cache_path();
-        let mut cache = if cache_path.exists() {
-            let data = std::fs::read(&cache_path).unwrap_or_default();
-            bincode::deserialize::<ExplainCacheFile>(&data).unwrap_or_default()
-        } else {
-            ExplainCacheFile::default()
-        };
+        let mut cache = ExplainCacheFile::read(&cache_path);
 
         cache.entries.push(ExplainCacheEntry {
             prompt: prompt.to_string(),
@@ -928,14 +2113,7 @@ User request: {}",
                 .as_secs(),
         });
 
-        if let Some(parent) = cache_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-
-        let serialized = serde_json::to_string_pretty(&cache)?;
-        std::fs::write(&cache_path, serialized)?;
-
-        Ok(())
+        cache.write(&cache_path)
     }
 
     fn rag_cache_path() -> PathBuf {
@@ -1009,3 +2187,186 @@ User request: {}",
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{forward_output, Cli, CliApp, Command, ExplainCacheEntry, ExplainCacheFile, ModelsCommand};
+    use domain::language_model::LanguageModel;
+    use domain::mock::MockModel;
+    use domain::usage::UsageMeter;
+    use infrastructure::config::Config;
+    use std::sync::Mutex;
+
+    /// A reader that hands out one piece per `read`, like a pipe.
+    struct Pieces(Vec<&'static [u8]>);
+
+    impl std::io::Read for Pieces {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let piece = self.0.remove(0);
+            buf[..piece.len()].copy_from_slice(piece);
+            Ok(piece.len())
+        }
+    }
+
+    #[test]
+    fn test_forward_output_passes_partial_lines() {
+        let captured = Mutex::new(Vec::new());
+        let mut sink = Vec::new();
+        forward_output(Pieces(vec![b"Continue? [Y/n] "]), &mut sink, None, &captured);
+        assert_eq!(sink, b"Continue? [Y/n] ");
+        assert_eq!(captured.into_inner().unwrap(), b"Continue? [Y/n] ");
+    }
+
+    #[test]
+    fn test_forward_output_labels_each_line_once() {
+        let captured = Mutex::new(Vec::new());
+        let mut sink = Vec::new();
+        let pieces = Pieces(vec![b"one\ntw", b"o\n", b"three"]);
+        forward_output(pieces, &mut sink, Some("[2] "), &captured);
+        assert_eq!(String::from_utf8(sink).unwrap(), "[2] one\n[2] two\n[2] three");
+        assert_eq!(captured.into_inner().unwrap(), b"one\ntwo\nthree");
+    }
+
+    /// An app whose model chain is `small`, then `large`, answered by `model`.
+    fn app_with(model: &MockModel) -> CliApp<MockModel, MockModel> {
+        let config = Config {
+            ollama_model: "small".into(),
+            fallback_models: vec!["large".into()],
+            ..Config::load()
+        };
+        let llm = model.with_model("small");
+        CliApp::with_models(config, llm, model.clone(), UsageMeter::new())
+    }
+
+    fn asked_models(model: &MockModel) -> Vec<String> {
+        model.chats().into_iter().map(|(name, _)| name).collect()
+    }
+
+    #[tokio::test]
+    async fn test_request_plan_parses_a_json_plan() {
+        let model = MockModel::new(r#"[{"command": "ls -la"}, "df -h"]"#);
+        let app = app_with(&model);
+        let (steps, client) = app.request_plan(&app.llm, "list files").await.unwrap();
+        let commands: Vec<&str> = steps.iter().map(|s| s.command.as_str()).collect();
+        assert_eq!(commands, vec!["ls -la", "df -h"]);
+        assert_eq!(client.model_name(), "small");
+        assert_eq!(asked_models(&model), vec!["small"]);
+    }
+
+    #[tokio::test]
+    async fn test_request_plan_escalates_past_unusable_plans() {
+        let model = MockModel::replying(|name, _| {
+            Ok(match name {
+                "small" => "First run ls, then check the disk.".to_string(),
+                _ => r#"["ls", "df -h"]"#.to_string(),
+            })
+        });
+        let app = app_with(&model);
+        let (steps, client) = app.request_plan(&app.llm, "check disk").await.unwrap();
+        assert_eq!(steps.len(), 2);
+        assert_eq!(client.model_name(), "large");
+        assert_eq!(asked_models(&model), vec!["small", "large"]);
+
+        // A plan that does not parse as shell escalates too.
+        let model = MockModel::replying(|name, _| {
+            Ok(match name {
+                "small" => r#"["ls |"]"#.to_string(),
+                _ => r#"["ls"]"#.to_string(),
+            })
+        });
+        let app = app_with(&model);
+        let (_, client) = app.request_plan(&app.llm, "list").await.unwrap();
+        assert_eq!(client.model_name(), "large");
+    }
+
+    #[tokio::test]
+    async fn test_request_plan_salvages_the_first_free_text_plan() {
+        let model = MockModel::replying(|name, _| Ok(format!("1. ls {}\n2. df -h", name)));
+        let app = app_with(&model);
+        let (steps, client) = app.request_plan(&app.llm, "check disk").await.unwrap();
+        let commands: Vec<&str> = steps.iter().map(|s| s.command.as_str()).collect();
+        assert_eq!(commands, vec!["ls small", "df -h"]);
+        assert_eq!(client.model_name(), "small");
+        assert_eq!(asked_models(&model), vec!["small", "large"]);
+    }
+
+    #[test]
+    fn test_explain_cache_round_trip() {
+        let dir = std::env::temp_dir().join(format!("explain_cache_{}", std::process::id()));
+        let path = dir.join("cache.bin");
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(ExplainCacheFile::read(&path).entries.is_empty());
+
+        let cache = ExplainCacheFile {
+            entries: vec![ExplainCacheEntry {
+                prompt: "explain `ls -la`".into(),
+                response: "Lists all files.\n".into(),
+                timestamp: 1_700_000_000,
+            }],
+        };
+        cache.write(&path).unwrap();
+        let loaded = ExplainCacheFile::read(&path);
+        assert_eq!(loaded.entries.len(), 1);
+        assert_eq!(loaded.entries[0].prompt, "explain `ls -la`");
+        assert_eq!(loaded.entries[0].response, "Lists all files.\n");
+        assert_eq!(loaded.entries[0].timestamp, 1_700_000_000);
+
+        // Caches written as JSON by older versions start over.
+        std::fs::write(&path, r#"{"entries":[]}"#).unwrap();
+        assert!(ExplainCacheFile::read(&path).entries.is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::parse_args(std::iter::once("vibe_cli").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn test_subcommands_with_their_arguments() {
+        assert!(matches!(parse(&["stats"]).command, Some(Command::Stats { days: 30 })));
+        assert!(matches!(parse(&["stats", "--days", "7"]).command, Some(Command::Stats { days: 7 })));
+        assert!(matches!(parse(&["doctor"]).command, Some(Command::Doctor)));
+        let cli = parse(&["models", "pull", "qwen2.5"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Models { action: ModelsCommand::Pull { ref name } }) if name == "qwen2.5"
+        ));
+    }
+
+    #[test]
+    fn test_subcommand_words_can_start_a_query() {
+        for words in [
+            &["stats", "for", "nginx", "logs"][..],
+            &["models", "that", "use", "the", "most", "disk"],
+            &["doctor", "who", "episodes"],
+            &["help", "me", "find", "large", "files"],
+        ] {
+            let cli = parse(words);
+            assert!(cli.command.is_none(), "{:?}", words);
+            assert_eq!(cli.args, words);
+        }
+        let cli = parse(&["--vote", "3", "stats", "for", "nginx"]);
+        assert!(cli.command.is_none());
+        assert_eq!(cli.vote, 3);
+        assert_eq!(cli.args, ["stats", "for", "nginx"]);
+    }
+
+    #[test]
+    fn test_double_dash_forces_a_query() {
+        let cli = parse(&["--", "stats"]);
+        assert!(cli.command.is_none());
+        assert_eq!(cli.args, ["stats"]);
+    }
+
+    #[test]
+    fn test_subcommand_errors_are_kept() {
+        let parse = |args: &[&str]| Cli::parse_args(std::iter::once("vibe_cli").chain(args.iter().copied()));
+        // Missing or invalid arguments of a real subcommand are reported, not asked about.
+        assert!(parse(&["models", "pull"]).is_err());
+        assert!(parse(&["stats", "--days", "many"]).is_err());
+        assert!(parse(&["models"]).is_err());
+    }
+}
@@ -0,0 +1,568 @@
+use colored::Colorize;
+use infrastructure::config::Config;
+use infrastructure::embedding_storage::{EmbeddingStorage, SCHEMA_VERSION};
+use infrastructure::ollama_error::OllamaError;
+use infrastructure::ollama_models::OllamaModels;
+use infrastructure::vector_index::VectorIndex;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Outcome of one diagnostic, with a suggested fix when it did not pass.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+impl Check {
+    pub fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    pub fn warn(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Warn,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    pub fn fail(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: Status::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Print every check and return how many failed.
+pub fn print_report(checks: &[Check]) -> usize {
+    let width = checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    for check in checks {
+        let label = match check.status {
+            Status::Pass => "[PASS]".green(),
+            Status::Warn => "[WARN]".yellow(),
+            Status::Fail => "[FAIL]".red(),
+        };
+        println!("{} {:<width$}  {}", label, check.name, check.detail, width = width);
+        if let Some(hint) = &check.hint {
+            println!("       {:<width$}  {}", "", format!("Fix: {}", hint).dimmed(), width = width);
+        }
+    }
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    let warned = checks.iter().filter(|c| c.status == Status::Warn).count();
+    println!();
+    println!(
+        "{} passed, {} warnings, {} failed",
+        checks.len() - failed - warned,
+        warned,
+        failed
+    );
+    failed
+}
+
+/// Server version and presence of every configured model. `configured`
+/// pairs full model names with the roles they are used in.
+pub async fn check_ollama(config: &Config, configured: &[(String, Vec<String>)]) -> Vec<Check> {
+    // Report an unreachable server at once instead of retrying it.
+    let config = &Config {
+        max_retries: 0,
+        ..config.clone()
+    };
+    let models = match OllamaModels::from_config(config) {
+        Ok(models) => models,
+        Err(err) => {
+            return vec![Check::fail(
+                "Ollama server",
+                format!("{:#}", err),
+                "Check the system's TLS certificates and proxy settings.",
+            )]
+        }
+    };
+    let hint_for = |err: &anyhow::Error| {
+        err.downcast_ref::<OllamaError>()
+            .and_then(OllamaError::hint)
+            .unwrap_or_else(|| "Check OLLAMA_BASE_URL.".to_string())
+    };
+
+    let mut checks = Vec::new();
+    match models.version().await {
+        Ok(version) => checks.push(Check::pass(
+            "Ollama server",
+            format!("version {} at {}", version, config.ollama_base_url),
+        )),
+        Err(err) => {
+            checks.push(Check::fail("Ollama server", format!("{:#}", err), hint_for(&err)));
+            return checks;
+        }
+    }
+
+    let local = match models.list().await {
+        Ok(local) => local,
+        Err(err) => {
+            checks.push(Check::fail("Local models", format!("{:#}", err), hint_for(&err)));
+            return checks;
+        }
+    };
+    for (name, roles) in configured {
+        let check_name = format!("Model {}", name);
+        let used_as = format!("used as {}", roles.join(", "));
+        if !local.iter().any(|m| m.name == *name) {
+            checks.push(Check::fail(
+                check_name,
+                format!("not pulled ({})", used_as),
+                format!("vibe_cli models pull {}", name),
+            ));
+            continue;
+        }
+        // Servers that report capabilities say whether a model can embed.
+        let is_embed = roles.iter().any(|r| r == "embed");
+        match models.show(name).await {
+            Ok(info)
+                if is_embed
+                    && !info.capabilities.is_empty()
+                    && !info.capabilities.iter().any(|c| c == "embedding") =>
+            {
+                checks.push(Check::fail(
+                    check_name,
+                    format!("cannot produce embeddings ({})", used_as),
+                    "Set EMBED_MODEL to an embedding model such as nomic-embed-text.",
+                ))
+            }
+            _ => checks.push(Check::pass(check_name, used_as)),
+        }
+    }
+    checks
+}
+
+/// Integrity, schema version, row counts and vector dimensions of the index.
+pub fn check_database(config: &Config) -> Vec<Check> {
+    let path = Path::new(&config.db_path);
+    let rebuild = format!("Delete {} and run `vibe_cli --rag` to rebuild it.", path.display());
+    if !path.exists() {
+        return vec![Check::warn(
+            "Embedding DB",
+            format!("{} does not exist yet", path.display()),
+            "Run `vibe_cli --rag <question>` or `vibe_cli --context <path>` to build it.",
+        )];
+    }
+    let health = match EmbeddingStorage::check_health(path) {
+        Ok(health) => health,
+        Err(err) => return vec![Check::fail("Embedding DB", format!("{:#}", err), rebuild)],
+    };
+
+    let mut checks = Vec::new();
+    if health.integrity_errors.is_empty() {
+        checks.push(Check::pass("Embedding DB", path.display().to_string()));
+    } else {
+        checks.push(Check::fail(
+            "Embedding DB",
+            format!("integrity check failed: {}", health.integrity_errors.join("; ")),
+            rebuild.clone(),
+        ));
+    }
+
+    checks.push(match health.schema_version {
+        v if v == SCHEMA_VERSION => Check::pass("DB schema", format!("version {}", v)),
+        v if v > SCHEMA_VERSION => Check::fail(
+            "DB schema",
+            format!("version {} is newer than this build supports ({})", v, SCHEMA_VERSION),
+            "Upgrade vibe_cli or point DB_PATH at another file.",
+        ),
+        v => Check::warn(
+            "DB schema",
+            format!("version {}, current is {}", v, SCHEMA_VERSION),
+            "It is upgraded automatically on the next --rag or --context run.",
+        ),
+    });
+
+    checks.push(Check::pass(
+        "DB rows",
+        format!("{} chunks from {} files", health.embeddings, health.files),
+    ));
+
+    checks.push(match health.keyword_rows {
+        Some(rows) if rows == health.embeddings => {
+            Check::pass("Keyword index", format!("{} chunks", rows))
+        }
+        Some(rows) => Check::fail(
+            "Keyword index",
+            format!("{} rows for {} chunks", rows, health.embeddings),
+            rebuild.clone(),
+        ),
+        None => Check::warn(
+            "Keyword index",
+            "missing",
+            "It is built automatically on the next --rag or --context run.",
+        ),
+    });
+
+    let index_path = EmbeddingStorage::index_path(path);
+    checks.push(match VectorIndex::load(&index_path) {
+        Some(index) if index.len() == health.embeddings => Check::pass(
+            "Vector index",
+            format!("{} vectors in {} lists", index.len(), index.list_count()),
+        ),
+        Some(index) => Check::warn(
+            "Vector index",
+            format!("{} vectors for {} chunks", index.len(), health.embeddings),
+            "It is rebuilt from the database on the next --rag or --context run.",
+        ),
+        None => Check::warn(
+            "Vector index",
+            format!("{} is missing or unreadable", index_path.display()),
+            "It is rebuilt from the database on the next --rag or --context run.",
+        ),
+    });
+
+    let lengths: Vec<String> = health
+        .dimensions
+        .iter()
+        .map(|(dimension, count)| format!("{} x {}", count, dimension))
+        .collect();
+    let formats: Vec<String> = health
+        .formats
+        .iter()
+        .map(|(format, count)| format!("{} {}", count, format.name()))
+        .collect();
+    let vectors = if lengths.is_empty() {
+        "no vectors".to_string()
+    } else {
+        format!("vectors: {} ({})", lengths.join(", "), formats.join(", "))
+    };
+    checks.push(if health.malformed_vectors > 0 {
+        Check::fail(
+            "Vector dimensions",
+            format!("{} unreadable vectors; {}", health.malformed_vectors, vectors),
+            rebuild,
+        )
+    } else if health.dimensions.len() > 1 {
+        Check::fail("Vector dimensions", format!("mixed lengths; {}", vectors), rebuild)
+    } else {
+        match (&health.index_info, health.dimensions.first()) {
+            (_, None) => Check::pass("Vector dimensions", vectors),
+            (None, Some(_)) => Check::warn(
+                "Vector dimensions",
+                format!("{}; embedding model not recorded", vectors),
+                "The index is rebuilt on the next --rag or --context run.",
+            ),
+            (Some(info), Some((dimension, _))) if info.dimension != *dimension => Check::fail(
+                "Vector dimensions",
+                format!("{}; index metadata says {}", vectors, info.dimension),
+                rebuild,
+            ),
+            (Some(info), Some(_)) if info.model != config.embed_model => Check::warn(
+                "Vector dimensions",
+                format!("{}; built with {}, EMBED_MODEL is {}", vectors, info.model, config.embed_model),
+                "The index is rebuilt on the next --rag or --context run.",
+            ),
+            (Some(info), Some(_)) => {
+                Check::pass("Vector dimensions", format!("{} from {}", vectors, info.model))
+            }
+        }
+    });
+    checks
+}
+
+/// On-disk encoding of a cache file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheFormat {
+    Json,
+    Bincode,
+}
+
+/// Size of a cache file and whether it decodes. `decode` returns the number
+/// of entries, or `None` when the bytes are not a valid cache.
+pub fn check_cache(
+    name: &str,
+    path: &Path,
+    format: CacheFormat,
+    decode: impl Fn(&[u8]) -> Option<usize>,
+) -> Check {
+    let delete = format!("Delete {}; it is recreated on demand.", path.display());
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Check::pass(name, "empty");
+        }
+        Err(err) => return Check::fail(name, err.to_string(), delete),
+    };
+    let size = super::cli::format_size(bytes.len() as u64);
+    match decode(&bytes) {
+        Some(entries) => Check::pass(name, format!("{} entries, {}", entries, size)),
+        None => {
+            let is_json = serde_json::from_slice::<serde_json::Value>(&bytes).is_ok();
+            let detail = match (format, is_json) {
+                (CacheFormat::Bincode, true) => "written as JSON but read as bincode",
+                (CacheFormat::Bincode, false) => "not a valid bincode cache",
+                (CacheFormat::Json, true) => "unexpected JSON layout",
+                (CacheFormat::Json, false) => "not valid JSON",
+            };
+            Check::fail(name, format!("{} ({})", detail, size), delete)
+        }
+    }
+}
+
+pub fn check_clipboard() -> Check {
+    match arboard::Clipboard::new() {
+        Ok(_) => Check::pass("Clipboard", "available"),
+        Err(err) => Check::warn(
+            "Clipboard",
+            err.to_string(),
+            "Run inside a graphical session (X11 or Wayland) to enable copying commands.",
+        ),
+    }
+}
+
+pub fn check_raw_mode() -> Check {
+    if shared::confirmation::raw_mode_available() {
+        Check::pass("Terminal raw mode", "supported")
+    } else {
+        Check::fail(
+            "Terminal raw mode",
+            "stdin is not a terminal that supports raw mode",
+            "Run vibe_cli from an interactive terminal; confirmation prompts read single keys.",
+        )
+    }
+}
+
+pub fn check_system_profile(path: &Path, detected: &str) -> Check {
+    if detected.trim().is_empty() {
+        return Check::fail(
+            "System profile",
+            "nothing detected",
+            format!("Delete {} to detect it again.", path.display()),
+        );
+    }
+    Check::pass("System profile", detected.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain::models::Embedding;
+    use infrastructure::embedding_storage::IndexInfo;
+    use infrastructure::vector_codec::{self, VectorFormat};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::thread;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("doctor_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn status_of(checks: &[Check], name: &str) -> Status {
+        checks.iter().find(|c| c.name == name).unwrap().status
+    }
+
+    /// A server answering `requests` requests, each from `route(path)`.
+    fn serve(requests: usize, route: fn(&str) -> String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for _ in 0..requests {
+                let (mut stream, _) = listener.accept().unwrap();
+                let request = read_request(&mut stream);
+                let path = request.split_whitespace().nth(1).unwrap_or("");
+                let body = route(path);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        url
+    }
+
+    /// Reads headers and body, so closing the socket does not reset the connection.
+    fn read_request(stream: &mut impl Read) -> String {
+        let mut raw = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut buf).unwrap();
+            raw.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&raw).to_string();
+            let Some(end) = text.find("\r\n\r\n") else {
+                if n == 0 {
+                    return text;
+                }
+                continue;
+            };
+            let length = text[..end]
+                .lines()
+                .find_map(|l| l.to_lowercase().strip_prefix("content-length:")?.trim().parse().ok())
+                .unwrap_or(0);
+            if raw.len() >= end + 4 + length || n == 0 {
+                return text;
+            }
+        }
+    }
+
+    async fn database_with(dir: &Path, embed_model: &str) -> Config {
+        let config = Config {
+            db_path: dir.join("index.db").to_string_lossy().to_string(),
+            embed_model: embed_model.to_string(),
+            ..Config::load()
+        };
+        let storage = EmbeddingStorage::new(&config.db_path, VectorFormat::F32).await.unwrap();
+        storage
+            .insert_embeddings(vec![Embedding {
+                id: "a".to_string(),
+                vector: vec![1.0, 0.0],
+                text: "fn main() {}".to_string(),
+                path: "src/main.rs".to_string(),
+                start_line: 1,
+                end_line: 1,
+                symbol: None,
+            }])
+            .await
+            .unwrap();
+        storage
+            .set_index_info(IndexInfo {
+                model: embed_model.to_string(),
+                dimension: 2,
+            })
+            .await
+            .unwrap();
+        config
+    }
+
+    #[tokio::test]
+    async fn test_healthy_database_passes() {
+        let dir = temp_dir("healthy");
+        let config = database_with(&dir, "embedder").await;
+        let checks = check_database(&config);
+        assert!(checks.iter().all(|c| c.status == Status::Pass), "{:?}", checks);
+
+        // Another embedding model only warns; the index is rebuilt on use.
+        let checks = check_database(&Config {
+            embed_model: "other".to_string(),
+            ..config
+        });
+        assert_eq!(status_of(&checks, "Vector dimensions"), Status::Warn);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_dimension_row_fails() {
+        let dir = temp_dir("dimension");
+        let config = database_with(&dir, "embedder").await;
+        let conn = rusqlite::Connection::open(&config.db_path).unwrap();
+        conn.execute(
+            "INSERT INTO embeddings (id, vector, text, path) VALUES ('b', ?1, 'b', 'src/b.rs')",
+            [vector_codec::encode(&[0.0, 0.0, 1.0], VectorFormat::F32)],
+        )
+        .unwrap();
+        drop(conn);
+
+        let checks = check_database(&config);
+        assert_eq!(status_of(&checks, "Embedding DB"), Status::Pass);
+        assert_eq!(status_of(&checks, "Vector dimensions"), Status::Fail);
+        assert_eq!(status_of(&checks, "Vector index"), Status::Warn);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_missing_and_corrupt_database() {
+        let dir = temp_dir("corrupt");
+        let db_path = dir.join("index.db");
+        let config = Config {
+            db_path: db_path.to_string_lossy().to_string(),
+            ..Config::load()
+        };
+        assert_eq!(status_of(&check_database(&config), "Embedding DB"), Status::Warn);
+
+        std::fs::write(&db_path, b"not a database").unwrap();
+        assert_eq!(status_of(&check_database(&config), "Embedding DB"), Status::Fail);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_cache_checks() {
+        let dir = temp_dir("cache");
+        let path = dir.join("cache.bin");
+        let decode = |bytes: &[u8]| bincode::deserialize::<Vec<String>>(bytes).ok().map(|v| v.len());
+
+        assert_eq!(check_cache("Cache", &path, CacheFormat::Bincode, decode).status, Status::Pass);
+
+        std::fs::write(&path, bincode::serialize(&vec!["a".to_string()]).unwrap()).unwrap();
+        let check = check_cache("Cache", &path, CacheFormat::Bincode, decode);
+        assert_eq!(check.status, Status::Pass);
+        assert!(check.detail.starts_with("1 entries"));
+
+        std::fs::write(&path, [0xff, 0xff, 0xff]).unwrap();
+        let check = check_cache("Cache", &path, CacheFormat::Bincode, decode);
+        assert_eq!(check.status, Status::Fail);
+        assert!(check.detail.contains("not a valid bincode cache"));
+
+        std::fs::write(&path, br#"{"entries":{}}"#).unwrap();
+        let check = check_cache("Cache", &path, CacheFormat::Bincode, decode);
+        assert_eq!(check.status, Status::Fail);
+        assert!(check.detail.contains("written as JSON"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_check_ollama_maps_models() {
+        let url = serve(4, |path| match path {
+            "/api/version" => r#"{"version":"0.5.7"}"#.to_string(),
+            "/api/tags" => {
+                r#"{"models":[{"name":"small:latest"},{"name":"chatty:latest"}]}"#.to_string()
+            }
+            _ => r#"{"capabilities":["completion"]}"#.to_string(),
+        });
+        let config = Config {
+            ollama_base_url: url,
+            ..Config::load()
+        };
+        let configured = vec![
+            ("small:latest".to_string(), vec!["base".to_string()]),
+            ("chatty:latest".to_string(), vec!["embed".to_string()]),
+            ("missing:latest".to_string(), vec!["fallback".to_string()]),
+        ];
+        let checks = check_ollama(&config, &configured).await;
+        assert_eq!(status_of(&checks, "Ollama server"), Status::Pass);
+        assert_eq!(status_of(&checks, "Model small:latest"), Status::Pass);
+        assert_eq!(status_of(&checks, "Model chatty:latest"), Status::Fail);
+        let missing = checks.iter().find(|c| c.name == "Model missing:latest").unwrap();
+        assert_eq!(missing.status, Status::Fail);
+        assert_eq!(missing.hint.as_deref(), Some("vibe_cli models pull missing:latest"));
+    }
+
+    #[tokio::test]
+    async fn test_check_ollama_unreachable_server_fails() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let config = Config {
+            ollama_base_url: url,
+            ..Config::load()
+        };
+        let checks = check_ollama(&config, &[]).await;
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].status, Status::Fail);
+        assert!(checks[0].hint.as_deref().unwrap().contains("ollama serve"));
+    }
+}
@@ -1,2 +1,4 @@
 pub mod adapters;
 pub mod cli;
+pub mod doctor;
+pub mod plan_editor;
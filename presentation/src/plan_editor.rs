@@ -0,0 +1,351 @@
+use colored::Colorize;
+use dialoguer::{theme::ColorfulTheme, Input, Select};
+use domain::command_plan::{CommandPlan, PlanStep};
+use shared::types::Result;
+
+/// What the user decided to do with a proposed agent plan.
+pub enum PlanDecision {
+    /// Run the (possibly edited) list of steps.
+    Execute(Vec<PlanStep>),
+    /// Ask the model to revise the (possibly edited) steps using this feedback.
+    Revise(Vec<PlanStep>, String),
+    /// Abort without running anything.
+    Cancel,
+}
+
+const ACTIONS: [&str; 7] = [
+    "Run plan",
+    "Edit a step",
+    "Delete a step",
+    "Insert a step",
+    "Move a step",
+    "Ask the model to revise the plan",
+    "Cancel",
+];
+
+/// Interactive editor shown after "Proposed plan:" and before any step runs.
+pub fn edit_plan(mut steps: Vec<PlanStep>) -> Result<PlanDecision> {
+    let theme = ColorfulTheme::default();
+    loop {
+        print_plan(&steps);
+        let action = Select::with_theme(&theme)
+            .with_prompt("What would you like to do?")
+            .items(&ACTIONS)
+            .default(0)
+            .interact()?;
+
+        match action {
+            0 => {
+                if steps.is_empty() {
+                    println!("{}", "The plan is empty; insert a step or cancel.".yellow());
+                    continue;
+                }
+                return Ok(PlanDecision::Execute(steps));
+            }
+            1 => {
+                let Some(index) = pick_step(&theme, &steps, "Step to edit")? else {
+                    continue;
+                };
+                let edited: String = Input::with_theme(&theme)
+                    .with_prompt("Command")
+                    .with_initial_text(steps[index].command.clone())
+                    .interact_text()?;
+                let edited = edited.trim().to_string();
+                if edited.is_empty() {
+                    println!("{}", "Empty command ignored; use delete to remove a step.".yellow());
+                } else {
+                    steps[index].command = edited;
+                }
+            }
+            2 => {
+                if let Some(index) = pick_step(&theme, &steps, "Step to delete")? {
+                    let removed = delete_step(&mut steps, index);
+                    println!("{} {}", "Removed:".yellow(), removed.command);
+                }
+            }
+            3 => {
+                let position: usize = Input::with_theme(&theme)
+                    .with_prompt(format!("Insert at position (1-{})", steps.len() + 1))
+                    .default(steps.len() + 1)
+                    .validate_with(|n: &usize| {
+                        if (1..=steps.len() + 1).contains(n) {
+                            Ok(())
+                        } else {
+                            Err("position out of range")
+                        }
+                    })
+                    .interact_text()?;
+                let command: String = Input::with_theme(&theme)
+                    .with_prompt("Command")
+                    .interact_text()?;
+                let command = command.trim().to_string();
+                if !command.is_empty() {
+                    insert_step(&mut steps, position - 1, PlanStep::new(command));
+                }
+            }
+            4 => {
+                let Some(from) = pick_step(&theme, &steps, "Step to move")? else {
+                    continue;
+                };
+                let to: usize = Input::with_theme(&theme)
+                    .with_prompt(format!("New position (1-{})", steps.len()))
+                    .default(from + 1)
+                    .validate_with(|n: &usize| {
+                        if (1..=steps.len()).contains(n) {
+                            Ok(())
+                        } else {
+                            Err("position out of range")
+                        }
+                    })
+                    .interact_text()?;
+                if let Err(problem) = move_step(&mut steps, from, to - 1) {
+                    println!("{}", problem.yellow());
+                }
+            }
+            5 => {
+                let feedback: String = Input::with_theme(&theme)
+                    .with_prompt("What should change?")
+                    .interact_text()?;
+                let feedback = feedback.trim().to_string();
+                if !feedback.is_empty() {
+                    return Ok(PlanDecision::Revise(steps, feedback));
+                }
+            }
+            _ => return Ok(PlanDecision::Cancel),
+        }
+    }
+}
+
+pub fn print_plan(steps: &[PlanStep]) {
+    println!("\n{}", "Proposed plan:".green());
+    if steps.is_empty() {
+        println!("  {}", "(no steps)".yellow());
+    }
+    for (i, step) in steps.iter().enumerate() {
+        let mut notes = Vec::new();
+        if step.runs_concurrently() {
+            notes.push("read-only".to_string());
+        }
+        if let Some(deps) = step.depends_on.as_ref().filter(|d| !d.is_empty()) {
+            let deps: Vec<String> = deps.iter().map(|d| d.to_string()).collect();
+            notes.push(format!("after {}", deps.join(", ")));
+        }
+        let ignored: Vec<String> = ignored_dependencies(steps)
+            .into_iter()
+            .filter(|(step, _)| *step == i)
+            .map(|(_, number)| number.to_string())
+            .collect();
+        if !ignored.is_empty() {
+            notes.push(format!("ignoring later or missing step {}", ignored.join(", ")));
+        }
+        let notes = if notes.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", notes.join("; ")).dimmed().to_string()
+        };
+        println!("  {} {}{}", format!("[{}]", i + 1).blue(), step.command, notes);
+        if let Some(verify) = &step.verify {
+            println!("      {} {}", "verify:".dimmed(), verify.dimmed());
+        }
+        if let Some(rollback) = &step.rollback {
+            println!("      {} {}", "rollback:".dimmed(), rollback.dimmed());
+        }
+    }
+}
+
+fn pick_step(theme: &ColorfulTheme, steps: &[PlanStep], prompt: &str) -> Result<Option<usize>> {
+    if steps.is_empty() {
+        println!("{}", "The plan has no steps.".yellow());
+        return Ok(None);
+    }
+    let items: Vec<String> = steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| format!("[{}] {}", i + 1, step.command))
+        .collect();
+    let index = Select::with_theme(theme)
+        .with_prompt(prompt)
+        .items(&items)
+        .default(0)
+        .interact_opt()?;
+    Ok(index)
+}
+
+/// `(step, number)` for every dependency that does not name an earlier step.
+fn ignored_dependencies(steps: &[PlanStep]) -> Vec<(usize, usize)> {
+    CommandPlan::new(String::new(), String::new(), steps.to_vec()).ignored_dependencies()
+}
+
+/// Remove step `index`. Steps that waited for it wait for its own
+/// dependencies instead, so deleting a step never lets its dependents start
+/// early or run alongside steps they were ordered after.
+fn delete_step(steps: &mut Vec<PlanStep>, index: usize) -> PlanStep {
+    let number = index + 1;
+    let inherited: Vec<usize> = match &steps[index].depends_on {
+        Some(deps) => deps.clone(),
+        None => index.checked_sub(1).map(|prev| prev + 1).into_iter().collect(),
+    };
+    for step in steps.iter_mut() {
+        if let Some(deps) = step.depends_on.as_mut() {
+            if let Some(pos) = deps.iter().position(|d| *d == number) {
+                deps.remove(pos);
+                for dep in &inherited {
+                    if !deps.contains(dep) {
+                        deps.push(*dep);
+                    }
+                }
+                deps.sort_unstable();
+            }
+        }
+    }
+    let mut numbers = original_numbers(steps);
+    numbers.remove(index);
+    let removed = steps.remove(index);
+    renumber_dependencies(steps, &numbers);
+    removed
+}
+
+fn insert_step(steps: &mut Vec<PlanStep>, index: usize, step: PlanStep) {
+    let mut numbers = original_numbers(steps);
+    numbers.insert(index, None);
+    steps.insert(index, step);
+    renumber_dependencies(steps, &numbers);
+}
+
+/// Move step `from` to position `to` (both 0-based). A move that would put a
+/// step ahead of a step it depends on is refused, since the dependency could
+/// no longer be honoured.
+fn move_step(steps: &mut Vec<PlanStep>, from: usize, to: usize) -> std::result::Result<(), String> {
+    let mut numbers = original_numbers(steps);
+    let number = numbers.remove(from);
+    numbers.insert(to, number);
+    let mut moved = steps.clone();
+    let step = moved.remove(from);
+    moved.insert(to, step);
+    renumber_dependencies(&mut moved, &numbers);
+
+    // Report only orderings the move breaks, not ones the plan already had.
+    let before = ignored_dependencies(steps);
+    let broken = ignored_dependencies(&moved).into_iter().find_map(|(step, dep)| {
+        let old_step = numbers[step]? - 1;
+        let old_dep = numbers.get(dep.checked_sub(1)?).copied().flatten()?;
+        (!before.contains(&(old_step, old_dep))).then_some((old_step + 1, old_dep))
+    });
+    if let Some((step, dep)) = broken {
+        return Err(format!(
+            "Step {} depends on step {}, so it cannot come before it; move or delete step {} first.",
+            step, dep, dep
+        ));
+    }
+    *steps = moved;
+    Ok(())
+}
+
+/// The 1-based number of every step before an edit.
+fn original_numbers(steps: &[PlanStep]) -> Vec<Option<usize>> {
+    (1..=steps.len()).map(Some).collect()
+}
+
+/// Rewrite `depends_on` after an edit. `numbers[i]` is the number step `i`
+/// had before the edit (`None` for an inserted step); references to deleted
+/// steps are dropped.
+fn renumber_dependencies(steps: &mut [PlanStep], numbers: &[Option<usize>]) {
+    for step in steps.iter_mut() {
+        if let Some(deps) = step.depends_on.as_mut() {
+            *deps = deps
+                .iter()
+                .filter_map(|old| numbers.iter().position(|n| *n == Some(*old)))
+                .map(|pos| pos + 1)
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{delete_step, insert_step, move_step};
+    use domain::command_plan::PlanStep;
+
+    fn step(command: &str, depends_on: Option<Vec<usize>>) -> PlanStep {
+        PlanStep {
+            depends_on,
+            read_only: true,
+            ..PlanStep::new(command)
+        }
+    }
+
+    fn deps(steps: &[PlanStep]) -> Vec<Option<Vec<usize>>> {
+        steps.iter().map(|s| s.depends_on.clone()).collect()
+    }
+
+    fn commands(steps: &[PlanStep]) -> Vec<&str> {
+        steps.iter().map(|s| s.command.as_str()).collect()
+    }
+
+    #[test]
+    fn test_insert_renumbers_later_references() {
+        let mut steps = vec![step("a", Some(vec![])), step("b", Some(vec![1]))];
+        insert_step(&mut steps, 0, step("new", None));
+        assert_eq!(commands(&steps), vec!["new", "a", "b"]);
+        assert_eq!(deps(&steps), vec![None, Some(vec![]), Some(vec![2])]);
+    }
+
+    #[test]
+    fn test_delete_passes_dependencies_on() {
+        let mut steps = vec![
+            step("a", Some(vec![])),
+            step("b", Some(vec![1])),
+            step("c", Some(vec![2])),
+        ];
+        let removed = delete_step(&mut steps, 1);
+        assert_eq!(removed.command, "b");
+        // "c" waited for "b", which waited for "a".
+        assert_eq!(deps(&steps), vec![Some(vec![]), Some(vec![1])]);
+    }
+
+    #[test]
+    fn test_delete_of_implicitly_ordered_step() {
+        let mut steps = vec![step("a", Some(vec![])), step("b", None), step("c", Some(vec![2]))];
+        delete_step(&mut steps, 1);
+        assert_eq!(deps(&steps), vec![Some(vec![]), Some(vec![1])]);
+    }
+
+    #[test]
+    fn test_delete_of_independent_step() {
+        let mut steps = vec![step("a", Some(vec![])), step("b", Some(vec![1]))];
+        delete_step(&mut steps, 0);
+        assert_eq!(deps(&steps), vec![Some(vec![])]);
+    }
+
+    #[test]
+    fn test_move_renumbers_references() {
+        let mut steps = vec![
+            step("a", Some(vec![])),
+            step("b", Some(vec![])),
+            step("c", Some(vec![1])),
+        ];
+        move_step(&mut steps, 0, 1).unwrap();
+        assert_eq!(commands(&steps), vec!["b", "a", "c"]);
+        assert_eq!(deps(&steps), vec![Some(vec![]), Some(vec![]), Some(vec![2])]);
+    }
+
+    #[test]
+    fn test_move_ahead_of_dependency_is_refused() {
+        let mut steps = vec![step("a", Some(vec![])), step("b", Some(vec![1]))];
+        let err = move_step(&mut steps, 1, 0).unwrap_err();
+        assert!(err.contains("Step 2 depends on step 1"));
+        assert_eq!(commands(&steps), vec!["a", "b"]);
+        assert_eq!(deps(&steps), vec![Some(vec![]), Some(vec![1])]);
+
+        // Moving the dependency past its dependent is the same violation.
+        assert!(move_step(&mut steps, 0, 1).is_err());
+    }
+
+    #[test]
+    fn test_existing_ignored_reference_does_not_block_moves() {
+        let mut steps = vec![step("a", Some(vec![3])), step("b", Some(vec![])), step("c", Some(vec![]))];
+        move_step(&mut steps, 1, 2).unwrap();
+        assert_eq!(commands(&steps), vec!["a", "c", "b"]);
+        assert_eq!(deps(&steps)[0], Some(vec![2]));
+    }
+}
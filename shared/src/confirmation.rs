@@ -3,6 +3,7 @@ use colored::Colorize;
 use crossterm::event::{read, Event, KeyCode};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use dialoguer::console::Term;
+use std::io::IsTerminal;
 
 /// Standardized confirmation prompt used across binaries.
 /// Returns immediately on single keypress: y/Y, n/N, or Enter for default.
@@ -32,3 +33,9 @@ pub fn ask_confirmation(prompt: &str, default_yes: bool) -> Result<bool> {
 
     Ok(result)
 }
+
+/// Whether `ask_confirmation` can work here: stdin is a terminal that can be
+/// switched to raw mode.
+pub fn raw_mode_available() -> bool {
+    std::io::stdin().is_terminal() && enable_raw_mode().and_then(|_| disable_raw_mode()).is_ok()
+}
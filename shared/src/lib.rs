@@ -3,3 +3,4 @@ pub mod telemetry;
 pub mod types;
 pub mod utils;
 pub mod confirmation;
+pub mod shell;
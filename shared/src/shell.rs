@@ -0,0 +1,357 @@
+//! Minimal POSIX shell lexer used to compare and sanity-check generated commands.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Word(String),
+    Operator(String),
+}
+
+const OPERATORS: [&str; 15] = [
+    "&&", "||", ";;", ">>", "<<", ">&", "<&", "&>", "|", "&", ";", "<", ">", "(", ")",
+];
+
+/// How a character of a word was written, which decides whether the shell
+/// expands it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    /// Unquoted: subject to expansion, splitting and globbing.
+    Bare,
+    /// Single-quoted or backslash-escaped: taken literally.
+    Literal,
+    /// Inside double quotes: only `$`, `` ` `` and `\` keep a meaning.
+    Double,
+}
+
+/// A word as written, character by character.
+type QuotedWord = Vec<(char, Quoting)>;
+
+enum Lexeme {
+    Word(QuotedWord),
+    Operator(String),
+}
+
+/// Split a command line into words and operators, resolving quotes and escapes.
+pub fn tokenize(command: &str) -> std::result::Result<Vec<Token>, String> {
+    Ok(lex(command)?
+        .into_iter()
+        .map(|lexeme| match lexeme {
+            Lexeme::Word(word) => Token::Word(word.into_iter().map(|(c, _)| c).collect()),
+            Lexeme::Operator(op) => Token::Operator(op),
+        })
+        .collect())
+}
+
+fn lex(command: &str) -> std::result::Result<Vec<Lexeme>, String> {
+    let chars: Vec<char> = command.chars().collect();
+    let mut tokens = Vec::new();
+    let mut word: QuotedWord = Vec::new();
+    let mut in_word = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' | '\n' => {
+                if in_word {
+                    tokens.push(Lexeme::Word(std::mem::take(&mut word)));
+                    in_word = false;
+                }
+                i += 1;
+            }
+            '#' if !in_word => {
+                // Comment until end of line.
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '\\' => {
+                if let Some(&next) = chars.get(i + 1) {
+                    if next != '\n' {
+                        word.push((next, Quoting::Literal));
+                        in_word = true;
+                    }
+                    i += 2;
+                } else {
+                    return Err("Trailing backslash".to_string());
+                }
+            }
+            '\'' => {
+                let Some(len) = chars[i + 1..].iter().position(|&ch| ch == '\'') else {
+                    return Err("Unclosed single quote".to_string());
+                };
+                word.extend(chars[i + 1..i + 1 + len].iter().map(|&ch| (ch, Quoting::Literal)));
+                in_word = true;
+                i += len + 2;
+            }
+            '"' => {
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err("Unclosed double quote".to_string()),
+                        Some('"') => break,
+                        Some('\\') if matches!(chars.get(i + 1), Some('"' | '\\' | '$' | '`')) => {
+                            word.push((chars[i + 1], Quoting::Literal));
+                            i += 2;
+                        }
+                        Some(&ch) => {
+                            word.push((ch, Quoting::Double));
+                            i += 1;
+                        }
+                    }
+                }
+                in_word = true;
+                i += 1;
+            }
+            _ => {
+                let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+                if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                    // `2>` and friends: a digit word glued to a redirection.
+                    let io_number = in_word
+                        && (op.starts_with('>') || op.starts_with('<'))
+                        && !word.is_empty()
+                        && word.iter().all(|(ch, q)| ch.is_ascii_digit() && *q == Quoting::Bare);
+                    if io_number {
+                        let digits: String = word.drain(..).map(|(ch, _)| ch).collect();
+                        tokens.push(Lexeme::Operator(format!("{}{}", digits, op)));
+                    } else {
+                        if in_word {
+                            tokens.push(Lexeme::Word(std::mem::take(&mut word)));
+                        }
+                        tokens.push(Lexeme::Operator(op.to_string()));
+                    }
+                    in_word = false;
+                    i += op.len();
+                } else {
+                    word.push((c, Quoting::Bare));
+                    in_word = true;
+                    i += 1;
+                }
+            }
+        }
+    }
+    if in_word {
+        tokens.push(Lexeme::Word(word));
+    }
+    Ok(tokens)
+}
+
+/// Canonical form of a command: tokens separated by single spaces, words
+/// re-quoted consistently. Quoting that changes what the shell does is kept,
+/// so `echo '$HOME'`, `echo "$HOME"` and `echo $HOME` stay distinct while
+/// `'a b'`, `"a b"` and `a\ b` agree. Returns `None` when the command does
+/// not lex.
+pub fn normalize(command: &str) -> Option<String> {
+    let tokens = lex(command).ok()?;
+    if tokens.is_empty() {
+        return None;
+    }
+    let parts: Vec<String> = tokens
+        .into_iter()
+        .map(|token| match token {
+            Lexeme::Operator(op) => op,
+            Lexeme::Word(word) => quote_word(&word),
+        })
+        .collect();
+    Some(parts.join(" "))
+}
+
+/// Characters that mean the same to the shell whether quoted or not.
+fn is_plain(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_-./=:,+@%^".contains(c)
+}
+
+/// Render `word` with the least quoting that keeps its meaning: literal text
+/// in single quotes when it needs them, expanding double-quoted text in
+/// double quotes, and unquoted special characters as they were.
+fn quote_word(word: &[(char, Quoting)]) -> String {
+    // Double-quoted text without `$` or backquotes expands nothing.
+    let expands = word
+        .iter()
+        .any(|(c, q)| *q == Quoting::Double && (*c == '$' || *c == '`'));
+    let mut runs: Vec<(Quoting, String)> = Vec::new();
+    for &(c, quoting) in word {
+        let quoting = match quoting {
+            Quoting::Double if !expands => Quoting::Literal,
+            Quoting::Bare if is_plain(c) => Quoting::Literal,
+            other => other,
+        };
+        match runs.last_mut() {
+            Some((last, text)) if *last == quoting => text.push(c),
+            _ => runs.push((quoting, c.to_string())),
+        }
+    }
+    if runs.is_empty() {
+        return "''".to_string();
+    }
+    runs.into_iter()
+        .map(|(quoting, text)| match quoting {
+            Quoting::Literal if text.chars().all(is_plain) => text,
+            Quoting::Literal => format!("'{}'", text.replace('\'', r"'\''")),
+            Quoting::Double => format!("\"{}\"", text.replace('\\', r"\\").replace('"', "\\\"")),
+            Quoting::Bare => text,
+        })
+        .collect()
+}
+
+/// Reject commands that are obviously malformed: unbalanced quotes or a
+/// trailing operator. When `bash` is available its `-n` (parse only) mode
+/// is the final word, which also covers grouping and `case` patterns.
+pub fn check_syntax(command: &str) -> std::result::Result<(), String> {
+    if command.trim().is_empty() {
+        return Err("Empty command".to_string());
+    }
+    // Here-document bodies are data, not shell words, so only bash can judge them.
+    if !command.contains("<<") {
+        let tokens = tokenize(command)?;
+        if tokens.is_empty() {
+            return Err("Empty command".to_string());
+        }
+        if let Some(Token::Operator(op)) = tokens.last() {
+            if matches!(op.as_str(), "|" | "||" | "&&" | "<" | ">" | ">>") {
+                return Err(format!("Command ends with incomplete expression '{}'", op));
+            }
+        }
+    }
+
+    let parsed = std::process::Command::new("bash")
+        .arg("-n")
+        .arg("-c")
+        .arg(command)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .output();
+    match parsed {
+        Ok(output) if !output.status.success() => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(stderr
+                .lines()
+                .next()
+                .unwrap_or("bash rejected the command")
+                .trim()
+                .to_string())
+        }
+        // Either valid, or bash isn't installed and the lexer checks have to do.
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_syntax, normalize, tokenize, Token};
+
+    fn words(command: &str) -> Vec<Token> {
+        tokenize(command).unwrap()
+    }
+
+    fn w(text: &str) -> Token {
+        Token::Word(text.to_string())
+    }
+
+    fn op(text: &str) -> Token {
+        Token::Operator(text.to_string())
+    }
+
+    #[test]
+    fn test_tokenize_quotes() {
+        assert_eq!(words("echo 'a b' \"c d\""), vec![w("echo"), w("a b"), w("c d")]);
+        assert_eq!(words("echo 'it'\\''s'"), vec![w("echo"), w("it's")]);
+        assert_eq!(words("echo \"say \\\"hi\\\"\""), vec![w("echo"), w("say \"hi\"")]);
+        assert_eq!(words("echo ''"), vec![w("echo"), w("")]);
+        assert_eq!(words("echo 'a|b;c'"), vec![w("echo"), w("a|b;c")]);
+    }
+
+    #[test]
+    fn test_tokenize_escapes() {
+        assert_eq!(words("touch a\\ b"), vec![w("touch"), w("a b")]);
+        assert_eq!(words("ls \\\nfoo"), vec![w("ls"), w("foo")]);
+        assert_eq!(words("echo \"\\n\""), vec![w("echo"), w("\\n")]);
+        assert!(tokenize("echo \\").is_err());
+    }
+
+    #[test]
+    fn test_tokenize_operators() {
+        assert_eq!(
+            words("a && b || c | d; e &"),
+            vec![
+                w("a"), op("&&"), w("b"), op("||"), w("c"), op("|"), w("d"), op(";"), w("e"),
+                op("&"),
+            ]
+        );
+        assert_eq!(words("cmd 2>/dev/null"), vec![w("cmd"), op("2>"), w("/dev/null")]);
+        assert_eq!(words("cmd >>log 2>&1"), vec![w("cmd"), op(">>"), w("log"), op("2>&"), w("1")]);
+        assert_eq!(words("(cd x)"), vec![op("("), w("cd"), w("x"), op(")")]);
+        assert_eq!(words("ls # list files"), vec![w("ls")]);
+        assert_eq!(words("echo a#b"), vec![w("echo"), w("a#b")]);
+    }
+
+    #[test]
+    fn test_tokenize_unclosed_quotes() {
+        assert!(tokenize("echo 'abc").is_err());
+        assert!(tokenize("echo \"abc").is_err());
+    }
+
+    #[test]
+    fn test_normalize_whitespace_and_quoting_style() {
+        let key = normalize("grep  -r 'foo bar'   .").unwrap();
+        assert_eq!(key, "grep -r 'foo bar' .");
+        assert_eq!(normalize("grep -r \"foo bar\" .").unwrap(), key);
+        assert_eq!(normalize("grep -r foo\\ bar .").unwrap(), key);
+        assert_eq!(normalize("ls   -la|wc -l").unwrap(), "ls -la | wc -l");
+        assert_eq!(normalize("echo 'ls'").unwrap(), "echo ls");
+    }
+
+    #[test]
+    fn test_normalize_keeps_expansion_apart() {
+        let single = normalize("echo '$HOME'").unwrap();
+        let double = normalize("echo \"$HOME\"").unwrap();
+        let bare = normalize("echo $HOME").unwrap();
+        assert_eq!(single, "echo '$HOME'");
+        assert_eq!(double, "echo \"$HOME\"");
+        assert_eq!(bare, "echo $HOME");
+        assert_eq!(normalize("echo \\$HOME").unwrap(), single);
+        assert_ne!(normalize("ls '*.rs'"), normalize("ls *.rs"));
+        assert_ne!(normalize("echo '`id`'"), normalize("echo \"`id`\""));
+    }
+
+    #[test]
+    fn test_normalize_rejects_bad_input() {
+        assert_eq!(normalize(""), None);
+        assert_eq!(normalize("   # only a comment"), None);
+        assert_eq!(normalize("echo 'oops"), None);
+    }
+
+    #[test]
+    fn test_check_syntax_accepts_valid_commands() {
+        assert!(check_syntax("ls -la").is_ok());
+        assert!(check_syntax("echo 'hello world'").is_ok());
+        assert!(check_syntax("du -h --max-depth=1 | sort -hr").is_ok());
+        assert!(check_syntax("find . -name '*.rs' -exec grep 'fn' {} \\;").is_ok());
+        assert!(check_syntax("(cd /tmp && ls)").is_ok());
+        // `[` is an ordinary word to the shell.
+        assert!(check_syntax("ls [ -f file").is_ok());
+    }
+
+    #[test]
+    fn test_check_syntax_accepts_case_statements() {
+        assert!(check_syntax("case x in a) echo;; esac").is_ok());
+        assert!(check_syntax("case \"$1\" in start|stop) echo \"$1\" ;; *) exit 1 ;; esac").is_ok());
+    }
+
+    #[test]
+    fn test_check_syntax_accepts_heredocs() {
+        assert!(check_syntax("cat <<EOF\nit's (not) shell\nEOF").is_ok());
+        assert!(check_syntax("cat <<'EOF' > notes.txt\n\"unbalanced\nEOF").is_ok());
+    }
+
+    #[test]
+    fn test_check_syntax_rejects_malformed_commands() {
+        assert!(check_syntax("").is_err());
+        assert!(check_syntax("echo 'hello").is_err());
+        assert!(check_syntax("du -h | awk '$1 >").is_err());
+        assert!(check_syntax("ls &&").is_err());
+        assert!(check_syntax("ls |").is_err());
+        assert!(check_syntax("echo (hello").is_err());
+        assert!(check_syntax("if true; then echo").is_err());
+    }
+}
@@ -5,7 +5,6 @@ mod safety;
 mod runner;
 mod prompt;
 mod agent;
-mod scriptgen;
 mod clipboard;
 
 use clap::{ArgAction, Parser};
@@ -35,14 +34,6 @@ struct Cli {
     #[arg(long, action = ArgAction::SetTrue)]
     rag: bool,
 
-    /// Generate a bash script instead of running commands
-    #[arg(long, action = ArgAction::SetTrue)]
-    script: bool,
-
-    /// Output file for --script mode
-    #[arg(short = 'o', long)]
-    output: Option<String>,
-
     /// Relax safety checks (still asks for confirmation)
     #[arg(long, action = ArgAction::SetTrue)]
     unsafe_mode: bool,
@@ -70,8 +61,8 @@ async fn main() -> Result<()> {
 
     let prompt_text = if !cli.prompt.is_empty() {
         cli.prompt.join(" ")
-    } else if !cli.chat && !cli.agent && !cli.script {
-        // Only ask interactively when not in chat/agent/script explicit modes
+    } else if !cli.chat && !cli.agent {
+        // Only ask interactively when not in chat/agent explicit modes
         prompt::ask_user_prompt()?
     } else {
         String::new()
@@ -82,7 +73,7 @@ async fn main() -> Result<()> {
     if cli.retrain {
         config.clear_cache()?;
         println!("Cache cleared. Starting fresh.");
-        if cli.prompt.is_empty() && !cli.chat && !cli.agent && !cli.script {
+        if cli.prompt.is_empty() && !cli.chat && !cli.agent {
             return Ok(());
         }
     }
@@ -102,11 +93,6 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
-    if cli.script {
-        scriptgen::run_script_mode(&config, &prompt_text, cli.output.as_deref()).await?;
-        return Ok(());
-    }
-
     // Default: one-shot prompt -> single command
     run_one_shot(&config, &prompt_text).await?;
 
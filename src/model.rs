@@ -428,38 +428,3 @@ Generate the plan based on the user's request.
     // If we couldn't parse a JSON array, return empty so caller can report failure instead of spitting prose.
     Ok(Vec::new())
 }
-
-/// Request a bash script (one string output)
-pub async fn request_script(config: &Config, user_prompt: &str) -> Result<String> {
-    let client = reqwest::Client::new();
-
-    let system = r#"Generate a POSIX-compatible bash script only.
-Return only the script text, no markdown."#;
-
-    let msgs = vec![
-        Message {
-            role: "system".into(),
-            content: system.into(),
-        },
-        Message {
-            role: "user".into(),
-            content: user_prompt.into(),
-        },
-    ];
-
-    let req = ChatRequest {
-        model: &config.model,
-        messages: &msgs,
-        stream: false,
-    };
-
-    let raw = client
-        .post(&config.endpoint)
-        .json(&req)
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    Ok(raw.trim().into())
-}